@@ -22,7 +22,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
              Path: {}\n\
              Version: {}\n\
              Opcodes: {}",
-            prog.inner().name().unwrap_or("N/A".to_string()),
+            prog.port_name().unwrap_or("N/A"),
             bootloader.version_string(),
             bootloader
                 .opcodes()
@@ -0,0 +1,162 @@
+use crate::device::{self, Family};
+use crate::{Error, Programmer, Result};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Address space of a device
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum AddressSpace {
+    /// Main flash memory, where applications live.
+    Flash,
+    /// SRAM, where code can be loaded and run.
+    Ram,
+    /// System memory holding the bootloader itself, which is read-only.
+    SystemMemory,
+    /// Option bytes.
+    OptionBytes,
+}
+
+impl AddressSpace {
+    /// Gets the absolute base address of the space on a device family.
+    ///
+    /// Returns [`None`] if the base is unknown, e.g. system memory, whose
+    /// location varies between parts.
+    pub fn base(&self, family: Option<Family>) -> Option<u32> {
+        match self {
+            Self::Flash => Some(0x0800_0000),
+            Self::Ram => Some(0x2000_0000),
+            Self::SystemMemory => None,
+            Self::OptionBytes => family?
+                .register("option_bytes")
+                .map(|register| register.address()),
+        }
+    }
+}
+
+/// Address relative to the start of an [`AddressSpace`]
+///
+/// Unlike bare addresses, the space is explicit, so data meant for one space
+/// cannot end up in another, e.g. an application in option bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TargetAddress {
+    space: AddressSpace,
+    offset: u32,
+}
+
+impl TargetAddress {
+    /// Creates a [`TargetAddress`].
+    pub fn new(space: AddressSpace, offset: u32) -> Self {
+        Self { space, offset }
+    }
+
+    /// Creates a [`TargetAddress`] in flash memory.
+    pub fn flash(offset: u32) -> Self {
+        Self::new(AddressSpace::Flash, offset)
+    }
+
+    /// Creates a [`TargetAddress`] in RAM.
+    pub fn ram(offset: u32) -> Self {
+        Self::new(AddressSpace::Ram, offset)
+    }
+
+    /// Creates a [`TargetAddress`] in option bytes.
+    pub fn option_bytes(offset: u32) -> Self {
+        Self::new(AddressSpace::OptionBytes, offset)
+    }
+
+    /// Gets address space.
+    pub fn space(&self) -> AddressSpace {
+        self.space
+    }
+
+    /// Gets offset from the start of the address space.
+    pub fn offset(&self) -> u32 {
+        self.offset
+    }
+
+    /// Resolves to an absolute address on a device family.
+    pub fn resolve(&self, family: Option<Family>) -> Result<u32> {
+        self.space
+            .base(family)
+            .and_then(|base| base.checked_add(self.offset))
+            .ok_or(Error::InvalidTarget(*self))
+    }
+}
+
+impl Programmer {
+    /// Reads the size of flash memory in bytes from the `flash_size` register
+    /// of a family, or returns [`None`] if the register is unknown for it.
+    pub(crate) fn read_flash_size(&mut self, family: Option<Family>) -> Result<Option<u64>> {
+        let Some(register) = family.and_then(|family| family.register("flash_size")) else {
+            return Ok(None);
+        };
+        let mut kib = [0u8; 2];
+        self.read_chunk(register.address(), &mut kib)?;
+        Ok(Some(u16::from_le_bytes(kib) as u64 * 1024))
+    }
+
+    /// Writes data to a target address, after checking that it fits within
+    /// its address space.
+    ///
+    /// The device is identified by GET_ID first. Writing to system memory is
+    /// always rejected, and so is writing to spaces unknown for the device.
+    /// Flash memory is checked against the flash size register where known,
+    /// and RAM against the RAM of the device, see [`device::lookup()`].
+    pub fn write_target(&mut self, target: TargetAddress, data: &[u8]) -> Result<()> {
+        let id = self.read_id()?;
+        let family = id.family();
+        let address = target.resolve(family)?;
+        let size = match target.space() {
+            AddressSpace::Flash => self.read_flash_size(family)?,
+            AddressSpace::Ram => {
+                let ram = Self::ram_range(device::lookup(id.as_u16()));
+                Some(ram.end - ram.start)
+            }
+            AddressSpace::SystemMemory => return Err(Error::InvalidTarget(target)),
+            AddressSpace::OptionBytes => family
+                .and_then(|family| family.register("option_bytes"))
+                .map(|register| register.size() as u64),
+        };
+        if size.is_some_and(|size| target.offset() as u64 + data.len() as u64 > size) {
+            return Err(Error::OutOfRange {
+                address,
+                size: data.len(),
+            });
+        }
+        self.write_memory_chunked(address, data)
+    }
+}
+
+#[test]
+fn writing_targets() -> Result<()> {
+    use crate::Probe;
+    use crate::mock::{Faults, MockPort};
+
+    let port = MockPort::device(Faults::default());
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &Probe::new());
+    programmer.write_target(TargetAddress::flash(0xfe00), &[0xa5; 0x200])?;
+    assert!(port.flash()[0xfe00..].iter().all(|&b| b == 0xa5));
+
+    // Past the 64 KiB in the flash size register, and the 20 KiB of RAM of
+    // F1 medium-density
+    let written = port.written().len();
+    for target in [TargetAddress::flash(0xff00), TargetAddress::ram(0x4f00)] {
+        let result = programmer.write_target(target, &[0xa5; 0x200]);
+        assert!(result.is_err_and(|e| e.is_out_of_range()));
+    }
+    assert!(
+        !port.written()[written..]
+            .windows(2)
+            .any(|w| w == [0x31, 0xce])
+    );
+
+    // RAM is not emulated, so WRITE is refused once sent.
+    let result = programmer.write_target(TargetAddress::ram(0x4e00), &[0xa5; 0x200]);
+    assert!(result.is_err_and(|e| e.is_nack()));
+    let result = programmer.write_target(TargetAddress::new(AddressSpace::SystemMemory, 0), &[0]);
+    assert!(matches!(result, Err(Error::InvalidTarget(..))));
+    Ok(())
+}
@@ -0,0 +1,79 @@
+use crate::{Command, Programmer, Result, Size};
+use serialport::ClearBuffer;
+use std::io::Read;
+use std::time::Duration;
+
+impl Programmer {
+    /// Interval between polls of the port for incoming data.
+    const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+    /// Reads memory at specific region asynchronously.
+    ///
+    /// The region is read by successive READ commands of up to 256 bytes. While
+    /// commands are sent as usual, payloads are awaited with
+    /// [`tokio::time::timeout`] with [`Probe::data_timeout()`](crate::Probe::data_timeout)
+    /// instead of the blocking timeout of the port,
+    /// so other tasks can make progress while a slow device is transferring
+    /// data. It requires a Tokio runtime with time enabled.
+    ///
+    /// Sending each command and awaiting its ACKs still blocks, like
+    /// [`Self::send_command()`], so the runtime is held up for a round trip
+    /// per chunk. If a payload times out, the input of the port is cleared,
+    /// so the rest of it isn't taken as the reply to the next command.
+    pub async fn read_memory_async(&mut self, address: u32, size: usize) -> Result<Vec<u8>> {
+        Self::check_range(address, size)?;
+        let mut data = vec![0u8; size];
+        let mut offset = 0;
+        for chunk in data.chunks_mut(*Size::RANGE.end()) {
+            self.send_command(Command::Read {
+                address: (address + offset as u32).into(),
+                size: Size::try_from(chunk.len())?,
+            })?;
+            let timeout = self.probe.data_timeout();
+            match tokio::time::timeout(timeout, self.poll_exact(chunk)).await {
+                Ok(result) => result?,
+                Err(_) => {
+                    self.port.clear(ClearBuffer::Input)?;
+                    return Err(std::io::Error::from(std::io::ErrorKind::TimedOut).into());
+                }
+            }
+            offset += chunk.len();
+        }
+        Ok(data)
+    }
+
+    /// Fills the buffer with bytes available on the port, yielding to the
+    /// runtime while no data is available.
+    async fn poll_exact(&mut self, buf: &mut [u8]) -> Result<()> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            let available = self.port.bytes_to_read()? as usize;
+            if available == 0 {
+                tokio::time::sleep(Self::POLL_INTERVAL).await;
+                continue;
+            }
+            let end = buf.len().min(filled + available);
+            filled += self.port.read(&mut buf[filled..end])?;
+        }
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn timing_out_async() -> Result<()> {
+    use crate::Probe;
+    use crate::mock::MockPort;
+
+    // READ is acknowledged thrice, but only half of the payload arrives.
+    let port = MockPort::new(&[0x79, 0x79, 0x79, 0xaa, 0xbb]);
+    let mut builder = Probe::builder();
+    builder.data_timeout(Duration::from_millis(20));
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &builder.build());
+    let result = programmer.read_memory_async(0x0800_0000, 4).await;
+    assert!(result.is_err_and(|e| {
+        e.as_io_error()
+            .is_some_and(|e| e.kind() == std::io::ErrorKind::TimedOut)
+    }));
+    assert_eq!(port.clears(), [ClearBuffer::Input]);
+    Ok(())
+}
@@ -5,12 +5,16 @@ use anyhow::anyhow;
 #[allow(unused_imports)]
 use log::{debug, error, info, trace, warn};
 
-use yapu::{Baudrate, Identify, Probe, Programmer, Signal, SignalScheme};
+use yapu::{Baudrate, Discovered, Identify, Probe, Programmer, Signal, SignalScheme};
 
 pub use clap::{Args, Parser, Subcommand, ValueEnum};
 pub use shell::Shell;
 use std::fmt::Display;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::time::Duration;
 
 use serde::Serialize;
 use tabled::settings::{Width, peaker::Priority};
@@ -24,6 +28,20 @@ pub struct Cli {
 
     #[clap(long, default_value = "text")]
     format: Format,
+
+    /// Write the output to a file instead of stdout
+    ///
+    /// Progress and status messages are still printed to stderr.
+    #[clap(short, long, global = true)]
+    output: Option<PathBuf>,
+
+    /// Load the probe from a configuration file
+    ///
+    /// The format is detected from the extension: ".toml", ".json", or
+    /// ".yaml" and ".yml". Omitted fields take their defaults, while probe
+    /// options given on the command line take precedence.
+    #[clap(long, global = true)]
+    config: Option<PathBuf>,
 }
 
 #[derive(Args, Debug, Clone)]
@@ -35,46 +53,145 @@ pub struct DeviceOptions {
     device: Option<String>,
 }
 
+/// Options of the probe, overriding those of the configuration file if any
+///
+/// Options are only applied when given, so that the precedence is defaults,
+/// then the configuration file, then the command line.
 #[derive(Args, Debug, Clone)]
 pub struct ProbeOptions {
-    /// Specify the baudrate for probing and programming
-    #[clap(short, long, default_value_t = 115_200)]
-    baudrate: Baudrate,
+    /// Specify the baudrate for probing and programming [default: 115200]
+    #[clap(short, long)]
+    baudrate: Option<Baudrate>,
 
-    /// Specify reset MODEM signal
+    /// Specify reset MODEM signal [default: rts]
     ///
     /// A signal could be "none", "rts", "dtr", "!rts", "!dtr".
     ///
     /// However, some operating systems automatically assert specific signals on
     /// open, which cannot be changed from userspace.
-    #[clap(long, default_value_t = SignalScheme::new().reset().unwrap().into())]
-    reset: DeviceSignal,
+    #[clap(long)]
+    reset: Option<DeviceSignal>,
+
+    /// Specify boot MODEM signal [default: !dtr]
+    #[clap(long)]
+    boot: Option<DeviceSignal>,
 
-    /// Specify boot MODEM signal
-    #[clap(long, default_value_t = SignalScheme::new().boot().unwrap().into())]
-    boot: DeviceSignal,
+    /// Use a preset of reset and boot signals instead
+    #[clap(long, conflicts_with_all = ["reset", "boot"])]
+    scheme: Option<DeviceScheme>,
 
-    /// Identify a device by
+    /// Identify a device by [default: handshake]
     #[clap(short, long)]
-    identify: DeviceIdentify,
+    identify: Option<DeviceIdentify>,
+
+    /// Specify the delay before reconnecting to a reset device (in
+    /// milliseconds) [default: 500]
+    #[clap(long)]
+    reconnect_delay_ms: Option<u64>,
 }
 
 impl ProbeOptions {
-    pub fn build_probe(&self) -> Probe {
-        let mut scheme = SignalScheme::new();
-        scheme.set_reset(self.reset.0);
-        scheme.set_boot(self.boot.0);
-        let mut builder = Probe::builder();
-        builder
-            .baudrate(self.baudrate)
-            .signal_scheme(scheme)
-            .identify(self.identify.into());
-        builder.build()
+    /// Builds the probe from the configuration file if any, or the defaults
+    /// otherwise, then applies the options given.
+    pub fn build_probe(&self, config: Option<&Path>) -> anyhow::Result<Probe> {
+        let mut probe = match config {
+            Some(path) => load_probe(path)?,
+            None => Probe::new(),
+        };
+        self.apply(&mut probe);
+        Ok(probe)
     }
+
+    /// Overrides the probe with the options given.
+    fn apply(&self, probe: &mut Probe) {
+        if let Some(baudrate) = self.baudrate {
+            probe.set_baudrate(baudrate);
+        }
+        if let Some(scheme) = self.scheme {
+            probe.set_signal_scheme(scheme.into());
+        }
+        if let Some(reset) = self.reset {
+            probe.set_signal_reset(reset.0);
+        }
+        if let Some(boot) = self.boot {
+            probe.set_signal_boot(boot.0);
+        }
+        if let Some(identify) = self.identify {
+            probe.set_identify(identify.into());
+        }
+        if let Some(delay) = self.reconnect_delay_ms {
+            probe.set_reconnect_delay(Duration::from_millis(delay));
+        }
+    }
+}
+
+fn load_probe(path: &Path) -> anyhow::Result<Probe> {
+    let content = std::fs::read_to_string(path)?;
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("toml") => Ok(toml::from_str(&content)?),
+        Some("json") => Ok(serde_json::from_str(&content)?),
+        Some("yaml" | "yml") => Ok(Probe::from_yaml(&content)?),
+        _ => Err(anyhow!(
+            "unknown config format of {}, try \".toml\", \".json\" or \".yaml\"",
+            path.display()
+        )),
+    }
+}
+
+#[test]
+fn overriding_config() -> anyhow::Result<()> {
+    #[derive(Parser)]
+    struct Options {
+        #[clap(flatten)]
+        probe: ProbeOptions,
+    }
+    let parse = |args: &[&str]| Options::try_parse_from([&["yapu"], args].concat());
+
+    let mut config = Probe::from_yaml(
+        "baudrate: 57600\n\
+         signal_scheme:\n  reset: '!dtr'\n  boot: rts\n\
+         reconnect_delay: 100\n",
+    )?;
+    // Options not given leave the configuration as is.
+    parse(&["--identify", "get"])?.probe.apply(&mut config);
+    assert_eq!(config.baudrate(), 57_600);
+    assert_eq!(config.signal_reset(), Some(Signal::dtr(false)));
+    assert_eq!(config.signal_boot(), Some(Signal::rts(true)));
+    assert_eq!(config.reconnect_delay(), Duration::from_millis(100));
+    assert_eq!(config.identify(), Identify::Get);
+
+    let options = parse(&["--baudrate", "9600", "--boot", "none"])?.probe;
+    options.apply(&mut config);
+    assert_eq!(config.baudrate(), 9_600);
+    assert_eq!(config.signal_reset(), Some(Signal::dtr(false)));
+    assert_eq!(config.signal_boot(), None);
+    assert_eq!(config.reconnect_delay(), Duration::from_millis(100));
+
+    // Without configuration, options apply over the defaults.
+    let probe = parse(&["-i", "handshake", "--reconnect-delay-ms", "20"])?
+        .probe
+        .build_probe(None)?;
+    assert_eq!(probe.baudrate(), Probe::new().baudrate());
+    assert_eq!(probe.signal_reset(), Probe::new().signal_reset());
+    assert_eq!(probe.reconnect_delay(), Duration::from_millis(20));
+    Ok(())
+}
+
+#[test]
+fn parsing_global_options() -> anyhow::Result<()> {
+    use clap::CommandFactory;
+
+    Cli::command().debug_assert();
+    // Global options are taken after the subcommand as well.
+    let cli = Cli::try_parse_from(["yapu", "discover", "-o", "/tmp/x.json"])?;
+    assert_eq!(cli.output, Some(PathBuf::from("/tmp/x.json")));
+    let cli = Cli::try_parse_from(["yapu", "--output", "/tmp/x.json", "discover"])?;
+    assert_eq!(cli.output, Some(PathBuf::from("/tmp/x.json")));
+    Ok(())
 }
 
 #[derive(ValueEnum, Default, Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
-enum Format {
+pub enum Format {
     /// Normal output
     #[default]
     Text,
@@ -97,7 +214,6 @@ impl Format {
         matches!(self, Self::Table)
     }
 
-    #[allow(dead_code)]
     #[inline]
     pub fn is_json(&self) -> bool {
         matches!(self, Self::Json)
@@ -110,12 +226,39 @@ pub enum Command {
     Discover(DiscoverOptions),
     /// Enter interactive shell
     Shell(ShellOptions),
+    /// Print completion script for a shell
+    Completions(CompletionsOptions),
 }
 
 #[derive(Args, Debug, Clone)]
 pub struct DiscoverOptions {
     #[clap(flatten)]
     probe: ProbeOptions,
+
+    /// Probe only the specified port, which can be repeated
+    ///
+    /// Probe all available ports if omitted
+    #[clap(short, long = "port", value_name = "PORT")]
+    ports: Vec<String>,
+
+    /// Probe only USB ports of the specified vendor and product ID in hex,
+    /// e.g. "0403:6001", which can be repeated
+    #[clap(long = "usb-id", value_name = "VID:PID", value_parser = parse_usb_id)]
+    usb_ids: Vec<(u16, u16)>,
+}
+
+/// Parses USB vendor and product ID in hex separated by a colon.
+fn parse_usb_id(s: &str) -> anyhow::Result<(u16, u16)> {
+    let (vid, pid) = s
+        .split_once(':')
+        .ok_or_else(|| anyhow!("expected VID:PID, got {}", s))?;
+    Ok((u16::from_str_radix(vid, 16)?, u16::from_str_radix(pid, 16)?))
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct CompletionsOptions {
+    /// Shell to complete for
+    shell: clap_complete::Shell,
 }
 
 #[derive(Args, Debug, Clone)]
@@ -123,6 +266,13 @@ pub struct ShellOptions {
     /// Turn off prompt and welcome messages
     #[clap(long)]
     no_prompt: bool,
+
+    /// Close the active device after being idle (in seconds)
+    ///
+    /// The port is released for other tools, and reopened on the next command
+    /// requiring the device.
+    #[clap(long)]
+    idle_close: Option<u64>,
 }
 
 impl Cli {
@@ -130,10 +280,14 @@ impl Cli {
     where
         I::Item: Tabled + Display,
     {
+        let mut writer: Box<dyn Write> = match &self.output {
+            Some(path) => Box::new(File::create(path)?),
+            None => Box::new(std::io::stdout()),
+        };
         match self.format {
             Format::Text => {
                 for o in output.into_iter() {
-                    println!("{}", o);
+                    writeln!(writer, "{}", o)?;
                 }
             }
             Format::Table => {
@@ -143,32 +297,58 @@ impl Cli {
                         .keep_words(true)
                         .priority(Priority::max(true)),
                 );
-                println!("{}", table);
+                writeln!(writer, "{}", table)?;
             }
             Format::Json => {
-                serde_json::to_writer(std::io::stdout(), &output)?;
+                serde_json::to_writer(&mut writer, &output)?;
             }
         }
+        writer.flush()?;
         Ok(())
     }
 
     fn discover(&self, options: &DiscoverOptions) -> anyhow::Result<()> {
-        let probe = options.probe.build_probe();
+        let mut probe = options.probe.build_probe(self.config.as_deref())?;
+        // Discovery only inventories devices, which must be left as they are.
+        probe.set_safe_mode(true);
         if self.format.is_text() {
             eprintln!("Please wait for probing...");
         }
 
-        let devices = Programmer::discover(&probe)?
+        let mut discover = yapu::DiscoverOptions::new();
+        discover.set_ports(options.ports.clone());
+        discover.set_usb_ids(options.usb_ids.clone());
+        let outcomes = Programmer::discover_with(&probe, &discover)?;
+        let devices = outcomes
             .into_iter()
+            .filter_map(|(name, discovered)| match discovered {
+                Discovered::Device(p) => Some(p),
+                Discovered::Unusable(e) => {
+                    warn!("skipping {}: port opens but is unusable: {}", name, e);
+                    None
+                }
+                Discovered::Unresponsive(e) => {
+                    debug!("skipping {}: no device responds: {}", name, e);
+                    None
+                }
+                Discovered::Failed(e) if e.is_boot_reversed() => {
+                    warn!("skipping {}: {}", name, e);
+                    None
+                }
+                Discovered::Failed(e) => {
+                    debug!("skipping {}: {}", name, e);
+                    None
+                }
+            })
             .filter_map(|mut p| {
                 let result = p.read_bootloader();
-                let name = p.inner().name();
+                let name = p.port_name().map(str::to_owned);
                 match result {
                     Ok(b) => Some(output::Device::from_bootloader(name, &b)),
                     Err(e) => {
                         warn!(
                             "cannot read bootloader info from {}: {}",
-                            name.as_ref().map(|s| s.as_ref()).unwrap_or("N/A"),
+                            name.as_deref().unwrap_or("N/A"),
                             e,
                         );
                         None
@@ -181,14 +361,26 @@ impl Cli {
     }
 
     fn shell(&self, options: &ShellOptions) -> anyhow::Result<()> {
-        let mut shell = Shell::new(options.clone());
+        let probe = match &self.config {
+            Some(path) => load_probe(path)?,
+            None => Probe::new(),
+        };
+        let mut shell = Shell::new(options.clone(), self.format, probe);
         shell.run()
     }
 
+    fn completions(&self, options: &CompletionsOptions) -> anyhow::Result<()> {
+        let mut command = <Self as clap::CommandFactory>::command();
+        let name = command.get_name().to_owned();
+        clap_complete::generate(options.shell, &mut command, name, &mut std::io::stdout());
+        Ok(())
+    }
+
     pub fn execute(&self) -> anyhow::Result<()> {
         match &self.command {
             Command::Discover(options) => self.discover(options),
             Command::Shell(options) => self.shell(options),
+            Command::Completions(options) => self.completions(options),
         }
     }
 }
@@ -196,11 +388,6 @@ impl Cli {
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct DeviceSignal(Option<Signal>);
 
-impl DeviceSignal {
-    const HIGH: bool = true;
-    const LOW: bool = false;
-}
-
 impl From<Signal> for DeviceSignal {
     fn from(value: Signal) -> Self {
         Self(Some(value))
@@ -211,23 +398,9 @@ impl FromStr for DeviceSignal {
     type Err = anyhow::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        // Active high by default.
-        let mut active_when = Self::HIGH;
-
-        let name = if s.starts_with('!') {
-            // The prefix "!" indicates "active low".
-            active_when = Self::LOW;
-
-            s.trim_start_matches('!')
-        } else {
-            s
-        };
-
-        match name {
-            "rts" => Ok(Self(Some(Signal::rts(active_when)))),
-            "dtr" => Ok(Self(Some(Signal::dtr(active_when)))),
-            _ if s == "none" => Ok(Self(None)),
-            _ => Err(anyhow!("incorrect signal format: {}", s)),
+        match s {
+            "none" => Ok(Self(None)),
+            s => Ok(Self(Some(s.parse()?))),
         }
     }
 }
@@ -235,16 +408,8 @@ impl FromStr for DeviceSignal {
 impl std::fmt::Display for DeviceSignal {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self.0 {
-            Some(signal) => {
-                if signal.active_when() == Self::LOW {
-                    write!(f, "!")?;
-                }
-                match signal {
-                    Signal::Rts { .. } => write!(f, "{}", "rts"),
-                    Signal::Dtr { .. } => write!(f, "{}", "dtr"),
-                }
-            }
-            None => write!(f, "{}", "none"),
+            Some(signal) => write!(f, "{}", signal),
+            None => write!(f, "none"),
         }
     }
 }
@@ -264,6 +429,28 @@ fn parsing_signals() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceScheme {
+    /// RTS drives reset and DTR drives boot (reset: "rts", boot: "!dtr")
+    RtsDtr,
+
+    /// DTR drives reset and RTS drives boot (reset: "dtr", boot: "!rts")
+    DtrRts,
+
+    /// No signals, for the ST-LINK virtual COM port of Nucleo boards
+    Nucleo,
+}
+
+impl From<DeviceScheme> for SignalScheme {
+    fn from(value: DeviceScheme) -> Self {
+        match value {
+            DeviceScheme::RtsDtr => SignalScheme::rts_dtr_auto(),
+            DeviceScheme::DtrRts => SignalScheme::dtr_rts_auto(),
+            DeviceScheme::Nucleo => SignalScheme::stm32_nucleo(),
+        }
+    }
+}
+
 #[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DeviceIdentify {
     /// Baudrate handshaking (0x7f magic)
@@ -273,11 +460,11 @@ pub enum DeviceIdentify {
     Get,
 }
 
-impl Into<Identify> for DeviceIdentify {
-    fn into(self) -> Identify {
-        match self {
-            Self::Handshake => Identify::Handshake,
-            Self::Get => Identify::Get,
+impl From<DeviceIdentify> for Identify {
+    fn from(value: DeviceIdentify) -> Self {
+        match value {
+            DeviceIdentify::Handshake => Identify::Handshake,
+            DeviceIdentify::Get => Identify::Get,
         }
     }
 }
@@ -7,7 +7,7 @@ use yapu::{Bootloader, Opcode};
 #[derive(Serialize, Debug)]
 struct Opcodes(Vec<Opcode>);
 
-impl<'a> Display for Opcodes {
+impl Display for Opcodes {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
         write!(
             f,
@@ -32,7 +32,7 @@ pub struct Device {
 impl Device {
     pub fn from_bootloader(name: Option<String>, bootloader: &Bootloader) -> Self {
         Self {
-            name: name.map(|s| s.into()),
+            name,
             version: bootloader.version_string(),
             opcodes: Opcodes(bootloader.opcodes().to_vec()),
         }
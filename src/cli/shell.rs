@@ -1,24 +1,72 @@
 use anyhow::anyhow;
+use base64::Engine;
+use log::warn;
 use std::borrow::Cow;
+use std::fs::File;
+use std::io::{BufReader, Write};
+use std::path::{Path, PathBuf};
 
-use yapu::{Baudrate, Probe, Programmer};
+use yapu::image::Image;
+use yapu::{AddressSpace, Baudrate, FlashOptions, FlashProgress, Probe, Programmer};
 
 use rustyline::DefaultEditor;
 use rustyline::error::ReadlineError;
-use std::time::Duration;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
 
 #[allow(unused_imports)]
 use clap::{Args, Parser, Subcommand};
 
-use super::{DeviceIdentify, DeviceSignal, ShellOptions};
+use super::{DeviceIdentify, DeviceScheme, DeviceSignal, Format, ShellOptions};
 
 #[derive(Debug)]
 pub struct Shell {
-    current: Option<Programmer>,
+    // Shared with the background thread.
+    session: Arc<Mutex<Session>>,
     options: ShellOptions,
+    format: Format,
     probe: Probe,
 }
 
+/// Devices of the shell, which the background thread keeps alive or closes
+/// while idle.
+#[derive(Debug)]
+struct Session {
+    devices: Vec<Device>,
+    current: Option<usize>,
+    last_command: Instant,
+    idle_close: Option<Duration>,
+}
+
+impl Session {
+    fn current_mut(&mut self) -> Option<&mut Device> {
+        self.current.and_then(|i| self.devices.get_mut(i))
+    }
+
+    fn find(&self, device: &str) -> Option<usize> {
+        match device.parse::<usize>() {
+            Ok(index) if index < self.devices.len() => Some(index),
+            _ => self.devices.iter().position(|d| d.name == device),
+        }
+    }
+}
+
+/// A device known to the shell, whose port may be closed while idle.
+#[derive(Debug)]
+struct Device {
+    name: String,
+    programmer: Option<Programmer>,
+}
+
+impl From<Programmer> for Device {
+    fn from(programmer: Programmer) -> Self {
+        Self {
+            name: programmer.port_name().unwrap_or_default().to_owned(),
+            programmer: Some(programmer),
+        }
+    }
+}
+
 #[derive(Parser, Debug, Clone)]
 #[clap(
     no_binary_name = true,
@@ -47,6 +95,10 @@ enum Command {
         #[clap(long)]
         boot: Option<DeviceSignal>,
 
+        /// Change probe reset and boot signals to a preset
+        #[clap(long, conflicts_with_all = ["reset", "boot"])]
+        scheme: Option<DeviceScheme>,
+
         /// Change probe reset active duration (in milliseconds)
         #[clap(long)]
         reset_for: Option<u64>,
@@ -54,70 +106,390 @@ enum Command {
         /// Change probe identification scheme
         #[clap(long)]
         identify: Option<DeviceIdentify>,
+
+        /// Change keep-alive interval (in milliseconds, 0 to disable)
+        ///
+        /// Idle devices are pinged with GET so that the bootloader doesn't
+        /// time out, at the cost of extra traffic.
+        #[clap(long)]
+        keepalive: Option<u64>,
     },
     /// List all ports available (without any probe)
     Ports,
     /// Discover devices available
     Discover,
+    /// Discover devices and list them with indices
+    Devices,
+    /// Make a listed device active
+    Select {
+        /// Device index or name
+        device: String,
+    },
     /// Get bootloader info of current active device
     Get,
     /// Get bootloader version of current active device
     Version,
     /// Get ID of current active device
     Id,
+    /// Read memory of current active device and print a hexdump
+    Read {
+        /// Start address, e.g. "0x08000000"
+        #[clap(value_parser = parse_u32)]
+        address: u32,
+
+        /// Size in bytes
+        #[clap(value_parser = parse_u32)]
+        size: u32,
+
+        /// Write raw bytes to a file instead
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Flash an image file to current active device
+    ///
+    /// The format is detected from the extension: ".hex" for Intel HEX,
+    /// ".srec", ".s19", ".s28" or ".s37" for Motorola S-record, and ".bin" for
+    /// raw binary.
+    Flash {
+        /// Image file
+        file: PathBuf,
+
+        /// Address to flash a raw binary at, e.g. "0x08000000"
+        #[clap(short, long, value_parser = parse_u32)]
+        address: Option<u32>,
+
+        /// Erase the whole flash memory first
+        #[clap(short, long)]
+        erase: bool,
+
+        /// Verify each segment once written
+        #[clap(short, long)]
+        verify: bool,
+    },
+    /// Erase flash memory of current active device
+    Erase {
+        /// Erase the whole flash memory
+        #[clap(long, conflicts_with = "pages")]
+        all: bool,
+
+        /// Page numbers to erase
+        #[clap(required_unless_present = "all")]
+        pages: Vec<u16>,
+
+        /// Skip confirmation
+        #[clap(short, long)]
+        yes: bool,
+    },
+    /// Jump to code of current active device, which leaves the bootloader
+    ///
+    /// The device is dropped from the shell afterwards, and must be opened
+    /// again to return to the bootloader.
+    Go {
+        /// Address to jump to, base of flash memory by default
+        #[clap(value_parser = parse_u32)]
+        address: Option<u32>,
+    },
+    /// Compare two regions of current active device
+    Diff {
+        /// Address of one region, e.g. "0x08000000"
+        #[clap(value_parser = parse_u32)]
+        a: u32,
+
+        /// Address of the other region
+        #[clap(value_parser = parse_u32)]
+        b: u32,
+
+        /// Size of both regions in bytes
+        #[clap(value_parser = parse_u32)]
+        size: u32,
+    },
+    /// Change shell settings
+    Set {
+        #[clap(subcommand)]
+        setting: Setting,
+    },
+    /// Send raw bytes to current active device and print bytes received
+    ///
+    /// No framing, checksum or ACK handling is applied, for debugging only.
+    Raw {
+        /// Bytes in hex, e.g. "7f" or "0x7f"
+        #[clap(value_parser = parse_hex_byte)]
+        bytes: Vec<u8>,
+
+        /// Maximum number of bytes to receive
+        #[clap(short, long, default_value_t = 1)]
+        read: usize,
+
+        /// Timeout of receiving (in milliseconds)
+        #[clap(short, long, default_value_t = 100)]
+        timeout: u64,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum Setting {
+    /// Close the active device after being idle, releasing its port until the
+    /// next command requiring it
+    IdleClose {
+        /// Idle time (in seconds, 0 to disable)
+        secs: u64,
+    },
 }
 
 impl Command {
     fn requires_device(&self) -> bool {
-        match self {
-            Self::Get | Self::Version | Self::Id => true,
-            _ => false,
+        matches!(
+            self,
+            Self::Get
+                | Self::Version
+                | Self::Id
+                | Self::Read { .. }
+                | Self::Flash { .. }
+                | Self::Erase { .. }
+                | Self::Go { .. }
+                | Self::Raw { .. }
+                | Self::Diff { .. }
+        )
+    }
+}
+
+fn parse_hex_byte(s: &str) -> anyhow::Result<u8> {
+    let digits = s.trim_start_matches("0x");
+    Ok(u8::from_str_radix(digits, 16)?)
+}
+
+/// Parses a number in hex with prefix "0x", or in decimal otherwise.
+fn parse_u32(s: &str) -> anyhow::Result<u32> {
+    match s.strip_prefix("0x") {
+        Some(digits) => Ok(u32::from_str_radix(digits, 16)?),
+        None => Ok(s.parse()?),
+    }
+}
+
+/// Loads an image by the extension of its file, while raw binaries need an
+/// address.
+fn load_image(path: &Path, address: Option<u32>) -> anyhow::Result<Image> {
+    let reader = BufReader::new(File::open(path)?);
+    let extension = path.extension().and_then(|e| e.to_str());
+    let image = match (extension, address) {
+        (Some("bin"), Some(address)) => Image::from_bin(reader, address)?,
+        (Some("bin"), None) => return Err(anyhow!("--address is required for .bin")),
+        (_, Some(_)) => return Err(anyhow!("--address only applies to .bin")),
+        (Some("hex"), None) => Image::from_ihex(reader)?,
+        (Some("srec" | "s19" | "s28" | "s37"), None) => Image::from_srec(reader)?,
+        _ => {
+            return Err(anyhow!(
+                "unknown image format of {}, try \".hex\", \".srec\" or \".bin\"",
+                path.display()
+            ));
+        }
+    };
+    Ok(image)
+}
+
+/// Asks a yes/no question on the terminal, which defaults to no.
+fn confirm(question: &str) -> anyhow::Result<bool> {
+    eprint!("{} [y/N] ", question);
+    std::io::stderr().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// Draws a progress bar of flashing on stderr, in place.
+fn draw_progress(progress: FlashProgress) {
+    const WIDTH: usize = 40;
+    let done = match progress.total() {
+        0 => WIDTH,
+        total => progress.written() * WIDTH / total,
+    };
+    eprint!(
+        "\r[{}{}] {}/{} bytes",
+        "#".repeat(done),
+        " ".repeat(WIDTH - done),
+        progress.written(),
+        progress.total(),
+    );
+    let _ = std::io::stderr().flush();
+}
+
+/// Formats memory as lines of address, 16 bytes in hex and ASCII.
+fn hexdump(address: u32, data: &[u8]) -> String {
+    let mut dump = String::new();
+    for (i, line) in data.chunks(16).enumerate() {
+        let hex = line
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let ascii = line
+            .iter()
+            .map(|&b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect::<String>();
+        dump += &format!(
+            "{:08x}  {:<47}  |{}|\n",
+            address as u64 + i as u64 * 16,
+            hex,
+            ascii
+        );
+    }
+    dump
+}
+
+/// Collapses sorted offsets into inclusive ranges of consecutive offsets.
+fn offset_ranges(offsets: &[usize]) -> Vec<(usize, usize)> {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for &offset in offsets {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == offset => *end = offset,
+            _ => ranges.push((offset, offset)),
         }
     }
+    ranges
 }
 
 impl Shell {
-    pub fn new(options: ShellOptions) -> Self {
-        Self {
+    /// Interval between checks of the background thread.
+    const BACKGROUND_CHECK: Duration = Duration::from_millis(100);
+
+    pub fn new(options: ShellOptions, format: Format, probe: Probe) -> Self {
+        let session = Session {
+            devices: Vec::new(),
             current: None,
+            last_command: Instant::now(),
+            idle_close: options.idle_close.map(Duration::from_secs),
+        };
+        Self {
+            session: Arc::new(Mutex::new(session)),
             options,
-            probe: Probe::default(),
+            format,
+            probe,
         }
     }
 
-    fn prompt(&self) -> Cow<str> {
+    fn prompt(&self) -> Cow<'_, str> {
         if self.options.no_prompt {
             "".into()
         } else {
-            match &self.current {
-                Some(p) => p
-                    .inner()
-                    .name()
-                    .map_or("N/A".into(), |name| format!("yapu ({})> ", name).into()),
+            match self.session().current_mut() {
+                Some(device) => format!("yapu ({})> ", device.name).into(),
                 None => "yapu> ".into(),
             }
         }
     }
 
+    /// Prints a reply of the device as JSON or as text, according to the
+    /// output format.
+    fn print<T: std::fmt::Display + serde::Serialize>(&self, value: &T) -> anyhow::Result<()> {
+        if self.format.is_json() {
+            println!("{}", serde_json::to_string(value)?);
+        } else {
+            println!("{}", value);
+        }
+        Ok(())
+    }
+
+    fn session(&self) -> MutexGuard<'_, Session> {
+        // Devices stay usable even if the background thread panicked.
+        self.session.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Spawns the thread that pings idle devices and closes the active device
+    /// after [`Session::idle_close`].
+    fn spawn_background(&self) {
+        let session = Arc::downgrade(&self.session);
+        std::thread::spawn(move || {
+            // The thread stops once the shell is dropped.
+            while let Some(session) = session.upgrade() {
+                let mut session = session.lock().unwrap_or_else(PoisonError::into_inner);
+                if let Some(idle_close) = session.idle_close
+                    && session.last_command.elapsed() >= idle_close
+                    && let Some(device) = session.current_mut()
+                    && device.programmer.take().is_some()
+                {
+                    eprintln!(
+                        "\nclosed {} after being idle for {:?}",
+                        device.name, idle_close,
+                    );
+                }
+                for device in session.devices.iter_mut() {
+                    if let Some(programmer) = device.programmer.as_mut()
+                        && let Err(e) = programmer.keep_alive()
+                    {
+                        warn!("cannot keep device alive: {}", e);
+                    }
+                }
+                drop(session);
+                std::thread::sleep(Self::BACKGROUND_CHECK);
+            }
+        });
+    }
+
     fn execute(&mut self, command: &Command) -> anyhow::Result<()> {
         match command {
             Command::Clear => {
                 clearscreen::clear()?;
             }
             Command::Open { device } => {
-                let programmer = Programmer::open(device, &Probe::default())?;
-                self.current = Some(programmer);
+                let programmer = Programmer::open(device, &self.probe)?;
+                let mut session = self.session();
+                session.devices.push(programmer.into());
+                session.current = Some(session.devices.len() - 1);
             }
+            Command::Devices => {
+                let mut session = self.session();
+                // Ports held by cached devices are busy, so release them first.
+                let current = session.current_mut().map(|d| d.name.clone());
+                session.devices.clear();
+                session.current = None;
+                session.devices = Programmer::discover(&self.probe)?
+                    .into_iter()
+                    .map(Device::from)
+                    .collect();
+                session.current = current.and_then(|name| session.find(&name));
+                for (index, device) in session.devices.iter().enumerate() {
+                    let marker = if Some(index) == session.current {
+                        '*'
+                    } else {
+                        ' '
+                    };
+                    println!("{} {}: {}", marker, index, device.name);
+                }
+            }
+            Command::Select { device } => {
+                let mut session = self.session();
+                let index = session
+                    .find(device)
+                    .ok_or(anyhow!("no such device, try \"devices\""))?;
+                session.current = Some(index);
+            }
+            Command::Set { setting } => match setting {
+                Setting::IdleClose { secs } => {
+                    self.session().idle_close =
+                        Some(*secs).filter(|s| *s > 0).map(Duration::from_secs);
+                }
+            },
             Command::Probe {
                 baudrate,
                 reset,
                 boot,
+                scheme,
                 reset_for,
                 identify,
+                keepalive,
             } => {
                 if let Some(baudrate) = baudrate {
                     self.probe.set_baudrate(*baudrate);
                 }
+                if let Some(scheme) = scheme {
+                    self.probe.set_signal_scheme((*scheme).into());
+                }
                 if let Some(reset) = reset {
                     self.probe.set_signal_reset(reset.0);
                 }
@@ -130,24 +502,142 @@ impl Shell {
                 if let Some(identify) = identify {
                     self.probe.set_identify((*identify).into());
                 }
+                if let Some(keepalive) = keepalive {
+                    let interval = Some(*keepalive)
+                        .filter(|ms| *ms > 0)
+                        .map(Duration::from_millis);
+                    self.probe.set_keepalive_interval(interval);
+                }
             }
             command if command.requires_device() => {
-                let programmer = self
-                    .current
-                    .as_mut()
+                let mut session = self.session();
+                let device = session
+                    .current_mut()
                     .ok_or(anyhow!("you need to open a device"))?;
+                let programmer = match &mut device.programmer {
+                    Some(programmer) => programmer,
+                    None => {
+                        eprintln!("reopening {}", device.name);
+                        let programmer = Programmer::open(&device.name, &self.probe)?;
+                        device.programmer.insert(programmer)
+                    }
+                };
                 match command {
                     Command::Get => {
-                        println!("{:?}", programmer.read_bootloader()?);
+                        self.print(&programmer.read_bootloader()?)?;
                     }
                     Command::Version => {
-                        println!("{:?}", programmer.read_version()?);
+                        self.print(&programmer.read_version()?)?;
                     }
                     Command::Id => {
-                        println!("{:?}", programmer.read_id()?);
+                        self.print(&programmer.read_id()?)?;
+                    }
+                    Command::Read {
+                        address,
+                        size,
+                        output,
+                    } => {
+                        let data = programmer.read_memory(*address, *size as usize)?;
+                        if let Some(output) = output {
+                            std::fs::write(output, &data)?;
+                            println!("{} bytes written to {}", data.len(), output.display());
+                        } else if self.format.is_json() {
+                            let json = serde_json::json!({
+                                "address": address,
+                                "data": base64::engine::general_purpose::STANDARD.encode(&data),
+                            });
+                            println!("{}", json);
+                        } else {
+                            print!("{}", hexdump(*address, &data));
+                        }
+                    }
+                    Command::Flash {
+                        file,
+                        address,
+                        erase,
+                        verify,
+                    } => {
+                        let image = load_image(file, *address)?;
+                        let mut options = FlashOptions::new();
+                        options.set_erase(*erase);
+                        options.set_verify(*verify);
+                        options.set_progress(draw_progress);
+                        let result = programmer.flash(&image, options);
+                        eprintln!();
+                        result?;
+                        println!(
+                            "flashed {} bytes in {} segments{}",
+                            image.len(),
+                            image.segments().len(),
+                            if *verify {
+                                ", verified"
+                            } else {
+                                ", not verified"
+                            },
+                        );
+                    }
+                    Command::Erase { all, pages, yes } => {
+                        let question = if *all {
+                            format!("Erase the whole flash memory of {}?", device.name)
+                        } else {
+                            format!("Erase {} pages of {}?", pages.len(), device.name)
+                        };
+                        if !*yes && !confirm(&question)? {
+                            println!("cancelled");
+                            return Ok(());
+                        }
+                        if *all {
+                            programmer.erase_all()?;
+                            println!("erased the whole flash memory");
+                        } else {
+                            programmer.erase_pages(pages)?;
+                            println!("erased pages {:?}", pages);
+                        }
+                    }
+                    Command::Go { address } => {
+                        // Flash memory is always at a known base.
+                        let address = address.or(AddressSpace::Flash.base(None)).unwrap();
+                        programmer.go(address)?;
+                        println!(
+                            "jumped to {:#010x}, {} has left bootloader mode",
+                            address, device.name
+                        );
+                    }
+                    Command::Raw {
+                        bytes,
+                        read,
+                        timeout,
+                    } => {
+                        programmer.send_raw(bytes)?;
+                        let received =
+                            programmer.recv_raw(*read, Duration::from_millis(*timeout))?;
+                        if received.is_empty() {
+                            println!("(nothing received)");
+                        } else {
+                            let hex = received
+                                .iter()
+                                .map(|b| format!("{:02x}", b))
+                                .collect::<Vec<_>>();
+                            println!("{}", hex.join(" "));
+                        }
+                    }
+                    Command::Diff { a, b, size } => {
+                        let diff = programmer.diff_regions(*a, *b, *size as usize)?;
+                        if diff.is_empty() {
+                            println!("identical");
+                        }
+                        for (start, end) in offset_ranges(&diff) {
+                            println!("{:#010x}..={:#010x}", start, end);
+                        }
                     }
                     _ => unreachable!(),
                 }
+                if let Command::Go { .. } = command {
+                    // The bootloader no longer responds, so the port is closed.
+                    if let Some(current) = session.current.take() {
+                        session.devices.remove(current);
+                    }
+                }
             }
             _ => todo!(),
         }
@@ -158,11 +648,12 @@ impl Shell {
         #[allow(unused_imports)]
         use clap::error::{ContextKind, Error, ErrorKind};
         let segments = line.trim().split_ascii_whitespace().collect::<Vec<_>>();
-        if segments.len() > 0 {
+        if !segments.is_empty() {
             let command = Command::try_parse_from(segments).map_err(|mut e| {
                 e.remove(ContextKind::Usage);
                 e
             })?;
+            self.session().last_command = Instant::now();
             self.execute(&command)?;
         }
         Ok(())
@@ -170,6 +661,7 @@ impl Shell {
 
     pub fn run(&mut self) -> anyhow::Result<()> {
         let mut editor = DefaultEditor::new()?;
+        self.spawn_background();
         if !self.options.no_prompt {
             println!(
                 "yapu: Yet Another Programmer via USART ({})\n\
@@ -202,3 +694,17 @@ impl Shell {
         Ok(())
     }
 }
+
+#[test]
+fn dumping_hex() {
+    let data = (0x3eu8..0x52).collect::<Vec<_>>();
+    assert_eq!(
+        hexdump(0x0800_0000, &data),
+        "08000000  3e 3f 40 41 42 43 44 45 46 47 48 49 4a 4b 4c 4d  |>?@ABCDEFGHIJKLM|\n\
+         08000010  4e 4f 50 51                                      |NOPQ|\n"
+    );
+    assert_eq!(
+        hexdump(0, &[0x00, 0x20, 0x7f]),
+        format!("00000000  00 20 7f{}  |. .|\n", " ".repeat(39))
+    );
+}
@@ -0,0 +1,70 @@
+/// Initial value of [`stm32_crc32()`].
+pub(crate) const STM32_CRC32_INIT: u32 = 0xffff_ffff;
+
+/// Polynomial of [`stm32_crc32()`].
+pub(crate) const STM32_CRC32_POLYNOMIAL: u32 = 0x04c1_1db7;
+
+/// Computes CRC-32 the same way as the CRC unit of STM32 in its reset
+/// configuration, which GET_CHECKSUM uses as well: CRC-32/MPEG-2, i.e.
+/// polynomial `0x04c11db7`, initial value `0xffffffff`, no reflection and no
+/// final XOR.
+///
+/// The unit takes whole 32-bit words, most significant bit first. Memory is
+/// little endian, so the word at an address is [`u32::from_le_bytes()`] of
+/// the 4 bytes there, not the bytes in the order they're stored. Families
+/// whose unit can reverse input bits, e.g. F0, F3 or L4, don't by default, so
+/// no reflection is applied here either.
+pub fn stm32_crc32(words: &[u32]) -> u32 {
+    words
+        .iter()
+        .fold(STM32_CRC32_INIT, |crc, &word| update(crc, word))
+}
+
+/// Computes [`stm32_crc32()`] of memory contents, taken as little-endian
+/// words.
+///
+/// The length of data must be a multiple of 4; a trailing partial word is
+/// ignored.
+pub(crate) fn stm32_crc32_bytes(data: &[u8]) -> u32 {
+    stm32_crc32_update(STM32_CRC32_INIT, data)
+}
+
+/// Continues CRC-32 computed by [`stm32_crc32_bytes()`] over more data, so it
+/// can be computed piece by piece.
+pub(crate) fn stm32_crc32_update(crc: u32, data: &[u8]) -> u32 {
+    data.chunks_exact(4).fold(crc, |crc, word| {
+        update(crc, u32::from_le_bytes(word.try_into().unwrap()))
+    })
+}
+
+fn update(mut crc: u32, word: u32) -> u32 {
+    crc ^= word;
+    for _ in 0..32 {
+        crc = if crc & 0x8000_0000 != 0 {
+            (crc << 1) ^ STM32_CRC32_POLYNOMIAL
+        } else {
+            crc << 1
+        };
+    }
+    crc
+}
+
+#[test]
+fn computing_stm32_crc32() {
+    // As computed by the CRC unit, e.g. with HAL_CRC_Calculate().
+    assert_eq!(stm32_crc32(&[0x0000_0000]), 0xc704_dd7b);
+    assert_eq!(stm32_crc32(&[0x1234_5678]), 0xdf8a_8a2b);
+    assert_eq!(stm32_crc32(&[]), STM32_CRC32_INIT);
+
+    // Memory holds words in little endian.
+    let bytes = [0x78, 0x56, 0x34, 0x12, 0x00, 0x00, 0x00, 0x00];
+    assert_eq!(
+        stm32_crc32_bytes(&bytes),
+        stm32_crc32(&[0x1234_5678, 0x0000_0000])
+    );
+    let (head, tail) = bytes.split_at(4);
+    assert_eq!(
+        stm32_crc32_update(stm32_crc32_bytes(head), tail),
+        stm32_crc32_bytes(&bytes)
+    );
+}
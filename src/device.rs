@@ -0,0 +1,458 @@
+//! Knowledge of specific devices
+//!
+//! Product IDs are taken from AN2606, which lists the bootloader of each
+//! STM32 device.
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// STM32 family
+///
+/// Devices of a family tend to share bootloader behavior, option byte layout
+/// and erase style, even when the exact part is unknown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[non_exhaustive]
+pub enum Family {
+    F0,
+    F1,
+    F2,
+    F3,
+    F4,
+    F7,
+    G0,
+    G4,
+    H7,
+    L0,
+    L1,
+    L4,
+    L5,
+    U5,
+    WB,
+    WL,
+}
+
+impl Family {
+    /// Product IDs of each family.
+    const PRODUCT_IDS: &[(Family, &[u16])] = &[
+        (Family::F0, &[0x440, 0x442, 0x444, 0x445, 0x448]),
+        (
+            Family::F1,
+            &[0x410, 0x412, 0x414, 0x418, 0x420, 0x428, 0x430],
+        ),
+        (Family::F2, &[0x411]),
+        (Family::F3, &[0x422, 0x432, 0x438, 0x439, 0x446]),
+        (
+            Family::F4,
+            &[
+                0x413, 0x419, 0x421, 0x423, 0x431, 0x433, 0x434, 0x441, 0x458, 0x463,
+            ],
+        ),
+        (Family::F7, &[0x449, 0x451, 0x452]),
+        (Family::G0, &[0x456, 0x460, 0x466, 0x467]),
+        (Family::G4, &[0x468, 0x469, 0x479]),
+        (Family::H7, &[0x450, 0x480, 0x483]),
+        (Family::L0, &[0x417, 0x425, 0x447, 0x457]),
+        (Family::L1, &[0x416, 0x427, 0x429, 0x436, 0x437]),
+        (
+            Family::L4,
+            &[0x415, 0x435, 0x461, 0x462, 0x464, 0x470, 0x471],
+        ),
+        (Family::L5, &[0x472]),
+        (Family::U5, &[0x455, 0x481, 0x482]),
+        (Family::WB, &[0x495]),
+        (Family::WL, &[0x497]),
+    ];
+
+    /// Registers common to all devices of each family.
+    ///
+    /// Families whose registers move around between parts, e.g. F7 and H7,
+    /// are left out rather than guessed.
+    const REGISTERS: &[(Family, &[Register])] = &[
+        (
+            Family::F0,
+            &[
+                Register::new("flash_size", 0x1fff_f7cc, 2),
+                Register::new("uid", 0x1fff_f7ac, 12),
+                Register::new("option_bytes", 0x1fff_f800, 16),
+            ],
+        ),
+        (
+            Family::F1,
+            &[
+                Register::new("flash_size", 0x1fff_f7e0, 2),
+                Register::new("uid", 0x1fff_f7e8, 12),
+                Register::new("option_bytes", 0x1fff_f800, 16),
+            ],
+        ),
+        (
+            Family::F2,
+            &[
+                Register::new("flash_size", 0x1fff_7a22, 2),
+                Register::new("uid", 0x1fff_7a10, 12),
+                Register::new("option_bytes", 0x1fff_c000, 16),
+            ],
+        ),
+        (
+            Family::F3,
+            &[
+                Register::new("flash_size", 0x1fff_f7cc, 2),
+                Register::new("uid", 0x1fff_f7ac, 12),
+                Register::new("option_bytes", 0x1fff_f800, 16),
+            ],
+        ),
+        (
+            Family::F4,
+            &[
+                Register::new("flash_size", 0x1fff_7a22, 2),
+                Register::new("uid", 0x1fff_7a10, 12),
+                Register::new("option_bytes", 0x1fff_c000, 16),
+            ],
+        ),
+        (Family::G0, Self::REGISTERS_G0),
+        (Family::G4, Self::REGISTERS_G0),
+        (Family::L4, Self::REGISTERS_G0),
+        (Family::WB, Self::REGISTERS_G0),
+        (Family::WL, Self::REGISTERS_G0),
+        (
+            Family::L5,
+            &[
+                Register::new("flash_size", 0x0bfa_05e0, 2),
+                Register::new("uid", 0x0bfa_0590, 12),
+                Register::new("package", 0x0bfa_0500, 2),
+            ],
+        ),
+        (
+            Family::U5,
+            &[
+                Register::new("flash_size", 0x0bfa_07a0, 2),
+                Register::new("uid", 0x0bfa_0700, 12),
+                Register::new("package", 0x0bfa_0500, 2),
+            ],
+        ),
+    ];
+
+    /// Registers shared by G0 and later families with the same layout.
+    const REGISTERS_G0: &[Register] = &[
+        Register::new("flash_size", 0x1fff_75e0, 2),
+        Register::new("uid", 0x1fff_7590, 12),
+        Register::new("package", 0x1fff_7500, 2),
+    ];
+
+    /// Looks up the family of a product ID, as returned by GET_ID.
+    ///
+    /// Returns [`None`] for product IDs not known to belong to any family.
+    pub fn from_product_id(pid: u16) -> Option<Self> {
+        Self::PRODUCT_IDS
+            .iter()
+            .find(|(_, pids)| pids.contains(&pid))
+            .map(|(family, _)| *family)
+    }
+
+    /// Gets registers known for the family, like `flash_size`, `uid`,
+    /// `package` and `option_bytes`.
+    pub fn registers(&self) -> &'static [Register] {
+        Self::REGISTERS
+            .iter()
+            .find(|(family, _)| family == self)
+            .map_or(&[], |(_, registers)| registers)
+    }
+
+    /// Looks up a register of the family by name.
+    pub fn register(&self, name: &str) -> Option<Register> {
+        self.registers()
+            .iter()
+            .find(|register| register.name() == name)
+            .copied()
+    }
+}
+
+/// Devices erased by uniform pages, taken from AN2606 and reference manuals.
+///
+/// Each product ID covers a line of parts, so sizes are those of its largest
+/// part. Products erased by sectors of varying sizes, e.g. F2, F4 and F7, are
+/// left out, as are those whose page size depends on the bank configuration.
+const DEVICES: &[(u16, DeviceInfo)] = &[
+    (
+        0x410,
+        DeviceInfo::new("STM32F10xxx medium-density", 128, 1024, 20 * 1024),
+    ),
+    (
+        0x412,
+        DeviceInfo::new("STM32F10xxx low-density", 32, 1024, 10 * 1024),
+    ),
+    (
+        0x414,
+        DeviceInfo::new("STM32F10xxx high-density", 256, 2048, 64 * 1024),
+    ),
+    (
+        0x415,
+        DeviceInfo::new("STM32L47xxx/48xxx", 512, 2048, 128 * 1024).with_alignment(8),
+    ),
+    (
+        0x416,
+        DeviceInfo::new("STM32L1xxx6(8/B)", 512, 256, 16 * 1024),
+    ),
+    (
+        0x417,
+        DeviceInfo::new("STM32L05xxx/06xxx", 512, 128, 8 * 1024),
+    ),
+    (
+        0x418,
+        DeviceInfo::new("STM32F105xx/107xx", 128, 2048, 64 * 1024),
+    ),
+    (
+        0x420,
+        DeviceInfo::new("STM32F10xxx medium-density value line", 128, 1024, 8 * 1024),
+    ),
+    (
+        0x422,
+        DeviceInfo::new("STM32F302xB(C)/303xB(C)/358xx", 128, 2048, 40 * 1024),
+    ),
+    (
+        0x425,
+        DeviceInfo::new("STM32L031xx/041xx", 256, 128, 8 * 1024),
+    ),
+    (0x427, DeviceInfo::new("STM32L1xxxC", 1024, 256, 32 * 1024)),
+    (
+        0x428,
+        DeviceInfo::new("STM32F10xxx high-density value line", 256, 2048, 32 * 1024),
+    ),
+    (
+        0x429,
+        DeviceInfo::new("STM32L1xxx6(8/B)A", 512, 256, 32 * 1024),
+    ),
+    (
+        0x430,
+        DeviceInfo::new("STM32F10xxx XL-density", 512, 2048, 96 * 1024),
+    ),
+    (
+        0x432,
+        DeviceInfo::new("STM32F373xx/378xx", 128, 2048, 32 * 1024),
+    ),
+    (
+        0x435,
+        DeviceInfo::new("STM32L43xxx/44xxx", 128, 2048, 64 * 1024).with_alignment(8),
+    ),
+    (0x436, DeviceInfo::new("STM32L1xxxD", 1536, 256, 48 * 1024)),
+    (0x437, DeviceInfo::new("STM32L1xxxE", 2048, 256, 80 * 1024)),
+    (
+        0x438,
+        DeviceInfo::new("STM32F303x4(6/8)/334xx/328xx", 32, 2048, 12 * 1024),
+    ),
+    (
+        0x439,
+        DeviceInfo::new("STM32F301xx/302x4(6/8)/318xx", 32, 2048, 16 * 1024),
+    ),
+    (
+        0x440,
+        DeviceInfo::new("STM32F05xxx/030x8", 64, 1024, 8 * 1024),
+    ),
+    (
+        0x442,
+        DeviceInfo::new("STM32F09xxx/030xC", 128, 2048, 32 * 1024),
+    ),
+    (0x444, DeviceInfo::new("STM32F03xx4/6", 32, 1024, 4 * 1024)),
+    (
+        0x445,
+        DeviceInfo::new("STM32F04xxx/070x6", 32, 1024, 6 * 1024),
+    ),
+    (
+        0x446,
+        DeviceInfo::new("STM32F302xD(E)/303xD(E)/398xx", 256, 2048, 64 * 1024),
+    ),
+    (
+        0x447,
+        DeviceInfo::new("STM32L07xxx/08xxx", 1536, 128, 20 * 1024),
+    ),
+    (0x448, DeviceInfo::new("STM32F07xxx", 64, 2048, 16 * 1024)),
+    (
+        0x456,
+        DeviceInfo::new("STM32G05xxx/061xx", 32, 2048, 18 * 1024).with_alignment(8),
+    ),
+    (
+        0x457,
+        DeviceInfo::new("STM32L01xxx/02xxx", 128, 128, 2 * 1024),
+    ),
+    (
+        0x460,
+        DeviceInfo::new("STM32G07xxx/08xxx", 64, 2048, 36 * 1024).with_alignment(8),
+    ),
+    (
+        0x461,
+        DeviceInfo::new("STM32L496xx/4A6xx", 512, 2048, 320 * 1024).with_alignment(8),
+    ),
+    (
+        0x462,
+        DeviceInfo::new("STM32L45xxx/46xxx", 256, 2048, 160 * 1024).with_alignment(8),
+    ),
+    (
+        0x464,
+        DeviceInfo::new("STM32L41xxx/42xxx", 64, 2048, 40 * 1024).with_alignment(8),
+    ),
+    (
+        0x466,
+        DeviceInfo::new("STM32G03xxx/04xxx", 32, 2048, 8 * 1024).with_alignment(8),
+    ),
+    (
+        0x467,
+        DeviceInfo::new("STM32G0Bxxx/0Cxxx", 256, 2048, 144 * 1024).with_alignment(8),
+    ),
+    (
+        0x468,
+        DeviceInfo::new("STM32G431xx/441xx", 64, 2048, 32 * 1024).with_alignment(8),
+    ),
+    (
+        0x479,
+        DeviceInfo::new("STM32G491xx/4A1xx", 256, 2048, 112 * 1024).with_alignment(8),
+    ),
+    (
+        0x495,
+        DeviceInfo::new("STM32WB5xxx/35xx", 256, 4096, 256 * 1024).with_alignment(8),
+    ),
+    (
+        0x497,
+        DeviceInfo::new("STM32WLE5xx/WL55xx", 128, 2048, 64 * 1024).with_alignment(8),
+    ),
+];
+
+/// Looks up a device by product ID, as returned by GET_ID.
+///
+/// Returns [`None`] for products not erased by uniform pages, or not known.
+pub fn lookup(pid: u16) -> Option<DeviceInfo> {
+    DEVICES
+        .iter()
+        .find(|(id, _)| *id == pid)
+        .map(|(_, info)| *info)
+}
+
+/// Looks up the size of flash pages of a product ID, as returned by GET_ID.
+///
+/// Returns [`None`] for products not erased by uniform pages, or not known.
+pub fn page_size(pid: u16) -> Option<u32> {
+    lookup(pid).map(|info| info.page_size())
+}
+
+/// Name and memory sizes of a device, see [`lookup()`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct DeviceInfo {
+    name: &'static str,
+    flash_pages: u32,
+    page_size: u32,
+    ram: u32,
+    alignment: u32,
+}
+
+impl DeviceInfo {
+    /// Creates a [`DeviceInfo`], whose flash memory is written by 32-bit
+    /// words.
+    pub const fn new(name: &'static str, flash_pages: u32, page_size: u32, ram: u32) -> Self {
+        Self {
+            name,
+            flash_pages,
+            page_size,
+            ram,
+            alignment: 4,
+        }
+    }
+
+    /// Changes alignment of writes, e.g. 8 for devices whose flash memory is
+    /// written by 64-bit double words.
+    pub const fn with_alignment(mut self, alignment: u32) -> Self {
+        self.alignment = alignment;
+        self
+    }
+
+    /// Gets name of the device, e.g. `STM32F10xxx medium-density`.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Gets number of flash pages.
+    pub fn flash_pages(&self) -> u32 {
+        self.flash_pages
+    }
+
+    /// Gets size of flash pages in bytes.
+    pub fn page_size(&self) -> u32 {
+        self.page_size
+    }
+
+    /// Gets size of flash memory in bytes.
+    pub fn flash_size(&self) -> u32 {
+        self.flash_pages * self.page_size
+    }
+
+    /// Gets size of SRAM in bytes.
+    pub fn ram(&self) -> u32 {
+        self.ram
+    }
+
+    /// Gets alignment of addresses written in bytes.
+    pub fn alignment(&self) -> u32 {
+        self.alignment
+    }
+}
+
+/// Register in the system memory of a device, read by name
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Register {
+    name: &'static str,
+    address: u32,
+    size: usize,
+}
+
+impl Register {
+    /// Creates a [`Register`].
+    pub const fn new(name: &'static str, address: u32, size: usize) -> Self {
+        Self {
+            name,
+            address,
+            size,
+        }
+    }
+
+    /// Gets name of the register.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Gets address of the register.
+    pub fn address(&self) -> u32 {
+        self.address
+    }
+
+    /// Gets size of the register in bytes.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+}
+
+impl std::fmt::Display for Family {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "STM32{:?}", self)
+    }
+}
+
+#[test]
+fn looking_up() {
+    let f103 = lookup(0x410).unwrap();
+    assert_eq!(f103.name(), "STM32F10xxx medium-density");
+    assert_eq!(f103.flash_size(), 128 * 1024);
+    assert_eq!(f103.page_size(), 1024);
+    assert_eq!(f103.ram(), 20 * 1024);
+
+    let l0 = lookup(0x417).unwrap();
+    assert_eq!(l0.flash_pages(), 512);
+    assert_eq!(l0.page_size(), 128);
+    assert_eq!(l0.alignment(), 4);
+    // L4 is written by double words.
+    assert_eq!(lookup(0x415).unwrap().alignment(), 8);
+
+    assert_eq!(lookup(0x495).unwrap().page_size(), 4096);
+    assert_eq!(page_size(0x468), Some(2048));
+    // F4 is erased by sectors.
+    assert_eq!(lookup(0x413), None);
+    assert_eq!(lookup(0xffff), None);
+}
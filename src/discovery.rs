@@ -0,0 +1,240 @@
+use crate::{Error, Probe, Programmer, Result};
+use log::debug;
+use serialport::{SerialPortInfo, SerialPortType};
+use std::sync::Arc;
+
+/// Options for [`Programmer::discover_with()`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoverOptions {
+    ports: Vec<String>,
+    usb_ids: Vec<(u16, u16)>,
+    parallel: bool,
+}
+
+impl Default for DiscoverOptions {
+    fn default() -> Self {
+        Self {
+            ports: Vec::new(),
+            usb_ids: Vec::new(),
+            parallel: true,
+        }
+    }
+}
+
+impl DiscoverOptions {
+    /// Creates default [`DiscoverOptions`], which probe every available port
+    /// in parallel.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Gets ports to probe, or all available ones if empty.
+    pub fn ports(&self) -> &[String] {
+        &self.ports
+    }
+
+    /// Sets ports to probe, or all available ones if empty.
+    pub fn set_ports(&mut self, ports: Vec<String>) {
+        self.ports = ports;
+    }
+
+    /// Gets USB vendor and product IDs of ports to probe, or any port if
+    /// empty.
+    pub fn usb_ids(&self) -> &[(u16, u16)] {
+        &self.usb_ids
+    }
+
+    /// Sets USB vendor and product IDs of ports to probe, e.g. of a specific
+    /// USB-to-serial adapter, or any port if empty.
+    ///
+    /// Ports other than USB ones are left untouched if any ID is set, so
+    /// modems and debug probes aren't reset by accident.
+    pub fn set_usb_ids(&mut self, ids: Vec<(u16, u16)>) {
+        self.usb_ids = ids;
+    }
+
+    /// Gets whether ports are probed in parallel.
+    pub fn parallel(&self) -> bool {
+        self.parallel
+    }
+
+    /// Sets whether ports are probed in parallel, each on its own thread.
+    pub fn set_parallel(&mut self, parallel: bool) {
+        self.parallel = parallel;
+    }
+
+    /// Checks whether a port passes the USB ID filter.
+    fn accepts(&self, info: &SerialPortInfo) -> bool {
+        if self.usb_ids.is_empty() {
+            return true;
+        }
+        match &info.port_type {
+            SerialPortType::UsbPort(usb) => self.usb_ids.contains(&(usb.vid, usb.pid)),
+            _ => false,
+        }
+    }
+}
+
+/// Outcome of probing a port during discovery
+// Outcomes are short-lived and devices are moved out right away, so boxing
+// the programmer isn't worth it.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug)]
+pub enum Discovered {
+    /// A compliant device responds on the port.
+    Device(Programmer),
+    /// The port opens but fails on I/O, see [`Error::Unusable`].
+    Unusable(std::io::Error),
+    /// The port works but no device responds, with the
+    /// [`Error::Unidentified`] telling how it was tried.
+    Unresponsive(Error),
+    /// The port cannot be opened, or any other failure.
+    Failed(Error),
+}
+
+impl Discovered {
+    /// Whether a compliant device is found.
+    pub fn is_device(&self) -> bool {
+        matches!(self, Self::Device(..))
+    }
+
+    /// Returns the programmer if a compliant device is found.
+    pub fn into_device(self) -> Option<Programmer> {
+        match self {
+            Self::Device(programmer) => Some(programmer),
+            _ => None,
+        }
+    }
+}
+
+impl From<Result<Programmer>> for Discovered {
+    fn from(value: Result<Programmer>) -> Self {
+        match value {
+            Ok(programmer) => Self::Device(programmer),
+            Err(Error::Unusable(e)) => Self::Unusable(e),
+            Err(e @ Error::Unidentified { .. }) => Self::Unresponsive(e),
+            Err(e) => Self::Failed(e),
+        }
+    }
+}
+
+impl Programmer {
+    /// Discovers compliant devices using a probe.
+    pub fn discover(probe: &Probe) -> Result<Vec<Self>> {
+        let devices = Self::discover_detailed(probe)?
+            .into_iter()
+            .filter_map(|(_, discovered)| discovered.into_device())
+            .collect();
+        Ok(devices)
+    }
+
+    /// Discovers compliant devices only on the given ports, leaving other
+    /// serial devices untouched.
+    ///
+    /// Unlike [`Self::open()`], ports without a responding device are skipped.
+    pub fn discover_on(probe: &Probe, ports: &[String]) -> Vec<Self> {
+        Self::discover_detailed_on(probe, ports)
+            .into_iter()
+            .filter_map(|(_, discovered)| discovered.into_device())
+            .collect()
+    }
+
+    /// Probes every available port and reports the outcome for each one along
+    /// with its name.
+    pub fn discover_detailed(probe: &Probe) -> Result<Vec<(String, Discovered)>> {
+        Self::discover_with(probe, &DiscoverOptions::new())
+    }
+
+    /// Probes the given ports and reports the outcome for each one along with
+    /// its name, in the order given.
+    pub fn discover_detailed_on(probe: &Probe, ports: &[String]) -> Vec<(String, Discovered)> {
+        Self::probe_ports(probe, ports, true)
+    }
+
+    /// Probes ports chosen by options and reports the outcome for each one
+    /// along with its name.
+    ///
+    /// Failing ports are reported as such, while the others are still probed.
+    /// Fails only if available ports cannot be listed.
+    pub fn discover_with(
+        probe: &Probe,
+        options: &DiscoverOptions,
+    ) -> Result<Vec<(String, Discovered)>> {
+        let ports = if options.ports.is_empty() || !options.usb_ids.is_empty() {
+            let available = serialport::available_ports()?;
+            available
+                .into_iter()
+                .filter(|info| options.ports.is_empty() || options.ports.contains(&info.port_name))
+                .filter(|info| {
+                    let accepted = options.accepts(info);
+                    if !accepted {
+                        debug!("skipping {}: USB ID not listed", info.port_name);
+                    }
+                    accepted
+                })
+                .map(|info| info.port_name)
+                .collect()
+        } else {
+            options.ports.clone()
+        };
+        Ok(Self::probe_ports(probe, &ports, options.parallel))
+    }
+
+    /// Probes ports, each on its own thread if `parallel`, and reports the
+    /// outcome for each one in the order given.
+    fn probe_ports(probe: &Probe, ports: &[String], parallel: bool) -> Vec<(String, Discovered)> {
+        let probe = Arc::new(probe.clone());
+        let open = |name: &String| -> (String, Discovered) {
+            (name.clone(), Self::open_shared(name, probe.clone()).into())
+        };
+        if !parallel {
+            return ports.iter().map(open).collect();
+        }
+        std::thread::scope(|scope| {
+            let handles = ports
+                .iter()
+                .map(|name| scope.spawn(|| open(name)))
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .zip(ports)
+                .map(|(handle, name)| {
+                    handle.join().unwrap_or_else(|_| {
+                        let e = std::io::Error::other("probing panicked");
+                        (name.clone(), Discovered::Failed(e.into()))
+                    })
+                })
+                .collect()
+        })
+    }
+}
+
+#[test]
+fn filtering_usb_ids() {
+    use serialport::UsbPortInfo;
+
+    let usb = |vid, pid| SerialPortInfo {
+        port_name: "/dev/ttyUSB0".into(),
+        port_type: SerialPortType::UsbPort(UsbPortInfo {
+            vid,
+            pid,
+            serial_number: None,
+            manufacturer: None,
+            product: None,
+        }),
+    };
+    let builtin = SerialPortInfo {
+        port_name: "/dev/ttyS0".into(),
+        port_type: SerialPortType::Unknown,
+    };
+
+    let mut options = DiscoverOptions::new();
+    assert!(options.accepts(&usb(0x0403, 0x6001)));
+    assert!(options.accepts(&builtin));
+
+    options.set_usb_ids(vec![(0x0403, 0x6001), (0x10c4, 0xea60)]);
+    assert!(options.accepts(&usb(0x0403, 0x6001)));
+    assert!(options.accepts(&usb(0x10c4, 0xea60)));
+    assert!(!options.accepts(&usb(0x0483, 0x374b)));
+    assert!(!options.accepts(&builtin));
+}
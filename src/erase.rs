@@ -0,0 +1,364 @@
+use crate::address::AddressSpace;
+use crate::device;
+use crate::image::Image;
+use crate::{Command, Erase, Error, ExtendedErase, Opcode, Programmer, Result};
+use crate::{ExtendedPageNos, PageNos};
+use log::debug;
+
+/// Pages of flash memory to be erased for an image, see
+/// [`Programmer::plan_erase()`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErasePlan {
+    page_size: u32,
+    pages: Vec<u16>,
+}
+
+impl ErasePlan {
+    /// Gets size of flash pages of the device.
+    pub fn page_size(&self) -> u32 {
+        self.page_size
+    }
+
+    /// Gets page numbers in ascending order, each one only once.
+    pub fn pages(&self) -> &[u16] {
+        &self.pages
+    }
+
+    /// Checks whether there's nothing to erase.
+    pub fn is_empty(&self) -> bool {
+        self.pages.is_empty()
+    }
+}
+
+/// Flash memory bank of dual-bank parts, see [`Programmer::erase_bank()`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Bank {
+    /// Bank 1, at the base of flash memory
+    One,
+    /// Bank 2, following bank 1
+    Two,
+}
+
+impl From<Bank> for ExtendedErase<'static> {
+    fn from(value: Bank) -> Self {
+        match value {
+            Bank::One => Self::Bank1,
+            Bank::Two => Self::Bank2,
+        }
+    }
+}
+
+impl Programmer {
+    /// Plans the minimal erase for an image, i.e. every page touched by any of
+    /// its segments.
+    ///
+    /// The page size is looked up by product ID, see [`device::page_size()`].
+    /// Segments must lie within flash memory, whose size is read from the
+    /// device if known for its family.
+    pub fn plan_erase(&mut self, image: &Image) -> Result<ErasePlan> {
+        let id = self.read_id()?;
+        let pid = id.as_u16();
+        let page_size = device::page_size(pid).ok_or(Error::UnknownLayout(pid))?;
+        // Flash memory is always at a known base.
+        let base = AddressSpace::Flash.base(id.family()).unwrap();
+        let end = self
+            .read_flash_size(id.family())?
+            .map(|size| base as u64 + size);
+
+        let mut pages = Vec::new();
+        for segment in image.segments().iter().filter(|s| !s.data().is_empty()) {
+            let out_of_range = || Error::OutOfRange {
+                address: segment.address(),
+                size: segment.data().len(),
+            };
+            if segment.address() < base || end.is_some_and(|end| segment.end() > end) {
+                return Err(out_of_range());
+            }
+            let first = (segment.address() - base) as u64 / page_size as u64;
+            let last = (segment.end() - 1 - base as u64) / page_size as u64;
+            for page in first..=last {
+                pages.push(u16::try_from(page).map_err(|_| out_of_range())?);
+            }
+        }
+        pages.sort_unstable();
+        pages.dedup();
+        Ok(ErasePlan { page_size, pages })
+    }
+
+    /// Erases only the pages an image will occupy, without writing it.
+    ///
+    /// The plan executed is returned, so it can be logged. If the device
+    /// refuses, e.g. because some pages are write-protected, pages are erased
+    /// one by one to find out which, and those failed are reported by
+    /// [`Error::EraseFailed`].
+    pub fn erase_for_image(&mut self, image: &Image) -> Result<ErasePlan> {
+        let plan = self.plan_erase(image)?;
+        debug!(
+            "erasing {} pages of {} bytes",
+            plan.pages().len(),
+            plan.page_size()
+        );
+        match self.erase_pages(plan.pages()) {
+            Err(Error::NAck) => {}
+            result => return result.map(|_| plan),
+        }
+        let mut failed = Vec::new();
+        for &page in plan.pages() {
+            match self.erase_pages(&[page]) {
+                Ok(()) => {}
+                Err(Error::NAck) => failed.push(page),
+                Err(e) => return Err(e),
+            }
+        }
+        if failed.is_empty() {
+            Ok(plan)
+        } else {
+            Err(Error::EraseFailed(failed))
+        }
+    }
+
+    /// Erases the whole flash memory, with EXTENDED_ERASE if the bootloader
+    /// supports it, or with ERASE otherwise.
+    ///
+    /// Fails with [`Error::Unsupported`] if the bootloader supports neither.
+    pub fn erase_all(&mut self) -> Result<()> {
+        let bootloader = self.bootloader_or_read()?;
+        if bootloader.supports(Opcode::EXTENDED_ERASE) {
+            self.send_command(Command::ExtendedErase(ExtendedErase::Global))
+        } else if bootloader.supports(Opcode::ERASE) {
+            self.send_command(Command::Erase(Erase::Global))
+        } else {
+            Err(Error::Unsupported(Opcode::ERASE))
+        }
+    }
+
+    /// Erases a whole bank of a dual-bank part, e.g. the inactive bank while
+    /// updating a golden image.
+    ///
+    /// Only EXTENDED_ERASE can address banks, so a bootloader without it fails
+    /// with [`Error::Unsupported`].
+    pub fn erase_bank(&mut self, bank: Bank) -> Result<()> {
+        if !self.bootloader_or_read()?.supports(Opcode::EXTENDED_ERASE) {
+            return Err(Error::Unsupported(Opcode::EXTENDED_ERASE));
+        }
+        self.send_command(Command::ExtendedErase(bank.into()))
+    }
+
+    /// Erases specific pages, with EXTENDED_ERASE if the bootloader supports
+    /// it, or with ERASE otherwise.
+    ///
+    /// ERASE only addresses the first 256 pages, so other pages fail with
+    /// [`Error::Unsupported`] for lack of EXTENDED_ERASE, as does a bootloader
+    /// supporting neither. It takes at most 255 pages at once, since 256 would
+    /// be framed as `0xff`, which stands for a global erase.
+    pub fn erase_pages(&mut self, pages: &[u16]) -> Result<()> {
+        let bootloader = self.bootloader_or_read()?;
+        if bootloader.supports(Opcode::EXTENDED_ERASE) {
+            for chunk in pages.chunks(0xff00) {
+                let pages = ExtendedPageNos::try_from(chunk)?;
+                self.send_command(Command::ExtendedErase(ExtendedErase::Specific(pages)))?;
+            }
+            Ok(())
+        } else if bootloader.supports(Opcode::ERASE) {
+            let pages = pages
+                .iter()
+                .map(|&page| u8::try_from(page))
+                .collect::<std::result::Result<Vec<_>, _>>()
+                .map_err(|_| Error::Unsupported(Opcode::EXTENDED_ERASE))?;
+            for chunk in pages.chunks(255) {
+                let pages = PageNos::try_from(chunk)?;
+                self.send_command(Command::Erase(Erase::Specific(pages)))?;
+            }
+            Ok(())
+        } else {
+            Err(Error::Unsupported(Opcode::ERASE))
+        }
+    }
+}
+
+#[test]
+fn erasing() -> Result<()> {
+    use crate::Probe;
+    use crate::mock::MockPort;
+
+    let get = |version, erase| {
+        let mut reply = vec![0x79, 0x0b, version, 0x00, 0x01, 0x02, 0x11, 0x21, 0x31];
+        reply.extend([erase, 0x63, 0x73, 0x82, 0x92, 0x79]);
+        reply
+    };
+    // ERASE only, e.g. F1
+    let port = MockPort::new(&[get(0x22, 0x43), vec![0x79; 4]].concat());
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &Probe::new());
+    programmer.erase_all()?;
+    programmer.erase_pages(&[1, 2])?;
+    assert_eq!(
+        port.written(),
+        [
+            0x00, 0xff, 0x43, 0xbc, 0xff, 0x00, 0x43, 0xbc, 0x01, 0x01, 0x02, 0x02
+        ]
+    );
+    assert!(
+        programmer
+            .erase_pages(&[256])
+            .is_err_and(|e| e.is_unsupported())
+    );
+
+    // EXTENDED_ERASE only, e.g. F4
+    let port = MockPort::new(&[get(0x31, 0x44), vec![0x79; 4]].concat());
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &Probe::new());
+    programmer.erase_all()?;
+    programmer.erase_pages(&[1, 2])?;
+    assert_eq!(
+        port.written(),
+        [
+            0x00, 0xff, 0x44, 0xbb, 0xff, 0xff, 0x00, 0x44, 0xbb, 0x00, 0x01, 0x00, 0x01, 0x00,
+            0x02, 0x02
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn erasing_many_pages() -> Result<()> {
+    use crate::Probe;
+    use crate::mock::MockPort;
+
+    // ERASE only, e.g. F1 high-density with 256 pages
+    let mut replies = vec![0x79, 0x0b, 0x22, 0x00, 0x01, 0x02, 0x11, 0x21, 0x31];
+    replies.extend([0x43, 0x63, 0x73, 0x82, 0x92, 0x79]);
+    replies.extend([0x79; 4]);
+    let port = MockPort::new(&replies);
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &Probe::new());
+    let pages: Vec<u16> = (0..256).collect();
+    programmer.erase_pages(&pages)?;
+
+    // 255 pages, then the last one, since N-1 = 0xff is a global erase.
+    let first: Vec<u8> = (0..255).collect();
+    let mut expected = vec![0x00, 0xff, 0x43, 0xbc, 0xfe];
+    expected.extend(&first);
+    expected.push(first.iter().fold(0xfe, |a, b| a ^ b));
+    expected.extend([0x43, 0xbc, 0x00, 0xff, 0xff]);
+    assert_eq!(port.written(), expected);
+
+    assert!(PageNos::try_from(vec![0u8; 256]).is_err());
+    Ok(())
+}
+
+#[test]
+fn erasing_banks() -> Result<()> {
+    use crate::Probe;
+    use crate::mock::MockPort;
+
+    let get = |version, erase| {
+        let mut reply = vec![0x79, 0x0b, version, 0x00, 0x01, 0x02, 0x11, 0x21, 0x31];
+        reply.extend([erase, 0x63, 0x73, 0x82, 0x92, 0x79]);
+        reply
+    };
+    let port = MockPort::new(&[get(0x31, 0x44), vec![0x79; 4]].concat());
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &Probe::new());
+    programmer.erase_bank(Bank::One)?;
+    programmer.erase_bank(Bank::Two)?;
+    assert_eq!(
+        port.written()[2..],
+        [0x44, 0xbb, 0xff, 0xfe, 0x01, 0x44, 0xbb, 0xff, 0xfd, 0x02]
+    );
+
+    // ERASE cannot address banks.
+    let port = MockPort::new(&get(0x22, 0x43));
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &Probe::new());
+    assert!(
+        programmer
+            .erase_bank(Bank::Two)
+            .is_err_and(|e| e.is_unsupported())
+    );
+    assert_eq!(port.written(), [0x00, 0xff]);
+    Ok(())
+}
+
+#[test]
+fn erasing_within_timeout() -> Result<()> {
+    use crate::Probe;
+    use crate::mock::MockPort;
+    use serialport::SerialPort;
+    use std::time::Duration;
+
+    let mut replies = vec![0x79, 0x0b, 0x22, 0x00, 0x01, 0x02, 0x11, 0x21, 0x31];
+    replies.extend([0x43, 0x63, 0x73, 0x82, 0x92, 0x79, 0x79, 0x79]);
+    let port = MockPort::new(&replies);
+    let mut builder = Probe::builder();
+    builder
+        .ack_timeout(Duration::from_millis(100))
+        .erase_ack_timeout(Duration::from_secs(40));
+    let probe = builder.build();
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &probe);
+    programmer.bootloader_or_read()?;
+    let before = port.timeout();
+    let set = port.timeouts().len();
+    programmer.erase_all()?;
+    assert_eq!(
+        port.timeouts()[set..],
+        [
+            Duration::from_millis(100),
+            before,
+            Duration::from_secs(40),
+            before
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn planning_erase() -> Result<()> {
+    use crate::Probe;
+    use crate::mock::{Faults, MockPort};
+
+    let port = MockPort::device(Faults::default());
+    let mut programmer = Programmer::attach(Box::new(port), &Probe::new());
+    let mut image = Image::new();
+    image.push(0x0800_0000, [0xa5; 0x10]);
+    // Crossing into the next page, and overlapping the first segment's page
+    image.push(0x0800_03f0, [0xa5; 0x20]);
+    image.push(0x0800_2000, [0xa5]);
+    image.push(0x0800_4000, []);
+    let plan = programmer.plan_erase(&image)?;
+    assert_eq!(plan.page_size(), 1024);
+    assert_eq!(plan.pages(), [0, 1, 8]);
+
+    // Below flash memory, and past the 64 KiB in the flash size register
+    for (address, size) in [(0x07ff_fff0, 0x20), (0x0800_ff00, 0x200)] {
+        let mut image = Image::new();
+        image.push(address, vec![0xa5; size]);
+        let result = programmer.plan_erase(&image);
+        assert!(result.is_err_and(|e| e.is_out_of_range()));
+    }
+    Ok(())
+}
+
+#[test]
+fn erasing_for_image() -> Result<()> {
+    use crate::Probe;
+    use crate::mock::{Faults, MockPort};
+
+    let faults = Faults {
+        protected_page: Some(1),
+        ..Default::default()
+    };
+    let port = MockPort::device(faults);
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &Probe::new());
+    programmer.write_memory_chunked(0x0800_0000, &[0xa5; 0xc00])?;
+    let mut image = Image::new();
+    image.push(0x0800_0000, [0x5a; 0xc00]);
+    let result = programmer.erase_for_image(&image);
+    assert!(matches!(result, Err(Error::EraseFailed(pages)) if pages == [1]));
+    // Pages around the protected one are erased one by one.
+    let flash = port.flash();
+    assert!(flash[..0x400].iter().all(|&b| b == 0xff));
+    assert!(flash[0x400..0x800].iter().all(|&b| b == 0xa5));
+    assert!(flash[0x800..0xc00].iter().all(|&b| b == 0xff));
+
+    let port = MockPort::device(Faults::default());
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &Probe::new());
+    let plan = programmer.erase_for_image(&image)?;
+    assert_eq!(plan.pages(), [0, 1, 2]);
+    Ok(())
+}
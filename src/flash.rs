@@ -0,0 +1,220 @@
+use crate::image::Image;
+use crate::{Error, ProgramOptions, Programmer, Progress, Result};
+use log::{debug, info};
+
+/// Progress of [`Programmer::flash()`], reported after each chunk written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlashProgress {
+    segment: usize,
+    written: usize,
+    total: usize,
+}
+
+impl FlashProgress {
+    /// Gets index of the segment being written.
+    pub fn segment(&self) -> usize {
+        self.segment
+    }
+
+    /// Gets bytes written so far, of all segments.
+    pub fn written(&self) -> usize {
+        self.written
+    }
+
+    /// Gets bytes of the whole image.
+    pub fn total(&self) -> usize {
+        self.total
+    }
+}
+
+/// Options for [`Programmer::flash()`]
+#[derive(Default)]
+pub struct FlashOptions {
+    erase: bool,
+    verify: bool,
+    progress: Option<Box<dyn FnMut(FlashProgress)>>,
+}
+
+impl FlashOptions {
+    /// Creates default [`FlashOptions`], which neither erase nor verify.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Gets whether the whole flash memory is erased before writing.
+    pub fn erase(&self) -> bool {
+        self.erase
+    }
+
+    /// Sets whether the whole flash memory is erased before writing.
+    pub fn set_erase(&mut self, erase: bool) {
+        self.erase = erase;
+    }
+
+    /// Gets whether each segment is verified after writing.
+    pub fn verify(&self) -> bool {
+        self.verify
+    }
+
+    /// Sets whether each segment is verified after writing.
+    pub fn set_verify(&mut self, verify: bool) {
+        self.verify = verify;
+    }
+
+    /// Sets callback reporting progress after each chunk written.
+    pub fn set_progress(&mut self, progress: impl FnMut(FlashProgress) + 'static) {
+        self.progress = Some(Box::new(progress));
+    }
+}
+
+impl std::fmt::Debug for FlashOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FlashOptions")
+            .field("erase", &self.erase)
+            .field("verify", &self.verify)
+            .field("progress", &self.progress.is_some())
+            .finish()
+    }
+}
+
+impl Programmer {
+    /// Flashes an image, writing each of its segments by successive WRITE
+    /// commands of up to 256 bytes.
+    ///
+    /// The whole flash memory is erased first if [`FlashOptions::erase()`],
+    /// see [`Self::erase_all()`]. Each segment is verified once written if
+    /// [`FlashOptions::verify()`], see [`Self::verify_image()`].
+    ///
+    /// Any failure while writing or verifying a segment is reported by
+    /// [`Error::FlashFailed`], telling the segment and the offset within it,
+    /// e.g. of the chunk refused or of the first byte mismatched.
+    ///
+    /// An estimate of the time it takes is logged beforehand, see
+    /// [`Self::estimate_program_time()`].
+    pub fn flash(&mut self, image: &Image, mut options: FlashOptions) -> Result<()> {
+        let mut program = ProgramOptions::new();
+        program.set_verify(options.verify);
+        self.log_estimate(image.len(), &program);
+        if options.erase {
+            info!("erasing");
+            self.erase_all()?;
+        }
+        let total = image.len();
+        let mut written = 0;
+        for (i, segment) in image.segments().iter().enumerate() {
+            let failed = |offset: usize, e: Error| Error::FlashFailed {
+                segment: i,
+                offset,
+                source: Box::new(e),
+            };
+            info!(
+                "writing {} bytes at {:#010x}",
+                segment.data().len(),
+                segment.address()
+            );
+            let before = written;
+            let mut report = |progress: Progress| {
+                if let Some(report) = options.progress.as_mut() {
+                    report(FlashProgress {
+                        segment: i,
+                        written: before + progress.done(),
+                        total,
+                    });
+                }
+            };
+            self.write_chunks(
+                segment.address(),
+                segment.data(),
+                |_, _| Ok(()),
+                Some(&mut report),
+            )
+            .map_err(|e| match e {
+                Error::PartialWrite { written, source } => failed(written, *source),
+                e => failed(0, e),
+            })?;
+            written += segment.data().len();
+            if options.verify {
+                self.verify_segment(segment.address(), segment.data())
+                    .map_err(|e| match e {
+                        Error::VerifyMismatch { address, .. } => {
+                            failed((address - segment.address()) as usize, e)
+                        }
+                        e => failed(0, e),
+                    })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Verifies a segment by [`Self::verify_image()`], reading it back to
+    /// find the first byte mismatched if it doesn't match.
+    fn verify_segment(&mut self, address: u32, data: &[u8]) -> Result<()> {
+        if self.verify_image(address, data)?.matched() {
+            return Ok(());
+        }
+        debug!("segment at {:#010x} mismatched, reading back", address);
+        self.compare_memory(address, data)
+    }
+}
+
+#[test]
+fn flashing() -> Result<()> {
+    use crate::Probe;
+    use crate::mock::{Faults, MockPort};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    let mut image = Image::new();
+    image.push(0x0800_0000, (0..600).map(|i| i as u8).collect::<Vec<_>>());
+    image.push(0x0800_1000, [0xde, 0xad, 0xbe, 0xef]);
+
+    let port = MockPort::device(Faults::default());
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &Probe::new());
+    let reports = Rc::new(RefCell::new(Vec::new()));
+    let mut options = FlashOptions::new();
+    options.set_erase(true);
+    options.set_verify(true);
+    options.set_progress({
+        let reports = reports.clone();
+        move |progress| reports.borrow_mut().push(progress.written())
+    });
+    programmer.flash(&image, options)?;
+    let flash = port.flash();
+    assert_eq!(&flash[..600], image.segments()[0].data());
+    assert_eq!(&flash[0x1000..0x1004], [0xde, 0xad, 0xbe, 0xef]);
+    assert_eq!(*reports.borrow(), [256, 512, 600, 604]);
+
+    // The third chunk of the first segment is refused.
+    let port = MockPort::device(Faults {
+        refuse_write_at: Some(0x0800_0200),
+        ..Faults::default()
+    });
+    let mut programmer = Programmer::attach(Box::new(port), &Probe::new());
+    let result = programmer.flash(&image, FlashOptions::new());
+    assert!(matches!(
+        result,
+        Err(Error::FlashFailed {
+            segment: 0,
+            offset: 0x200,
+            ..
+        })
+    ));
+
+    let port = MockPort::device(Faults {
+        corrupt_at: Some(0x0800_1002),
+        ..Faults::default()
+    });
+    let mut programmer = Programmer::attach(Box::new(port), &Probe::new());
+    let mut options = FlashOptions::new();
+    options.set_verify(true);
+    let result = programmer.flash(&image, options);
+    assert!(matches!(
+        result,
+        Err(Error::FlashFailed {
+            segment: 1,
+            offset: 2,
+            ..
+        })
+    ));
+    Ok(())
+}
@@ -0,0 +1,107 @@
+use crate::protocol::{Address, Command, Erase, ExtendedErase, Opcode, Reply, Sector, Size};
+use crate::{Byte, Slice};
+use binrw::BinWrite;
+use binrw::io::NoSeek;
+use binrw::meta::WriteEndian;
+
+/// Frame with a human-readable description, for transcripts of what is sent
+/// to the device
+///
+/// A transcript reads like `→ GET opcode [00 ff]`, `← ACK [79]`, so protocol
+/// issues can be debugged without decoding hex by hand.
+pub(crate) trait Annotate: for<'b> BinWrite<Args<'b> = ()> + WriteEndian {
+    /// Describes the frame, e.g. `GET opcode` or `address 0x08000000`.
+    fn label(&self) -> String;
+
+    /// Describes the frame along with its bytes on the wire.
+    fn annotate(&self) -> String {
+        let mut bytes = Vec::new();
+        match self.write(&mut NoSeek::new(&mut bytes)) {
+            Ok(()) => format!("{} [{}]", self.label(), hex(&bytes)),
+            Err(_) => self.label(),
+        }
+    }
+}
+
+/// Bytes shown for each frame at most, since data frames are long.
+const MAX_SHOWN: usize = 16;
+
+fn hex(bytes: &[u8]) -> String {
+    let shown = bytes[..bytes.len().min(MAX_SHOWN)]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join(" ");
+    if bytes.len() > MAX_SHOWN {
+        format!("{} …", shown)
+    } else {
+        shown
+    }
+}
+
+/// Describes a reply along with its byte on the wire.
+pub(crate) fn annotate_reply(reply: Reply) -> &'static str {
+    match reply {
+        Reply::Ack => "ACK [79]",
+        Reply::NAck => "NACK [1f]",
+    }
+}
+
+impl Annotate for Opcode {
+    fn label(&self) -> String {
+        format!("{} opcode", self)
+    }
+}
+
+impl Annotate for Address {
+    fn label(&self) -> String {
+        format!("address {:#010x}", self.as_u32())
+    }
+}
+
+impl Annotate for Size {
+    fn label(&self) -> String {
+        format!("size {}", usize::from(*self))
+    }
+}
+
+impl Annotate for Slice<'_, Byte> {
+    fn label(&self) -> String {
+        format!("data of {} bytes", self.len())
+    }
+}
+
+impl Annotate for Slice<'_, Sector> {
+    fn label(&self) -> String {
+        format!("{} sectors", self.len())
+    }
+}
+
+impl Annotate for Erase<'_> {
+    fn label(&self) -> String {
+        match self {
+            Self::Global => "global erase".into(),
+            Self::Specific(pages) => format!("erase of {} pages", pages.len()),
+        }
+    }
+}
+
+impl Annotate for ExtendedErase<'_> {
+    fn label(&self) -> String {
+        match self {
+            Self::Global => "global erase".into(),
+            Self::Bank1 => "bank 1 erase".into(),
+            Self::Bank2 => "bank 2 erase".into(),
+            Self::Specific(pages) => format!("erase of {} pages", pages.len()),
+        }
+    }
+}
+
+impl Annotate for Command<'_> {
+    fn label(&self) -> String {
+        match self.opcode() {
+            Some(opcode) => format!("{} command", opcode),
+            None => "synchronization".into(),
+        }
+    }
+}
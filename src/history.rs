@@ -0,0 +1,133 @@
+use crate::{Command, Erase, Error, ExtendedErase, Result};
+use std::time::SystemTime;
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+/// History of operations done by a [`crate::Programmer`]
+///
+/// See [`crate::Programmer::enable_history()`].
+pub type ProgrammingHistory = Vec<HistoryEntry>;
+
+/// An operation recorded in [`ProgrammingHistory`]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct HistoryEntry {
+    timestamp: SystemTime,
+    operation: HistoryOperation,
+    result: HistoryResult,
+}
+
+impl HistoryEntry {
+    /// Creates an entry of an operation finished just now.
+    pub fn new(operation: HistoryOperation, result: HistoryResult) -> Self {
+        Self {
+            timestamp: SystemTime::now(),
+            operation,
+            result,
+        }
+    }
+
+    /// Time when the operation finished.
+    pub fn timestamp(&self) -> SystemTime {
+        self.timestamp
+    }
+
+    /// The operation.
+    pub fn operation(&self) -> &HistoryOperation {
+        &self.operation
+    }
+
+    /// Result of the operation.
+    pub fn result(&self) -> &HistoryResult {
+        &self.result
+    }
+}
+
+/// Operation in [`HistoryEntry`], which corresponds to a [`Command`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum HistoryOperation {
+    Get,
+    Version,
+    Id,
+    Read {
+        address: u32,
+        size: usize,
+    },
+    Go {
+        address: u32,
+    },
+    Write {
+        address: u32,
+        size: usize,
+    },
+    /// Erasure of specific pages, or global erasure if `pages` is [`None`].
+    Erase {
+        pages: Option<Vec<u16>>,
+    },
+    EraseBank {
+        bank: u8,
+    },
+    WriteProtect,
+    WriteUnprotect,
+    ReadProtect,
+    ReadUnprotect,
+    Synchronize,
+}
+
+impl From<&Command<'_>> for HistoryOperation {
+    fn from(value: &Command<'_>) -> Self {
+        match value {
+            Command::Get() => Self::Get,
+            Command::Version() => Self::Version,
+            Command::Id() => Self::Id,
+            Command::Read { address, size } => Self::Read {
+                address: address.as_u32(),
+                size: (*size).into(),
+            },
+            Command::Go(address) => Self::Go {
+                address: address.as_u32(),
+            },
+            Command::Write { address, data } => Self::Write {
+                address: address.as_u32(),
+                size: data.len(),
+            },
+            Command::Erase(Erase::Global) => Self::Erase { pages: None },
+            Command::Erase(Erase::Specific(pages)) => Self::Erase {
+                pages: Some(pages.iter().map(|&page| page.into()).collect()),
+            },
+            Command::ExtendedErase(ExtendedErase::Global) => Self::Erase { pages: None },
+            Command::ExtendedErase(ExtendedErase::Bank1) => Self::EraseBank { bank: 1 },
+            Command::ExtendedErase(ExtendedErase::Bank2) => Self::EraseBank { bank: 2 },
+            Command::ExtendedErase(ExtendedErase::Specific(pages)) => Self::Erase {
+                pages: Some(pages.to_vec()),
+            },
+            Command::WriteProtect(..) => Self::WriteProtect,
+            Command::WriteUnprotect() => Self::WriteUnprotect,
+            Command::ReadProtect() => Self::ReadProtect,
+            Command::ReadUnprotect() => Self::ReadUnprotect,
+            Command::Synchronize => Self::Synchronize,
+        }
+    }
+}
+
+/// Result in [`HistoryEntry`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum HistoryResult {
+    Ok,
+    NAck,
+    /// Any other error, kept as its message.
+    Failed(String),
+}
+
+impl<T> From<&Result<T>> for HistoryResult {
+    fn from(value: &Result<T>) -> Self {
+        match value {
+            Ok(_) => Self::Ok,
+            Err(Error::NAck) => Self::NAck,
+            Err(e) => Self::Failed(e.to_string()),
+        }
+    }
+}
@@ -0,0 +1,377 @@
+//! Firmware images to be programmed
+//!
+//! An image consists of segments of data at absolute addresses, which may
+//! leave gaps in between, e.g. an application and its configuration. Images
+//! are loaded from Intel HEX by [`Image::from_ihex()`], Motorola S-records by
+//! [`Image::from_srec()`], or raw binaries by [`Image::from_bin()`].
+
+use std::io::{BufRead, BufReader, Read};
+
+/// Error loading an [`Image`]
+#[derive(Debug)]
+pub enum ImageError {
+    /// A record is malformed, e.g. lacking the start code or with odd hex
+    /// digits, at a line counted from 1.
+    Malformed {
+        line: usize,
+    },
+    /// The checksum of a record doesn't match, at a line counted from 1.
+    Checksum {
+        line: usize,
+    },
+    /// A record type is not known, at a line counted from 1.
+    UnknownRecord {
+        line: usize,
+        kind: u8,
+    },
+    /// The input ends without an end-of-file or termination record.
+    MissingEof,
+    /// Data extends beyond the 32-bit address space.
+    OutOfRange {
+        address: u32,
+        size: usize,
+    },
+    Io(std::io::Error),
+}
+
+impl ImageError {
+    pub fn is_malformed(&self) -> bool {
+        matches!(self, Self::Malformed { .. })
+    }
+    pub fn is_checksum(&self) -> bool {
+        matches!(self, Self::Checksum { .. })
+    }
+    pub fn is_unknown_record(&self) -> bool {
+        matches!(self, Self::UnknownRecord { .. })
+    }
+    pub fn is_missing_eof(&self) -> bool {
+        matches!(self, Self::MissingEof)
+    }
+    pub fn is_out_of_range(&self) -> bool {
+        matches!(self, Self::OutOfRange { .. })
+    }
+
+    pub fn is_io_error(&self) -> bool {
+        matches!(self, Self::Io(..))
+    }
+    pub fn as_io_error(&self) -> Option<&std::io::Error> {
+        match self {
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+    pub fn into_io_error(self) -> Option<std::io::Error> {
+        match self {
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for ImageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Malformed { line } => write!(f, "malformed record at line {}", line),
+            Self::Checksum { line } => write!(f, "checksum mismatch at line {}", line),
+            Self::UnknownRecord { line, kind } => {
+                write!(f, "unknown record type {:#04x} at line {}", kind, line)
+            }
+            Self::MissingEof => write!(f, "missing end-of-file record"),
+            Self::OutOfRange { address, size } => {
+                write!(f, "{} bytes at {:#010x} are out of range", size, address)
+            }
+            Self::Io(e) => write!(f, "io error: {}", e),
+        }
+    }
+}
+
+impl From<std::io::Error> for ImageError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl std::error::Error for ImageError {}
+
+/// Data at an absolute address of an [`Image`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    address: u32,
+    data: Vec<u8>,
+}
+
+impl Segment {
+    /// Creates a [`Segment`].
+    pub fn new(address: u32, data: impl Into<Vec<u8>>) -> Self {
+        Self {
+            address,
+            data: data.into(),
+        }
+    }
+
+    /// Gets address of the first byte.
+    pub fn address(&self) -> u32 {
+        self.address
+    }
+
+    /// Gets data of the segment.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Gets address right after the last byte, which may lie beyond 32-bit
+    /// address space.
+    pub fn end(&self) -> u64 {
+        self.address as u64 + self.data.len() as u64
+    }
+}
+
+/// Firmware image made of [`Segment`]s
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    segments: Vec<Segment>,
+}
+
+impl Image {
+    /// Creates an empty [`Image`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a segment of data at `address`.
+    pub fn push(&mut self, address: u32, data: impl Into<Vec<u8>>) {
+        self.segments.push(Segment::new(address, data));
+    }
+
+    /// Gets segments in the order they were added.
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// Parses an image in Intel HEX.
+    ///
+    /// Data records at consecutive addresses are merged into one segment, and
+    /// extended segment and linear address records are taken into account.
+    /// Start address records are ignored, as is anything after the
+    /// end-of-file record.
+    pub fn from_ihex(reader: impl Read) -> Result<Self, ImageError> {
+        let mut image = Self::new();
+        // Base of addresses, set by extended address records.
+        let mut base = 0u32;
+        for (i, line) in BufReader::new(reader).lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let number = i + 1;
+            let record = line
+                .strip_prefix(':')
+                .and_then(decode_hex)
+                .filter(|record| record.len() >= 5)
+                .ok_or(ImageError::Malformed { line: number })?;
+            if record.iter().fold(0u8, |a, b| a.wrapping_add(*b)) != 0 {
+                return Err(ImageError::Checksum { line: number });
+            }
+            let (header, rest) = record.split_at(4);
+            let data = &rest[..rest.len() - 1];
+            if data.len() != header[0] as usize {
+                return Err(ImageError::Malformed { line: number });
+            }
+            let offset = u16::from_be_bytes([header[1], header[2]]);
+            match header[3] {
+                0x00 => image.extend(base.wrapping_add(offset as u32), data),
+                0x01 => return Ok(image),
+                0x02 | 0x04 => {
+                    let value = <[u8; 2]>::try_from(data)
+                        .map_err(|_| ImageError::Malformed { line: number })?;
+                    let value = u16::from_be_bytes(value) as u32;
+                    base = if header[3] == 0x02 {
+                        value << 4
+                    } else {
+                        value << 16
+                    };
+                }
+                0x03 | 0x05 => {}
+                kind => return Err(ImageError::UnknownRecord { line: number, kind }),
+            }
+        }
+        Err(ImageError::MissingEof)
+    }
+
+    /// Parses an image in Motorola S-records, i.e. S19, S28 or S37.
+    ///
+    /// Data records of 16, 24 and 32-bit addresses at consecutive addresses
+    /// are merged into one segment. Header and count records are ignored, as
+    /// is anything after the termination record.
+    pub fn from_srec(reader: impl Read) -> Result<Self, ImageError> {
+        let mut image = Self::new();
+        for (i, line) in BufReader::new(reader).lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let number = i + 1;
+            let (kind, record) = line
+                .strip_prefix('S')
+                .and_then(|rest| rest.split_at_checked(1))
+                .ok_or(ImageError::Malformed { line: number })?;
+            let kind = kind
+                .parse::<u8>()
+                .map_err(|_| ImageError::Malformed { line: number })?;
+            let record = decode_hex(record)
+                .filter(|record| record.len() >= 2 && record[0] as usize == record.len() - 1)
+                .ok_or(ImageError::Malformed { line: number })?;
+            if record.iter().fold(0u8, |a, b| a.wrapping_add(*b)) != 0xff {
+                return Err(ImageError::Checksum { line: number });
+            }
+            let width = match kind {
+                0 | 1 | 5 | 9 => 2,
+                2 | 6 | 8 => 3,
+                3 | 7 => 4,
+                kind => return Err(ImageError::UnknownRecord { line: number, kind }),
+            };
+            // Count, address and checksum
+            if record.len() < width + 2 {
+                return Err(ImageError::Malformed { line: number });
+            }
+            let (address, data) = record[1..record.len() - 1].split_at(width);
+            let address = address.iter().fold(0u32, |a, b| a << 8 | *b as u32);
+            match kind {
+                1..=3 => image.extend(address, data),
+                7..=9 => return Ok(image),
+                _ => {}
+            }
+        }
+        Err(ImageError::MissingEof)
+    }
+
+    /// Reads a raw binary, e.g. dumped by `objcopy -O binary`, into a single
+    /// segment at `base`.
+    ///
+    /// An empty input results in an empty image.
+    pub fn from_bin(mut reader: impl Read, base: u32) -> Result<Self, ImageError> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        if base as u64 + data.len() as u64 > u32::MAX as u64 + 1 {
+            return Err(ImageError::OutOfRange {
+                address: base,
+                size: data.len(),
+            });
+        }
+        let mut image = Self::new();
+        if !data.is_empty() {
+            image.push(base, data);
+        }
+        Ok(image)
+    }
+
+    /// Appends data at `address`, merging it into the last segment if it
+    /// follows right after.
+    fn extend(&mut self, address: u32, data: &[u8]) {
+        match self.segments.last_mut() {
+            Some(last) if last.end() == address as u64 => last.data.extend_from_slice(data),
+            _ => self.push(address, data),
+        }
+    }
+
+    /// Gets total bytes of all segments.
+    pub fn len(&self) -> usize {
+        self.segments.iter().map(|s| s.data.len()).sum()
+    }
+
+    /// Checks whether the image holds no data at all.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Decodes hex digits of a record after its start code.
+fn decode_hex(digits: &str) -> Option<Vec<u8>> {
+    let digits = digits.as_bytes();
+    if !digits.len().is_multiple_of(2) {
+        return None;
+    }
+    digits
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+        .collect()
+}
+
+#[test]
+fn parsing_ihex() -> Result<(), ImageError> {
+    let hex = "\
+:0400000001020304F2
+:02000004080AE8
+:040000000A0B0C0DCE
+:040004000E0F1011BA
+:0400000500000000F7
+:00000001FF
+";
+    let image = Image::from_ihex(hex.as_bytes())?;
+    assert_eq!(
+        image.segments(),
+        [
+            Segment::new(0x0000_0000, [0x01, 0x02, 0x03, 0x04]),
+            Segment::new(
+                0x080a_0000,
+                [0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f, 0x10, 0x11]
+            ),
+        ]
+    );
+
+    let corrupt = hex.replace(":0400000001020304F2", ":0400000001020305F2");
+    let result = Image::from_ihex(corrupt.as_bytes());
+    assert!(matches!(result, Err(ImageError::Checksum { line: 1 })));
+
+    let truncated = hex.replace(":00000001FF\n", "");
+    let result = Image::from_ihex(truncated.as_bytes());
+    assert!(result.is_err_and(|e| e.is_missing_eof()));
+    Ok(())
+}
+
+#[test]
+fn reading_bin() -> Result<(), ImageError> {
+    let bin = (0..1024).map(|i| i as u8).collect::<Vec<_>>();
+    let image = Image::from_bin(bin.as_slice(), 0x0800_0000)?;
+    assert_eq!(image.segments().len(), 1);
+    assert_eq!(image.segments()[0].address(), 0x0800_0000);
+    assert_eq!(image.segments()[0].data(), bin);
+    assert_eq!(image.len(), 1024);
+
+    let result = Image::from_bin(bin.as_slice(), 0xffff_ff00);
+    assert!(result.is_err_and(|e| e.is_out_of_range()));
+    Ok(())
+}
+
+#[test]
+fn parsing_srec() -> Result<(), ImageError> {
+    let srec = "\
+S00F000068656C6C6F202020202000003C
+S3090800000001020304E4
+S309080000040A0B0C0DBC
+S30708001000DEAD55
+S70508000000F2
+";
+    let image = Image::from_srec(srec.as_bytes())?;
+    assert_eq!(
+        image.segments(),
+        [
+            Segment::new(
+                0x0800_0000,
+                [0x01, 0x02, 0x03, 0x04, 0x0a, 0x0b, 0x0c, 0x0d]
+            ),
+            Segment::new(0x0800_1000, [0xde, 0xad]),
+        ]
+    );
+
+    let corrupt = srec.replace("S30708001000DEAD55", "S30708001000DEAE55");
+    let result = Image::from_srec(corrupt.as_bytes());
+    assert!(matches!(result, Err(ImageError::Checksum { line: 4 })));
+
+    let miscounted = srec.replace("S30708001000DEAD55", "S30808001000DEAD55");
+    let result = Image::from_srec(miscounted.as_bytes());
+    assert!(matches!(result, Err(ImageError::Malformed { line: 4 })));
+    Ok(())
+}
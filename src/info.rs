@@ -0,0 +1,59 @@
+use crate::device::Family;
+use crate::{Bootloader, Id, Programmer, Result, Version};
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+/// Information aggregated from GET, GET_VERSION and GET_ID
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct DeviceInfoReport {
+    port: Option<String>,
+    bootloader: Bootloader,
+    version: Version,
+    id: Id,
+    family: Option<Family>,
+}
+
+impl DeviceInfoReport {
+    /// Gets name of the port the device is on.
+    pub fn port(&self) -> Option<&str> {
+        self.port.as_deref()
+    }
+
+    /// Gets bootloader information.
+    pub fn bootloader(&self) -> &Bootloader {
+        &self.bootloader
+    }
+
+    /// Gets bootloader version and option bytes.
+    pub fn version(&self) -> &Version {
+        &self.version
+    }
+
+    /// Gets chip ID.
+    pub fn id(&self) -> &Id {
+        &self.id
+    }
+
+    /// Gets family decoded from chip ID, see [`Id::family()`].
+    pub fn family(&self) -> Option<Family> {
+        self.family
+    }
+}
+
+impl Programmer {
+    /// Reads bootloader information, version and chip ID at once.
+    pub fn read_info_report(&mut self) -> Result<DeviceInfoReport> {
+        let bootloader = self.read_bootloader()?;
+        let version = self.read_version()?;
+        let id = self.read_id()?;
+        Ok(DeviceInfoReport {
+            port: self.port_name().map(str::to_owned),
+            bootloader,
+            version,
+            family: id.family(),
+            id,
+        })
+    }
+}
@@ -16,14 +16,40 @@
 //! [license badge]: https://img.shields.io/github/license/yapu-rs/yapu?style=flat
 //! [crates.io version badge]: https://img.shields.io/crates/v/yapu?style=flat
 
+mod address;
+#[cfg(feature = "async")]
+mod asynchronous;
+mod crc;
+pub mod device;
+mod discovery;
+mod erase;
+mod flash;
+mod frame;
+mod history;
+pub mod image;
+mod info;
+#[cfg(test)]
+mod mock;
+mod pipeline;
 mod probe;
+mod program;
+mod progress;
+mod protect;
 mod protocol;
+mod reader;
+mod recording;
+mod special;
+mod support;
+mod timeout;
+mod verify;
+mod wire;
 
-pub use probe::{Baudrate, Identify};
+pub use probe::{Baudrate, Identify, ParseSignalError};
 pub use probe::{Probe, ProbeBuilder, Signal, SignalScheme, SignalSchemeBuilder};
 
 // Common requests and responses in the protocol
-pub use protocol::{Address, Command, Opcode, Reply, Size};
+pub use protocol::checksum;
+pub use protocol::{Address, AddressWidth, Command, Opcode, Reply, Size};
 pub use protocol::{Bootloader, Id, Version};
 pub use protocol::{Erase, ExtendedErase};
 
@@ -33,19 +59,114 @@ pub use protocol::{
 };
 pub use protocol::{Slice, SliceItem};
 
+pub use address::{AddressSpace, TargetAddress};
+pub use crc::stm32_crc32;
+pub use discovery::{DiscoverOptions, Discovered};
+pub use erase::{Bank, ErasePlan};
+pub use flash::{FlashOptions, FlashProgress};
+pub use info::DeviceInfoReport;
+pub use pipeline::{Pipeline, PipelineReport, PipelineStep};
+pub use program::{
+    BatchPolicy, BatchSummary, ProgramOptions, ProgramResult, Trailer, TrailerContent,
+};
+pub use progress::Progress;
+pub use reader::FlashReader;
+pub use recording::RecordingHandle;
+pub use support::ProtocolSupport;
+pub use verify::{VerifyMethod, VerifyMode, VerifyReport};
+pub use wire::WIRE_TARGET;
+
+pub use history::{HistoryEntry, HistoryOperation, HistoryResult, ProgrammingHistory};
+
 use binrw::io::NoSeek;
 use binrw::meta::{ReadEndian, WriteEndian};
 use binrw::{BinRead, BinWrite};
-use log::trace;
+use frame::Annotate;
+use log::{debug, trace, warn};
 use serialport::ClearBuffer;
 pub use serialport::SerialPort;
-use serialport::{DataBits, FlowControl, Parity, StopBits};
+use serialport::{DataBits, FlowControl};
+pub use serialport::{Parity, StopBits};
+use std::hash::Hasher;
+use std::io::Read;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use timeout::TimeoutGuard;
 
 /// Error
 #[derive(Debug)]
 pub enum Error {
     NAck,
-    Unidentified,
+    /// The device cannot be identified within `attempts`, and the last one
+    /// fails with `last`, e.g. a timeout if nothing responds at all.
+    ///
+    /// `timed_out` tells whether it gave up on
+    /// [`Probe::max_identify_time()`] rather than running out of attempts.
+    Unidentified {
+        attempts: usize,
+        last: Option<Box<Error>>,
+        timed_out: bool,
+    },
+    /// The device cannot be identified, but responds with the boot signal
+    /// inverted, so its polarity is likely reversed.
+    BootReversed,
+    Unsupported(Opcode),
+    /// The port opens but fails on I/O, which is common for Bluetooth and
+    /// some virtual serial ports.
+    Unusable(std::io::Error),
+    /// Reading a region fails midway, after `read` bytes.
+    PartialRead {
+        read: usize,
+        source: Box<Error>,
+    },
+    /// Writing a region fails midway, after `written` bytes.
+    PartialWrite {
+        written: usize,
+        source: Box<Error>,
+    },
+    /// An address is not aligned as the device requires.
+    Alignment {
+        address: u32,
+    },
+    /// A region lies outside of the memory it's meant for.
+    OutOfRange {
+        address: u32,
+        size: usize,
+    },
+    /// An address cannot be targeted, e.g. system memory for writing, or a
+    /// space whose location is unknown for the device.
+    InvalidTarget(TargetAddress),
+    /// A register is not known for the device.
+    UnknownRegister(String),
+    /// An operation is aborted on purpose, e.g. after another device in the
+    /// same batch failed.
+    Aborted,
+    /// A configuration cannot be parsed or serialized.
+    Config(Box<dyn std::error::Error + Send + Sync>),
+    /// The flash layout of a product ID is not known, so its pages cannot be
+    /// planned.
+    UnknownLayout(u16),
+    /// Pages cannot be erased, e.g. because they are write-protected.
+    EraseFailed(Vec<u16>),
+    /// A command modifying the device is refused in safe mode, see
+    /// [`Probe::safe_mode()`].
+    SafeMode(Opcode),
+    /// Memory read back differs from what was written, first at `address`.
+    VerifyMismatch {
+        address: u32,
+        expected: u8,
+        found: u8,
+    },
+    /// Flashing an image fails at `offset` within a segment, counted from 0.
+    FlashFailed {
+        segment: usize,
+        offset: usize,
+        source: Box<Error>,
+    },
+    /// An option byte at `address` is not followed by its complement.
+    OptionBytesComplement {
+        address: u32,
+    },
     Protocol(protocol::Error),
     Io(std::io::Error),
     Serial(serialport::Error),
@@ -56,8 +177,75 @@ impl Error {
     pub fn is_nack(&self) -> bool {
         matches!(self, Self::NAck)
     }
+    /// Checks whether the device cannot be identified, including when it's
+    /// likely due to reversed boot signal.
     pub fn is_unidentified(&self) -> bool {
-        matches!(self, Self::Unidentified)
+        matches!(self, Self::Unidentified { .. } | Self::BootReversed)
+    }
+    pub fn is_boot_reversed(&self) -> bool {
+        matches!(self, Self::BootReversed)
+    }
+    pub fn is_unsupported(&self) -> bool {
+        matches!(self, Self::Unsupported(..))
+    }
+    pub fn is_unusable(&self) -> bool {
+        matches!(self, Self::Unusable(..))
+    }
+    pub fn is_partial_read(&self) -> bool {
+        matches!(self, Self::PartialRead { .. })
+    }
+    pub fn is_partial_write(&self) -> bool {
+        matches!(self, Self::PartialWrite { .. })
+    }
+    pub fn is_alignment(&self) -> bool {
+        matches!(self, Self::Alignment { .. })
+    }
+    pub fn is_out_of_range(&self) -> bool {
+        matches!(self, Self::OutOfRange { .. })
+    }
+    pub fn is_invalid_target(&self) -> bool {
+        matches!(self, Self::InvalidTarget(..))
+    }
+    pub fn is_unknown_register(&self) -> bool {
+        matches!(self, Self::UnknownRegister(..))
+    }
+    pub fn is_aborted(&self) -> bool {
+        matches!(self, Self::Aborted)
+    }
+    pub fn is_config_error(&self) -> bool {
+        matches!(self, Self::Config(..))
+    }
+    pub fn is_unknown_layout(&self) -> bool {
+        matches!(self, Self::UnknownLayout(..))
+    }
+    pub fn is_erase_failed(&self) -> bool {
+        matches!(self, Self::EraseFailed(..))
+    }
+    pub fn is_safe_mode(&self) -> bool {
+        matches!(self, Self::SafeMode(..))
+    }
+    pub fn is_verify_mismatch(&self) -> bool {
+        matches!(self, Self::VerifyMismatch { .. })
+    }
+    pub fn is_flash_failed(&self) -> bool {
+        matches!(self, Self::FlashFailed { .. })
+    }
+    pub fn is_option_bytes_complement(&self) -> bool {
+        matches!(self, Self::OptionBytesComplement { .. })
+    }
+
+    /// Converts any I/O failure other than a timeout into [`Self::Unusable`],
+    /// since the port itself is broken rather than the device being silent.
+    fn into_port_error(self) -> Self {
+        match self {
+            Self::Serial(e) => Self::Unusable(e.into()),
+            Self::Io(e) | Self::Frame(binrw::Error::Io(e))
+                if e.kind() != std::io::ErrorKind::TimedOut =>
+            {
+                Self::Unusable(e)
+            }
+            other => other,
+        }
     }
 
     pub fn is_protocol_conversion(&self) -> bool {
@@ -129,7 +317,73 @@ impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::NAck => write!(f, "negative ack"),
-            Self::Unidentified => write!(f, "cannot identify device"),
+            Self::Unidentified {
+                attempts,
+                last,
+                timed_out,
+            } => {
+                write!(f, "cannot identify device")?;
+                if *timed_out {
+                    write!(f, " in time")?;
+                }
+                write!(f, " after {} attempts", attempts)?;
+                match last {
+                    Some(last) => write!(f, ": {}", last),
+                    None => Ok(()),
+                }
+            }
+            Self::BootReversed => write!(
+                f,
+                "cannot identify device, but it responds with boot signal inverted \
+                 (try inverting --boot)"
+            ),
+            Self::Unsupported(opcode) => write!(f, "unsupported by bootloader: {}", opcode),
+            Self::Unusable(e) => write!(f, "port is unusable: {}", e),
+            Self::PartialRead { read, source } => {
+                write!(f, "read fails after {} bytes: {}", read, source)
+            }
+            Self::PartialWrite { written, source } => {
+                write!(f, "write fails after {} bytes: {}", written, source)
+            }
+            Self::Alignment { address } => write!(f, "{:#010x} is misaligned", address),
+            Self::OutOfRange { address, size } => {
+                write!(f, "{} bytes at {:#010x} are out of range", size, address)
+            }
+            Self::InvalidTarget(target) => write!(
+                f,
+                "cannot target {:?} at offset {:#x}",
+                target.space(),
+                target.offset(),
+            ),
+            Self::UnknownRegister(name) => write!(f, "unknown register: {}", name),
+            Self::Aborted => write!(f, "aborted"),
+            Self::Config(e) => write!(f, "config error: {}", e),
+            Self::UnknownLayout(pid) => write!(f, "unknown flash layout of product {:#05x}", pid),
+            Self::EraseFailed(pages) => write!(f, "cannot erase pages: {:?}", pages),
+            Self::SafeMode(opcode) => write!(f, "refused in safe mode: {}", opcode),
+            Self::VerifyMismatch {
+                address,
+                expected,
+                found,
+            } => write!(
+                f,
+                "mismatch at {:#010x}: expected {:#04x}, found {:#04x}",
+                address, expected, found
+            ),
+            Self::FlashFailed {
+                segment,
+                offset,
+                source,
+            } => write!(
+                f,
+                "flashing fails at offset {:#x} of segment {}: {}",
+                offset, segment, source
+            ),
+            Self::OptionBytesComplement { address } => write!(
+                f,
+                "option byte at {:#010x} is not followed by its complement",
+                address
+            ),
             Self::Protocol(e) => write!(f, "protocol conversion error: {}", e),
             Self::Io(e) => write!(f, "io error: {}", e),
             Self::Serial(e) => write!(f, "serial error: {}", e),
@@ -170,7 +424,17 @@ type Result<T> = std::result::Result<T, Error>;
 #[derive(Debug)]
 pub struct Programmer {
     port: Box<dyn SerialPort>,
-    probe: Probe,
+    // Shared, so devices of a batch don't each hold a copy.
+    probe: Arc<Probe>,
+    bootloader: Option<Bootloader>,
+    // Known once identified by `identify_chip()`.
+    device: Option<device::DeviceInfo>,
+    last_active: Instant,
+    history: Option<ProgrammingHistory>,
+    // Cached, since a disconnected port may not report its name anymore.
+    port_name: Option<String>,
+    // Set by `with_timeout()`, overriding timeouts of the probe for replies.
+    timeout_override: Option<Duration>,
 }
 
 impl Programmer {
@@ -189,63 +453,202 @@ impl Programmer {
 
     /// Opens a serial port by its name and configures it according to a probe.
     pub fn port(path: impl AsRef<str>, probe: &Probe) -> Result<Box<dyn SerialPort>> {
-        let port = serialport::new(path.as_ref(), probe.baudrate())
+        Ok(Self::port_builder(path.as_ref(), probe).open()?)
+    }
+
+    /// Prepares settings of a serial port according to a probe.
+    fn port_builder(path: &str, probe: &Probe) -> serialport::SerialPortBuilder {
+        serialport::new(path, probe.baudrate())
             .data_bits(DataBits::Eight)
-            .parity(Parity::Even)
-            .stop_bits(StopBits::One)
+            .parity(probe.parity())
+            .stop_bits(probe.stop_bits())
             .flow_control(FlowControl::None)
             .timeout(probe.timeout())
-            .open()?;
-        Ok(port)
     }
 
     /// Creates a programmer from an existing serial port without handshaking.
     pub fn attach(port: Box<dyn SerialPort>, probe: &Probe) -> Self {
+        Self::attach_shared(port, Arc::new(probe.clone()))
+    }
+
+    /// Creates a programmer from an existing serial port without handshaking,
+    /// sharing a probe rather than copying it.
+    pub fn attach_shared(port: Box<dyn SerialPort>, probe: Arc<Probe>) -> Self {
         Self {
+            port_name: port.name(),
             port,
-            probe: probe.clone(),
+            probe,
+            bootloader: None,
+            device: None,
+            last_active: Instant::now(),
+            history: None,
+            timeout_override: None,
         }
     }
 
+    /// Creates a programmer from an existing serial port like
+    /// [`Self::attach()`], then synchronizes with the device, see
+    /// [`Self::synchronize()`].
+    ///
+    /// No signal is touched, so the device must already be waiting in its
+    /// bootloader, e.g. after a reset sequence of the caller's own.
+    pub fn attach_synchronized(port: Box<dyn SerialPort>, probe: &Probe) -> Result<Self> {
+        let mut programmer = Self::attach(port, probe);
+        programmer.synchronize()?;
+        Ok(programmer)
+    }
+
     /// Creates a programmer from a port name and tries to identify.
     pub fn open(path: impl AsRef<str>, probe: &Probe) -> Result<Self> {
+        Self::open_with(path, &ProgrammerConfig::new(probe.clone()))
+    }
+
+    /// Creates a programmer from a port name like [`Self::open()`], sharing a
+    /// probe rather than copying it, e.g. among many devices of a batch.
+    pub fn open_shared(path: impl AsRef<str>, probe: Arc<Probe>) -> Result<Self> {
+        Self::open_with(path, &ProgrammerConfig::shared(probe))
+    }
+
+    /// Creates a programmer from a port name and a configuration derived from
+    /// another programmer, and tries to identify.
+    ///
+    /// Device information cached in the configuration is reused rather than
+    /// read again from the device.
+    pub fn open_with(path: impl AsRef<str>, config: &ProgrammerConfig) -> Result<Self> {
+        let probe = config.probe();
         let port = Self::port(path.as_ref(), probe)?;
-        let mut programmer = Self {
-            port,
-            probe: probe.clone(),
+        let mut programmer = Self::attach_shared(port, config.probe.clone());
+        programmer.bootloader = config.bootloader().cloned();
+        programmer.device = config.device();
+        programmer.port_name = Some(path.as_ref().to_owned());
+        programmer.identify()?;
+        Ok(programmer)
+    }
+
+    /// Baudrates tried by [`Self::autobaud()`] unless given, from the most
+    /// common.
+    pub const AUTOBAUD_CANDIDATES: &[Baudrate] = &[115_200, 57_600, 230_400, 9_600];
+
+    /// Opens a port and tries to identify the device at each baudrate of
+    /// `candidates` in turn, or of [`Self::AUTOBAUD_CANDIDATES`] if empty.
+    ///
+    /// The device is reset before each attempt, see [`Self::open()`]. The
+    /// programmer returned has the probe updated with the baudrate found, as
+    /// reported by the port, see [`Self::actual_baudrate()`].
+    pub fn autobaud(path: impl AsRef<str>, probe: &Probe, candidates: &[Baudrate]) -> Result<Self> {
+        let port = Self::port(path.as_ref(), probe)?;
+        let mut programmer = Self::autobaud_attached(port, probe, candidates)?;
+        programmer.port_name = Some(path.as_ref().to_owned());
+        Ok(programmer)
+    }
+
+    /// Tries to identify the device on an existing port like
+    /// [`Self::autobaud()`].
+    pub fn autobaud_attached(
+        port: Box<dyn SerialPort>,
+        probe: &Probe,
+        candidates: &[Baudrate],
+    ) -> Result<Self> {
+        let candidates = match candidates {
+            [] => Self::AUTOBAUD_CANDIDATES,
+            candidates => candidates,
         };
-        match probe.identify() {
-            Identify::Handshake => {
-                programmer.identify()?;
-            }
-            Identify::Get => {
-                programmer.send_command(Command::Get())?;
+        let mut programmer = Self::attach(port, probe);
+        let mut attempts = 0;
+        let mut last = None;
+        let mut timed_out = false;
+        for &baudrate in candidates {
+            programmer.port.set_baud_rate(baudrate)?;
+            programmer.port.clear(ClearBuffer::All)?;
+            // Adapters may silently clamp to rates they support, so the
+            // candidate isn't necessarily the one tried.
+            let baudrate = match programmer.check_baudrate(baudrate) {
+                Ok(actual) => actual,
+                Err(e) => {
+                    debug!("cannot query baudrate: {}", e);
+                    baudrate
+                }
+            };
+            match programmer.identify() {
+                Ok(()) => {
+                    debug!("identified at {} baud", baudrate);
+                    Arc::make_mut(&mut programmer.probe).set_baudrate(baudrate);
+                    return Ok(programmer);
+                }
+                // The device responds, so other baudrates won't help.
+                Err(e) if e.is_unusable() || e.is_boot_reversed() => return Err(e),
+                Err(Error::Unidentified {
+                    attempts: n,
+                    last: e,
+                    timed_out: t,
+                }) => {
+                    debug!("cannot identify at {} baud", baudrate);
+                    attempts += n;
+                    last = e.or(last);
+                    timed_out |= t;
+                }
+                Err(e) => {
+                    debug!("cannot identify at {} baud: {}", baudrate, e);
+                    attempts += 1;
+                    last = Some(Box::new(e));
+                }
             }
         }
-        Ok(programmer)
+        Err(Error::Unidentified {
+            attempts,
+            last,
+            timed_out,
+        })
     }
 
     /// Sends serializable [`BinWrite`] data to the underlying port.
+    ///
+    /// The data is serialized first, so it's written to the port at once
+    /// rather than field by field. Bytes sent are logged at trace level with
+    /// [`WIRE_TARGET`].
     pub fn send<T: for<'b> BinWrite<Args<'b> = ()> + WriteEndian>(
         &mut self,
         data: T,
     ) -> Result<()> {
-        let mut wrapper = NoSeek::new(&mut self.port);
-        data.write(&mut wrapper)?;
+        self.last_active = Instant::now();
+        let mut bytes = std::io::Cursor::new(Vec::new());
+        data.write(&mut bytes)?;
+        wire::tx(bytes.get_ref());
+        self.port.write_all(bytes.get_ref())?;
         Ok(())
     }
 
     /// Sends serializable [`BinWrite`] data through reliable channels.
     ///
     /// Unlike [`Self::send`], the sender expects a reply from the controller.
+    /// The reply is awaited no longer than [`Probe::ack_timeout()`].
     pub fn send_reliable<T: for<'b> BinWrite<Args<'b> = ()> + WriteEndian>(
         &mut self,
         data: T,
     ) -> Result<()> {
-        let mut wrapper = NoSeek::new(&mut self.port);
-        data.write(&mut wrapper)?;
-        let reply: Reply = Reply::read(&mut wrapper)?;
-        trace!("received reliable reply: {:?}", reply);
+        let timeout = self.probe.ack_timeout();
+        self.send_reliable_within(data, timeout)
+    }
+
+    /// Sends data like [`Self::send_reliable`], but waits for the reply no
+    /// longer than `timeout`.
+    ///
+    /// The port timeout is restored afterwards, even if the reply is missing.
+    fn send_reliable_within<T: for<'b> BinWrite<Args<'b> = ()> + WriteEndian>(
+        &mut self,
+        data: T,
+        timeout: Duration,
+    ) -> Result<()> {
+        self.send(data)?;
+        self.recv_ack_within(timeout)
+    }
+
+    /// Receives a reply no longer than `timeout`, failing with
+    /// [`Error::NAck`] unless it's ACK.
+    fn recv_ack_within(&mut self, timeout: Duration) -> Result<()> {
+        let timeout = self.reply_timeout(timeout);
+        let reply = TimeoutGuard::new(self, timeout)?.recv::<Reply>()?;
+        trace!("← {}", frame::annotate_reply(reply));
         match reply {
             Reply::NAck => Err(Error::NAck),
             Reply::Ack => Ok(()),
@@ -253,43 +656,238 @@ impl Programmer {
     }
 
     /// Receives serializable [`BinRead`] data from the underlying port.
+    ///
+    /// Bytes received are logged at trace level with [`WIRE_TARGET`].
     pub fn recv<T: for<'b> BinRead<Args<'b> = ()> + ReadEndian>(&mut self) -> Result<T> {
-        let mut wrapper = NoSeek::new(&mut self.port);
+        let mut wrapper = NoSeek::new(wire::Tap::new(&mut self.port));
         let data = T::read(&mut wrapper)?;
         Ok(data)
     }
 
     /// Receives serializable [`BinRead`] data through reliable channels.
+    ///
+    /// The data is awaited no longer than [`Probe::data_timeout()`]. As in
+    /// AN3155, the device ends the data with ACK, which is awaited no longer
+    /// than [`Probe::ack_timeout()`]; the host sends nothing in return, since
+    /// any byte would be taken as the start of the next command. Both are
+    /// overridden within [`Self::with_timeout()`].
     pub fn recv_reliable<T: for<'b> BinRead<Args<'b> = ()> + ReadEndian>(&mut self) -> Result<T> {
-        let mut wrapper = NoSeek::new(&mut self.port);
-        let data = T::read(&mut wrapper)?;
-        self.send_reliable(())?;
+        let timeout = self.reply_timeout(self.probe.data_timeout());
+        let data = TimeoutGuard::new(self, timeout)?.recv::<T>()?;
+        self.recv_ack_within(self.probe.ack_timeout())?;
+        Ok(data)
+    }
+
+    /// Fills the buffer with a payload from the port, waiting no longer than
+    /// [`Probe::data_timeout()`] unless overridden, see
+    /// [`Self::with_timeout()`].
+    fn recv_payload(&mut self, buf: &mut [u8]) -> Result<()> {
+        let timeout = self.reply_timeout(self.probe.data_timeout());
+        let mut guard = TimeoutGuard::new(self, timeout)?;
+        wire::Tap::new(&mut guard.port).read_exact(buf)?;
+        Ok(())
+    }
+
+    /// Sends raw bytes to the underlying port, as a low-level escape hatch
+    /// for debugging non-standard bootloaders.
+    ///
+    /// No framing, checksum or ACK handling is applied, so sending arbitrary
+    /// bytes may leave the bootloader in an unexpected state.
+    pub fn send_raw(&mut self, bytes: &[u8]) -> Result<()> {
+        self.last_active = Instant::now();
+        wire::tx(bytes);
+        self.port.write_all(bytes)?;
+        self.port.flush()?;
+        Ok(())
+    }
+
+    /// Receives up to `n` raw bytes from the underlying port, waiting no
+    /// longer than `timeout` for each read.
+    ///
+    /// Returns the bytes received until `n` bytes or a timeout is reached,
+    /// without any framing, checksum or ACK handling.
+    pub fn recv_raw(&mut self, n: usize, timeout: Duration) -> Result<Vec<u8>> {
+        let mut guard = TimeoutGuard::new(self, timeout)?;
+        let mut port = wire::Tap::new(&mut guard.port);
+        let mut data = vec![0u8; n];
+        let mut received = 0;
+        while received < n {
+            match port.read(&mut data[received..]) {
+                Ok(0) => break,
+                Ok(count) => received += count,
+                Err(e) if e.kind() == std::io::ErrorKind::TimedOut => break,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        data.truncate(received);
         Ok(data)
     }
 
     /// Sends a [`Command`] defined in the protocol.
+    ///
+    /// The command is recorded if history is enabled, see
+    /// [`Self::enable_history()`]. Commands modifying the device fail with
+    /// [`Error::SafeMode`] if the probe is in safe mode.
+    ///
+    /// A command NAcked by the device is resent after clearing the port, up
+    /// to [`Probe::command_retries()`] times, if allowed by
+    /// [`Probe::retry_modifying()`].
     pub fn send_command(&mut self, command: Command) -> Result<()> {
+        let operation = self.history.is_some().then(|| (&command).into());
+        let result = self.send_command_retrying(command);
+        if let (Some(history), Some(operation)) = (self.history.as_mut(), operation) {
+            history.push(HistoryEntry::new(operation, (&result).into()));
+        }
+        result
+    }
+
+    /// Sends a command, and resends it as long as it's NAcked and retries are
+    /// allowed for it.
+    fn send_command_retrying(&mut self, command: Command) -> Result<()> {
+        let retries = match command {
+            // NAck is expected once the device is already synchronized.
+            Command::Synchronize => 0,
+            ref command if command.is_harmless() || self.probe.retry_modifying() => {
+                self.probe.command_retries()
+            }
+            _ => 0,
+        };
+        for attempt in 1..=retries {
+            match self.send_command_unrecorded(command.clone()) {
+                Err(Error::NAck) => {
+                    debug!("command is NAcked, retrying {}/{}", attempt, retries);
+                    self.port.clear(ClearBuffer::All)?;
+                }
+                result => return result,
+            }
+        }
+        self.send_command_unrecorded(command)
+    }
+
+    fn send_command_unrecorded(&mut self, command: Command) -> Result<()> {
+        if self.probe.safe_mode()
+            && !command.is_harmless()
+            && let Some(opcode) = command.opcode()
+        {
+            return Err(Error::SafeMode(opcode));
+        }
         match command {
             Command::Read { address, size } => {
-                self.send_reliable(Opcode::READ)?;
-                self.send_reliable(address)?;
-                self.send_reliable(size)
+                let address = address.with_width(self.probe.address_width())?;
+                self.send_frame(Opcode::READ)?;
+                self.send_frame(address)?;
+                self.send_frame(size)
+            }
+            Command::Go(address) => {
+                let address = address.with_width(self.probe.address_width())?;
+                self.send_frame(Opcode::GO)?;
+                self.send_frame(address)
+            }
+            Command::Write { address, data } if self.probe.pipeline_writes() => {
+                let address = address.with_width(self.probe.address_width())?;
+                self.write_pipelined(address, data)
             }
             Command::Write { address, data } => {
-                self.send_reliable(Opcode::WRITE)?;
-                self.send_reliable(address)?;
-                self.send_reliable(data)
+                let address = address.with_width(self.probe.address_width())?;
+                self.send_frame(Opcode::WRITE)?;
+                self.send_frame(address)?;
+                self.send_frame(data)
             }
             Command::Erase(erase) => {
-                self.send_reliable(Opcode::ERASE)?;
-                self.send_reliable(erase)
+                self.send_frame(Opcode::ERASE)?;
+                self.send_frame_within(erase, self.probe.erase_ack_timeout())
             }
             Command::ExtendedErase(erase) => {
-                self.send_reliable(Opcode::EXTENDED_ERASE)?;
-                self.send_reliable(erase)
+                self.send_frame(Opcode::EXTENDED_ERASE)?;
+                self.send_frame_within(erase, self.probe.erase_ack_timeout())
+            }
+            Command::WriteProtect(sectors) => {
+                self.send_frame(Opcode::WRITE_PROTECT)?;
+                self.send_frame(sectors)
+            }
+            // The second ACK follows once the option bytes are changed.
+            Command::WriteUnprotect() => {
+                self.send_frame(Opcode::WRITE_UNPROTECT)?;
+                self.recv_ack_within(self.probe.ack_timeout())
+            }
+            Command::ReadProtect() => {
+                self.send_frame(Opcode::READ_PROTECT)?;
+                self.recv_ack_within(self.probe.ack_timeout())
+            }
+            // The second ACK follows once flash memory is mass-erased.
+            Command::ReadUnprotect() => {
+                self.send_frame(Opcode::READ_UNPROTECT)?;
+                self.recv_ack_within(self.probe.erase_ack_timeout())
+            }
+            other => self.send_frame(other),
+        }
+    }
+
+    /// Sends the opcode and address frames of WRITE at once, then the data
+    /// frame once both are acknowledged, see [`Probe::pipeline_writes()`].
+    ///
+    /// The data is held back until the address is accepted, since firmware
+    /// bytes taken as commands could erase or protect the device. Once the
+    /// opcode is refused, e.g. under readout protection, the address is taken
+    /// as commands, so their replies are drained and the bootloader is brought
+    /// back to waiting for commands, see [`Self::abort_pending_frame()`].
+    fn write_pipelined(&mut self, address: Address, data: Data<'_>) -> Result<()> {
+        if log::log_enabled!(log::Level::Trace) {
+            trace!("→ {}", Opcode::WRITE.annotate());
+            trace!("→ {}", address.annotate());
+        }
+        let mut batch = std::io::Cursor::new(Vec::new());
+        Opcode::WRITE.write(&mut batch)?;
+        address.write(&mut batch)?;
+        self.send_raw(batch.get_ref())?;
+        let timeout = self.probe.ack_timeout();
+        if let Err(e) = self.recv_ack_within(timeout) {
+            self.realign();
+            return Err(e);
+        }
+        match self.recv_ack_within(timeout) {
+            Ok(()) => {}
+            // A refused address is consumed whole, so commands are aligned.
+            Err(Error::NAck) => return Err(Error::NAck),
+            Err(e) => {
+                self.realign();
+                return Err(e);
             }
-            other => self.send_reliable(other),
         }
+        // The last ACK follows once the data is programmed.
+        self.send_frame(data)
+    }
+
+    /// Drains replies still arriving and brings the bootloader back to
+    /// waiting for commands after a pipelined frame goes astray.
+    ///
+    /// Failures are only logged, since the error of the frame itself is what
+    /// the caller needs, and [`Self::reconnect()`] remains as a last resort.
+    fn realign(&mut self) {
+        let quiet_for = self.probe.ack_timeout();
+        let result = self
+            .wait_for_quiet(quiet_for, Self::MAX_QUIET_WAIT)
+            .and_then(|()| self.abort_pending_frame());
+        if let Err(e) = result {
+            debug!("cannot realign commands: {}", e);
+        }
+    }
+
+    /// Sends a frame through reliable channels, logging it along with its
+    /// description at trace level.
+    fn send_frame<T: Annotate>(&mut self, frame: T) -> Result<()> {
+        let timeout = self.probe.ack_timeout();
+        self.send_frame_within(frame, timeout)
+    }
+
+    /// Sends a frame like [`Self::send_frame`], but waits for the reply no
+    /// longer than `timeout`.
+    fn send_frame_within<T: Annotate>(&mut self, frame: T, timeout: Duration) -> Result<()> {
+        if log::log_enabled!(log::Level::Trace) {
+            trace!("→ {}", frame.annotate());
+        }
+        self.send_reliable_within(frame, timeout)
     }
 
     /// Changes a signal value of the underlying port.
@@ -319,55 +917,375 @@ impl Programmer {
     }
 
     /// Resets the device.
+    ///
+    /// Reset is held for [`Probe::reset_for()`], then released for
+    /// [`Probe::boot_settle()`] before returning, so the bootloader is ready
+    /// to be synchronized with.
     pub fn reset(&mut self) -> Result<()> {
         if self.probe.signal_reset().is_some() {
             self.set_reset(false)?;
             self.set_reset(true)?;
             std::thread::sleep(self.probe.reset_for());
             self.set_reset(false)?;
+            std::thread::sleep(self.probe.boot_settle());
         }
         Ok(())
     }
 
+    /// Identifies the device according to [`Probe::identify()`].
+    ///
+    /// With [`Identify::Get`], the device is taken as identified once it
+    /// replies to GET, without touching the boot signal or resending the
+    /// synchronization byte, which a bootloader only accepts once.
     fn identify(&mut self) -> Result<()> {
+        match self.probe.identify() {
+            Identify::Handshake => self.handshake(),
+            Identify::Get => match self.read_bootloader().map_err(Error::into_port_error) {
+                Err(e) if !e.is_unusable() => {
+                    debug!("cannot identify by GET: {}", e);
+                    Err(Error::Unidentified {
+                        attempts: 1,
+                        last: Some(Box::new(e)),
+                        timed_out: false,
+                    })
+                }
+                result => result.map(|_| ()),
+            },
+        }
+    }
+
+    /// Sends the synchronization byte once, so the bootloader measures the
+    /// baudrate, without touching any signal or retrying.
+    ///
+    /// A bootloader only accepts it once after reset, so it fails with
+    /// [`Error::Unidentified`] if the device doesn't ACK, whether it's silent
+    /// or replies NAck, and with [`Error::Unusable`] if the port itself fails.
+    pub fn synchronize(&mut self) -> Result<()> {
+        match self.send_command(Command::Synchronize) {
+            Ok(()) => Ok(()),
+            Err(e) => match e.into_port_error() {
+                e if e.is_unusable() => Err(e),
+                e => Err(Error::Unidentified {
+                    attempts: 1,
+                    last: Some(Box::new(e)),
+                    timed_out: false,
+                }),
+            },
+        }
+    }
+
+    /// Identifies the device by handshaking.
+    ///
+    /// Fails with [`Error::Unusable`] as soon as the port itself fails, or
+    /// with [`Error::Unidentified`] if the device never responds, or with
+    /// [`Error::BootReversed`] if it only responds with boot signal inverted.
+    fn handshake(&mut self) -> Result<()> {
+        self.try_identify().map_err(Error::into_port_error)
+    }
+
+    fn try_identify(&mut self) -> Result<()> {
+        let start = Instant::now();
         let mut retries = 0;
+        let mut last = None;
         self.set_boot(true)?;
         while retries < self.probe.max_attempts() {
+            if let Some(max) = self.probe.max_identify_time()
+                && retries > 0
+                && start.elapsed() >= max
+            {
+                debug!(
+                    "cannot identify within {:?}, after {} attempts",
+                    max, retries
+                );
+                return self.unidentified(retries, last, true);
+            }
             self.reset()?;
+            if let Some(quiet_for) = self.probe.quiet_for()
+                && let Err(e) = self.wait_for_quiet(quiet_for, Self::MAX_QUIET_WAIT)
+            {
+                let e = e.into_port_error();
+                if e.is_unusable() {
+                    return Err(e);
+                }
+                last = Some(e);
+                retries += 1;
+                continue;
+            }
             self.port.clear(ClearBuffer::All)?;
-            match self.send_reliable(Command::Synchronize) {
+            match self.send_command(Command::Synchronize) {
                 Ok(_) => {
                     self.set_boot(false)?;
                     self.port.clear(ClearBuffer::All)?;
                     return Ok(());
                 }
-                _ => {}
+                Err(e) => {
+                    let e = e.into_port_error();
+                    if e.is_unusable() {
+                        return Err(e);
+                    }
+                    last = Some(e);
+                }
             }
             retries += 1;
         }
-        Err(Error::Unidentified)
+        debug!("cannot identify after {} attempts", retries);
+        self.unidentified(retries, last, false)
+    }
+
+    /// Fails identification after `attempts`, the last failing with `last`,
+    /// checking first whether the device responds with the boot signal
+    /// inverted, which is the most common wiring mistake.
+    ///
+    /// Once `timed_out`, the time limit is over, so nothing is checked.
+    fn unidentified<T>(
+        &mut self,
+        attempts: usize,
+        last: Option<Error>,
+        timed_out: bool,
+    ) -> Result<T> {
+        let unidentified = Error::Unidentified {
+            attempts,
+            last: last.map(Box::new),
+            timed_out,
+        };
+        let Some(signal) = self.probe.signal_boot().filter(|_| !timed_out) else {
+            return Err(unidentified);
+        };
+        self.set_signal(signal, false)?;
+        self.reset()?;
+        self.port.clear(ClearBuffer::All)?;
+        if self.send_command(Command::Synchronize).is_ok() {
+            debug!("device responds with boot signal inverted");
+            Err(Error::BootReversed)
+        } else {
+            Err(unidentified)
+        }
+    }
+
+    /// Longest wait for silence during identification, see
+    /// [`Probe::quiet_for()`].
+    const MAX_QUIET_WAIT: Duration = Duration::from_secs(2);
+
+    /// Reads and discards bytes until no data arrives on the port for
+    /// `quiet_for`.
+    ///
+    /// Fails with a timed-out [`Error::Io`] if data keeps arriving for longer
+    /// than `timeout`.
+    pub fn wait_for_quiet(&mut self, quiet_for: Duration, timeout: Duration) -> Result<()> {
+        let start = Instant::now();
+        let mut guard = TimeoutGuard::new(self, quiet_for)?;
+        let mut buf = [0u8; 64];
+        loop {
+            match guard.port.read(&mut buf) {
+                Ok(n) => trace!("discarded {} bytes while waiting for quiet", n),
+                Err(e) if e.kind() == std::io::ErrorKind::TimedOut => return Ok(()),
+                Err(e) => return Err(e.into()),
+            }
+            if start.elapsed() >= timeout {
+                debug!("port is still busy after {:?}", timeout);
+                return Err(std::io::Error::from(std::io::ErrorKind::TimedOut).into());
+            }
+        }
+    }
+
+    /// Reconnects to the device after it has been reset, e.g. by
+    /// [`Command::Go`] or [`Command::WriteUnprotect`].
+    ///
+    /// Waits for [`Probe::reconnect_delay()`] before identifying the device
+    /// again on the same port, signaling reset and boot and retrying as the
+    /// initial handshake does, up to [`Probe::max_attempts()`].
+    ///
+    /// A device waiting for synchronization must not be sent anything else
+    /// first, see [`Self::abort_pending_frame()`]. But if no reset signal is
+    /// wired and the device NAcks synchronization, it's still running the
+    /// bootloader, possibly stuck in the middle of a frame, so the pending
+    /// frame is aborted then.
+    pub fn reconnect(&mut self) -> Result<()> {
+        std::thread::sleep(self.probe.reconnect_delay());
+        // A device that has been reset waits for synchronization again.
+        match self.handshake() {
+            Err(Error::Unidentified {
+                last: Some(ref last),
+                ..
+            }) if last.is_nack() && self.probe.signal_scheme().reset().is_none() => {
+                debug!("synchronization is NAcked, aborting pending frame");
+                self.abort_pending_frame()
+            }
+            result => result,
+        }
+    }
+
+    /// Twice the time of a byte on the line, with 10 bits per byte, which is
+    /// how long a byte already sent by the device may take to arrive.
+    fn byte_wait(&self) -> Duration {
+        Duration::from_secs_f64(20.0 / self.probe.baudrate() as f64).max(Duration::from_millis(1))
+    }
+
+    /// Discards an ACK following a reply, if it arrives shortly and
+    /// [`Probe::tolerate_extra_ack()`] is set.
+    ///
+    /// Nothing else is expected from the device at this point, so any other
+    /// byte is discarded as well.
+    fn discard_extra_ack(&mut self) -> Result<()> {
+        if !self.probe.tolerate_extra_ack() {
+            return Ok(());
+        }
+        let wait = self.byte_wait();
+        let mut guard = TimeoutGuard::new(self, wait)?;
+        let mut byte = [0u8];
+        match wire::Tap::new(&mut guard.port).read(&mut byte) {
+            Ok(0) => {}
+            Ok(_) if byte[0] == 0x79 => trace!("discarded extra ACK"),
+            Ok(_) => debug!("discarded unexpected {:#04x} after reply", byte[0]),
+            Err(e) if e.kind() == std::io::ErrorKind::TimedOut => {}
+            Err(e) => return Err(e.into()),
+        }
+        Ok(())
+    }
+
+    /// Longest frame the bootloader may wait for, i.e. a WRITE data frame of
+    /// N-1, 256 bytes and checksum.
+    const MAX_FRAME: usize = 258;
+
+    /// Brings a bootloader waiting for the rest of an interrupted frame, e.g.
+    /// a cancelled WRITE, back to waiting for commands.
+    ///
+    /// Such a bootloader takes any command as frame data, so it seems to NAck
+    /// or ignore everything. The heuristic is:
+    ///
+    /// 1. Padding bytes are sent one by one, until the bootloader replies as
+    ///    the frame is complete, or as many bytes as the longest frame are
+    ///    sent. The padding is `0xff`, the erased state of flash memory, so
+    ///    even a frame completed with a valid checksum by chance writes nothing
+    ///    harmful.
+    /// 2. Replies are drained.
+    /// 3. GET checks that commands are aligned again. A reply arriving late
+    ///    may leave one byte pending, which the GET would complete with a
+    ///    NAck, so one more padding byte is sent and GET is retried once.
+    ///
+    /// A bootloader waiting for the synchronization byte must not be sent
+    /// padding, since it could measure a wrong baudrate from it.
+    pub fn abort_pending_frame(&mut self) -> Result<()> {
+        let wait = self.byte_wait();
+        self.port.clear(ClearBuffer::Input)?;
+        for _ in 0..Self::MAX_FRAME {
+            self.send_raw(&[0xff])?;
+            std::thread::sleep(wait);
+            if self.port.bytes_to_read()? > 0 {
+                break;
+            }
+        }
+        self.recv_raw(Self::MAX_FRAME, self.probe.timeout())?;
+        match self.read_bootloader() {
+            Err(Error::NAck) => {
+                self.send_raw(&[0xff])?;
+                self.recv_raw(Self::MAX_FRAME, self.probe.timeout())?;
+                self.read_bootloader()?;
+            }
+            result => {
+                result?;
+            }
+        }
+        Ok(())
     }
 
-    /// Discovers compliant devices using a probe.
-    pub fn discover(probe: &Probe) -> Result<Vec<Self>> {
-        let ports = serialport::available_ports()?
-            .into_iter()
-            .filter_map(|s| Self::open(s.port_name, probe).ok())
-            .collect();
-        Ok(ports)
+    /// Pings the device with GET if it has been idle for
+    /// [`Probe::keepalive_interval()`], so the bootloader doesn't time out.
+    ///
+    /// Returns whether the device has been pinged.
+    pub fn keep_alive(&mut self) -> Result<bool> {
+        match self.probe.keepalive_interval() {
+            Some(interval) if self.last_active.elapsed() >= interval => {
+                self.read_bootloader()?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
     }
 
     /// Reads bootloader information.
+    ///
+    /// The information is cached, see [`Self::bootloader()`].
     pub fn read_bootloader(&mut self) -> Result<Bootloader> {
         self.send_command(Command::Get())?;
         let bootloader: Bootloader = self.recv_reliable()?;
+        self.discard_extra_ack()?;
+        self.bootloader = Some(bootloader.clone());
         Ok(bootloader)
     }
 
+    /// Starts recording commands sent by [`Self::send_command()`] for audit
+    /// logs.
+    pub fn enable_history(&mut self) {
+        self.history.get_or_insert_with(Vec::new);
+    }
+
+    /// Stops recording commands and discards the recorded history.
+    pub fn disable_history(&mut self) {
+        self.history = None;
+    }
+
+    /// Takes the history recorded so far, while recording continues.
+    ///
+    /// Returns an empty history if recording hasn't been enabled.
+    pub fn take_history(&mut self) -> ProgrammingHistory {
+        self.history
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    /// Gets bootloader information cached by [`Self::read_bootloader()`].
+    pub fn bootloader(&self) -> Option<&Bootloader> {
+        self.bootloader.as_ref()
+    }
+
+    /// Gets cached bootloader information, reading it if not cached yet.
+    fn bootloader_or_read(&mut self) -> Result<Bootloader> {
+        match self.bootloader.clone() {
+            Some(bootloader) => Ok(bootloader),
+            None => self.read_bootloader(),
+        }
+    }
+
+    /// Runs `f` with the read timeout of the port set to `timeout`, restoring
+    /// the previous timeout afterwards, even if `f` fails.
+    ///
+    /// Replies to commands within `f` are awaited no longer than `timeout`
+    /// either, rather than the timeouts of the probe, e.g.
+    /// [`Probe::ack_timeout()`] or [`Probe::erase_ack_timeout()`].
+    pub fn with_timeout<R>(
+        &mut self,
+        timeout: Duration,
+        f: impl FnOnce(&mut Self) -> Result<R>,
+    ) -> Result<R> {
+        let previous = self.timeout_override.replace(timeout);
+        let result = TimeoutGuard::new(self, timeout).and_then(|mut guard| f(&mut guard));
+        self.timeout_override = previous;
+        result
+    }
+
+    /// Gets the timeout for a reply, which is `timeout` unless overridden by
+    /// [`Self::with_timeout()`].
+    fn reply_timeout(&self, timeout: Duration) -> Duration {
+        self.timeout_override.unwrap_or(timeout)
+    }
+
+    /// Gets configuration state of the programmer, which can be applied to
+    /// another port by [`Self::open_with()`].
+    pub fn config(&self) -> ProgrammerConfig {
+        ProgrammerConfig {
+            probe: self.probe.clone(),
+            bootloader: self.bootloader.clone(),
+            device: self.device,
+        }
+    }
+
     /// Reads version.
     pub fn read_version(&mut self) -> Result<Version> {
         self.send_command(Command::Version())?;
         let version: Version = self.recv_reliable()?;
+        self.discard_extra_ack()?;
         Ok(version)
     }
 
@@ -378,29 +1296,498 @@ impl Programmer {
         Ok(id)
     }
 
-    /// Reads memory at specific region.
-    pub fn read_memory(&mut self, address: impl Into<Address>, size: Size) -> Result<Data> {
+    /// Reads chip ID and looks up the device, see [`device::lookup()`].
+    ///
+    /// Returns [`None`] if the device is not known. Otherwise, the device is
+    /// kept for checks like [`Self::write_alignment()`].
+    pub fn identify_chip(&mut self) -> Result<Option<device::DeviceInfo>> {
+        self.device = device::lookup(self.read_id()?.as_u16());
+        Ok(self.device)
+    }
+
+    /// Gets alignment of addresses written, which is that of the device
+    /// identified by [`Self::identify_chip()`], or 4 bytes otherwise.
+    pub fn write_alignment(&self) -> u32 {
+        self.device.map_or(4, |device| device.alignment())
+    }
+
+    /// Reads memory at specific region of any size, by successive READ
+    /// commands of up to 256 bytes.
+    ///
+    /// If a READ fails after others succeeded, e.g. with NAck at the end of
+    /// flash memory, it fails with [`Error::PartialRead`], which tells how many
+    /// bytes were read.
+    pub fn read_memory(&mut self, address: u32, size: usize) -> Result<Vec<u8>> {
+        self.read_memory_with_progress(address, size, None)
+    }
+
+    /// Reads memory like [`Self::read_memory()`], calling `progress` after
+    /// each chunk.
+    pub fn read_memory_with_progress(
+        &mut self,
+        address: u32,
+        size: usize,
+        mut progress: Option<&mut dyn FnMut(Progress)>,
+    ) -> Result<Vec<u8>> {
+        Self::check_range(address, size)?;
+        let mut data = Vec::with_capacity(size);
+        let result = self.read_chunks(address, size, |_, chunk| {
+            data.extend_from_slice(chunk);
+            if let Some(progress) = progress.as_mut() {
+                progress(Progress::new(data.len(), size));
+            }
+            Ok(())
+        });
+        match result {
+            Ok(()) => Ok(data),
+            Err(e) if data.is_empty() => Err(e),
+            Err(e) => Err(Error::PartialRead {
+                read: data.len(),
+                source: Box::new(e),
+            }),
+        }
+    }
+
+    /// Reads memory at specific region into `buf`, whose length is the size
+    /// read, so a single buffer can be reused without allocating.
+    ///
+    /// The buffer may be longer than 256 bytes, since it's filled by
+    /// successive READ commands like [`Self::read_memory()`]. If a READ fails
+    /// after others succeeded, it fails with [`Error::PartialRead`], and the
+    /// bytes read so far are at the start of `buf`.
+    pub fn read_memory_into(&mut self, address: u32, buf: &mut [u8]) -> Result<()> {
+        let size = buf.len();
+        Self::check_range(address, size)?;
+        let mut read = 0;
+        for chunk in buf.chunks_mut(256) {
+            match self.read_chunk(address + read as u32, chunk) {
+                Ok(()) => read += chunk.len(),
+                Err(e) if read == 0 => return Err(e),
+                Err(e) => {
+                    return Err(Error::PartialRead {
+                        read,
+                        source: Box::new(e),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads a region of any size by successive READ commands, passing each
+    /// chunk to `f` along with its address rather than retaining it.
+    ///
+    /// A region crossing the end of address space fails with
+    /// [`Error::OutOfRange`] before anything is sent.
+    fn read_chunks(
+        &mut self,
+        address: u32,
+        size: usize,
+        mut f: impl FnMut(u32, &[u8]) -> Result<()>,
+    ) -> Result<()> {
+        Self::check_range(address, size)?;
+        let mut buf = [0u8; 256];
+        let mut offset = 0;
+        while offset < size {
+            let chunk = &mut buf[..(size - offset).min(256)];
+            let chunk_address = address + offset as u32;
+            self.read_chunk(chunk_address, chunk)?;
+            f(chunk_address, chunk)?;
+            offset += chunk.len();
+        }
+        Ok(())
+    }
+
+    /// Fails with [`Error::OutOfRange`] if a region crosses the end of address
+    /// space.
+    fn check_range(address: u32, size: usize) -> Result<()> {
+        if address as u64 + size as u64 > u32::MAX as u64 + 1 {
+            return Err(Error::OutOfRange { address, size });
+        }
+        Ok(())
+    }
+
+    /// Fills a buffer of up to 256 bytes by a single READ command.
+    fn read_chunk(&mut self, address: u32, buf: &mut [u8]) -> Result<()> {
         self.send_command(Command::Read {
             address: address.into(),
-            size,
+            size: Size::try_from(buf.len())?,
         })?;
-        let mut data = vec![0u8; size.into()];
-        self.port.read_exact(&mut data)?;
-        Ok(data.try_into().unwrap())
+        self.recv_payload(buf)
     }
 
-    /// Writes memory at specific region.
-    pub fn write_memory<'a, D>(&mut self, address: impl Into<Address>, data: Data) -> Result<()> {
-        self.send_reliable(Command::Write {
-            address: address.into(),
-            data,
+    /// Reads a register of the device by name, see
+    /// [`device::Family::registers()`] for registers known.
+    ///
+    /// The device is identified by GET_ID first.
+    pub fn read_register(&mut self, name: &str) -> Result<Vec<u8>> {
+        let register = self
+            .read_id()?
+            .family()
+            .and_then(|family| family.register(name))
+            .ok_or_else(|| Error::UnknownRegister(name.to_owned()))?;
+        let mut data = vec![0u8; register.size()];
+        self.read_chunk(register.address(), &mut data)?;
+        Ok(data)
+    }
+
+    /// Reads memory from `address` in chunks of `chunk` bytes (at most 256)
+    /// until the device refuses with NAck, e.g. at the end of flash memory.
+    ///
+    /// Returns the bytes read before the NAck, which is useful to find out
+    /// the extent of memory on devices unknown otherwise.
+    pub fn read_until_nack(&mut self, address: u32, chunk: usize) -> Result<Vec<u8>> {
+        Size::try_from(chunk)?;
+        let mut data = Vec::new();
+        let mut buf = vec![0u8; chunk];
+        // Stops at the end of address space as well.
+        while let Some(chunk_address) = u32::try_from(data.len())
+            .ok()
+            .and_then(|offset| address.checked_add(offset))
+        {
+            match self.read_chunk(chunk_address, &mut buf) {
+                Ok(()) => data.extend_from_slice(&buf),
+                Err(Error::NAck) => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(data)
+    }
+
+    /// Reads memory at specific region of any size and feeds it into a hasher,
+    /// without retaining the data.
+    ///
+    /// Returns [`Hasher::finish()`] of the hasher, while any other digest can
+    /// be obtained from the hasher itself.
+    pub fn read_memory_hashed(
+        &mut self,
+        address: u32,
+        size: usize,
+        hasher: &mut impl Hasher,
+    ) -> Result<u64> {
+        self.read_hashed_with_progress(address, size, hasher, None)
+    }
+
+    /// Reads memory and feeds it into a hasher like
+    /// [`Self::read_memory_hashed()`], while reporting progress, which is
+    /// what provisioning usually needs for manifests of large regions.
+    ///
+    /// `progress` is called after each chunk, like
+    /// [`Self::read_memory_with_progress()`].
+    pub fn read_hashed_with_progress(
+        &mut self,
+        address: u32,
+        size: usize,
+        hasher: &mut impl Hasher,
+        mut progress: Option<&mut dyn FnMut(Progress)>,
+    ) -> Result<u64> {
+        let mut read = 0;
+        self.read_chunks(address, size, |_, chunk| {
+            hasher.write(chunk);
+            read += chunk.len();
+            if let Some(progress) = progress.as_mut() {
+                progress(Progress::new(read, size));
+            }
+            Ok(())
         })?;
+        Ok(hasher.finish())
+    }
+
+    /// Compares two regions of the same size on the device, e.g. the banks of
+    /// a dual-bank copy, and returns offsets where they differ.
+    ///
+    /// Regions are read and compared chunk by chunk, so memory use stays
+    /// bounded regardless of size. Either region crossing the end of address
+    /// space fails with [`Error::OutOfRange`] before anything is sent.
+    pub fn diff_regions(&mut self, a: u32, b: u32, size: usize) -> Result<Vec<usize>> {
+        Self::check_range(a, size)?;
+        Self::check_range(b, size)?;
+        let mut diff = Vec::new();
+        let mut buf_a = [0u8; 256];
+        let mut buf_b = [0u8; 256];
+        let mut offset = 0;
+        while offset < size {
+            let len = (size - offset).min(256);
+            self.read_chunk(a + offset as u32, &mut buf_a[..len])?;
+            self.read_chunk(b + offset as u32, &mut buf_b[..len])?;
+            diff.extend(
+                (0..len)
+                    .filter(|i| buf_a[*i] != buf_b[*i])
+                    .map(|i| offset + i),
+            );
+            offset += len;
+        }
+        Ok(diff)
+    }
+
+    /// Verifies that memory at specific region matches `data`.
+    ///
+    /// The CRC computed by the device with GET_CHECKSUM is compared if
+    /// available, and memory is read back otherwise, see
+    /// [`Self::verify_image()`].
+    pub fn read_memory_crc32_verify(&mut self, address: u32, data: &[u8]) -> Result<bool> {
+        self.verify_image(address, data)
+            .map(|report| report.matched())
+    }
+
+    /// Writes memory at specific region.
+    ///
+    /// Fails with [`Error::Alignment`] before anything is sent if the address
+    /// is not aligned to [`Self::write_alignment()`], which the device would
+    /// refuse or mishandle. READ has no such requirement.
+    pub fn write_memory(&mut self, address: impl Into<Address>, data: Data<'_>) -> Result<()> {
+        let address = address.into();
+        if !address.as_u32().is_multiple_of(self.write_alignment()) {
+            return Err(Error::Alignment {
+                address: address.as_u32(),
+            });
+        }
+        self.send_command(Command::Write { address, data })
+    }
+
+    /// Writes memory at specific region of any size by successive WRITE
+    /// commands of up to 256 bytes.
+    ///
+    /// A misaligned address fails with [`Error::Alignment`] before anything
+    /// is sent, see [`Self::write_alignment()`]. If a WRITE fails after others
+    /// succeeded, it fails with [`Error::PartialWrite`], which tells how many
+    /// bytes were written.
+    ///
+    /// With the `tracing` feature, a span covers the whole write and an event
+    /// is emitted for each chunk.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self, data), fields(length = data.len()))
+    )]
+    pub fn write_memory_chunked(&mut self, address: u32, data: &[u8]) -> Result<()> {
+        self.write_memory_chunked_with_progress(address, data, None)
+    }
+
+    /// Writes memory like [`Self::write_memory_chunked()`], calling `progress`
+    /// after each chunk.
+    pub fn write_memory_chunked_with_progress(
+        &mut self,
+        address: u32,
+        data: &[u8],
+        progress: Option<&mut dyn FnMut(Progress)>,
+    ) -> Result<()> {
+        self.write_chunks(address, data, |_, _| Ok(()), progress)?;
         Ok(())
     }
 
+    /// Writes memory like [`Self::write_memory_chunked()`], then reads the
+    /// same region back in the same chunks and compares it byte by byte.
+    ///
+    /// If cached bootloader information shows GET_CHECKSUM support and the
+    /// address is word-aligned, the CRC computed by the device is compared to
+    /// [`stm32_crc32()`] of `bytes` first, and memory is only read back if
+    /// they differ, to locate the mismatch.
+    ///
+    /// The first byte differing fails with [`Error::VerifyMismatch`]. A READ
+    /// refused, e.g. under readout protection, fails with [`Error::NAck`]
+    /// rather than a mismatch, since nothing could be compared.
+    pub fn write_verify(&mut self, address: u32, bytes: &[u8]) -> Result<()> {
+        self.write_memory_chunked(address, bytes)?;
+        let checksum = self
+            .bootloader()
+            .is_some_and(|b| b.supports(Opcode::GET_CHECKSUM));
+        if checksum && address.is_multiple_of(4) {
+            if self.verify_image(address, bytes)?.matched() {
+                return Ok(());
+            }
+            debug!("checksum mismatch, reading back to locate it");
+        }
+        self.compare_memory(address, bytes)
+    }
+
+    /// Reads memory back in chunks of up to 256 bytes and compares it byte by
+    /// byte, failing with [`Error::VerifyMismatch`] at the first difference.
+    fn compare_memory(&mut self, address: u32, bytes: &[u8]) -> Result<()> {
+        let mut expected = bytes.iter();
+        self.read_chunks(address, bytes.len(), |chunk_address, chunk| {
+            for (i, (&found, &expected)) in chunk.iter().zip(&mut expected).enumerate() {
+                if found != expected {
+                    return Err(Error::VerifyMismatch {
+                        address: chunk_address + i as u32,
+                        expected,
+                        found,
+                    });
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Writes memory like [`Self::write_memory_chunked()`], passing each chunk
+    /// to `transform` along with its address before it's sent.
+    ///
+    /// The transform may alter the chunk in place, e.g. to encrypt or sign the
+    /// firmware, while `data` itself is left untouched. Any error it returns
+    /// aborts the write.
+    ///
+    /// Returns the number of bytes written.
+    pub fn write_memory_chunked_with_transform<F>(
+        &mut self,
+        address: u32,
+        data: &[u8],
+        transform: F,
+    ) -> Result<usize>
+    where
+        F: Fn(u32, &mut [u8]) -> Result<()>,
+    {
+        self.write_chunks(address, data, transform, None)
+    }
+
+    /// Writes memory by successive WRITE commands of up to 256 bytes, passing
+    /// each chunk to `transform` before it's sent and calling `progress` after.
+    ///
+    /// If a WRITE fails after others succeeded, it fails with
+    /// [`Error::PartialWrite`], so the write can be resumed. Errors of
+    /// `transform` are returned as they are. A misaligned address or a region
+    /// crossing the end of address space fails before anything is sent.
+    fn write_chunks<F>(
+        &mut self,
+        address: u32,
+        data: &[u8],
+        transform: F,
+        mut progress: Option<&mut dyn FnMut(Progress)>,
+    ) -> Result<usize>
+    where
+        F: Fn(u32, &mut [u8]) -> Result<()>,
+    {
+        if !address.is_multiple_of(self.write_alignment()) {
+            return Err(Error::Alignment { address });
+        }
+        Self::check_range(address, data.len())?;
+        #[cfg(feature = "tracing")]
+        let total_chunks = data.len().div_ceil(256);
+        let mut buf = [0u8; 256];
+        for (i, chunk) in data.chunks(256).enumerate() {
+            let chunk_address = address + (i * 256) as u32;
+            let buf = &mut buf[..chunk.len()];
+            buf.copy_from_slice(chunk);
+            transform(chunk_address, buf)?;
+            trace!("writing {} bytes at {:#010x}", buf.len(), chunk_address);
+            let written = i * 256;
+            self.write_memory(chunk_address, Data::try_from(&*buf)?)
+                .map_err(|e| match written {
+                    0 => e,
+                    written => Error::PartialWrite {
+                        written,
+                        source: Box::new(e),
+                    },
+                })?;
+            if let Some(progress) = progress.as_mut() {
+                progress(Progress::new(i * 256 + chunk.len(), data.len()));
+            }
+            #[cfg(feature = "tracing")]
+            tracing::event!(
+                tracing::Level::DEBUG,
+                address = chunk_address,
+                length = chunk.len(),
+                chunk_index = i,
+                total_chunks,
+                "chunk written",
+            );
+        }
+        Ok(data.len())
+    }
+
+    /// Creates a seekable reader of device memory starting at `base`.
+    pub fn reader(&mut self, base: impl Into<Address>) -> FlashReader<'_> {
+        FlashReader::new(self, base)
+    }
+
+    /// SRAM region in the memory map of Cortex-M devices.
+    const SRAM: std::ops::Range<u64> = 0x2000_0000..0x4000_0000;
+
+    /// Gets RAM of a device, starting at the base of SRAM, or the whole SRAM
+    /// region if the device is unknown.
+    fn ram_range(device: Option<device::DeviceInfo>) -> std::ops::Range<u64> {
+        match device {
+            Some(device) => Self::SRAM.start..Self::SRAM.start + device.ram() as u64,
+            None => Self::SRAM,
+        }
+    }
+
+    /// Loads code into RAM and jumps to it, which is the usual way to run a
+    /// custom flash loader or other helper routine.
+    ///
+    /// The device is identified first unless already, see
+    /// [`Self::identify_chip()`]. The code must lie within its RAM, or within
+    /// the SRAM region (`0x20000000` to `0x3fffffff`) if the device is not
+    /// known. It must not overlap RAM used by the bootloader itself either,
+    /// see AN2606 for the range of a specific device.
+    ///
+    /// After jumping, the bootloader is no longer running unless the code
+    /// returns to it, so the protocol may not be available anymore.
+    pub fn load_and_run_ram(&mut self, ram_addr: u32, code: &[u8]) -> Result<()> {
+        let device = match self.device {
+            Some(device) => Some(device),
+            None => self.identify_chip()?,
+        };
+        let ram = Self::ram_range(device);
+        let start = ram_addr as u64;
+        let end = start + code.len() as u64;
+        if code.is_empty() || !ram.contains(&start) || end > ram.end {
+            return Err(Error::OutOfRange {
+                address: ram_addr,
+                size: code.len(),
+            });
+        }
+        self.write_memory_chunked(ram_addr, code)?;
+        self.go(ram_addr)
+    }
+
+    /// Jumps to code at `address`, e.g. the application in flash memory.
+    ///
+    /// The opcode and the address are acknowledged separately. Afterwards,
+    /// the bootloader is no longer running, so the device must be reset into
+    /// it again, see [`Self::reconnect()`].
+    pub fn go(&mut self, address: u32) -> Result<()> {
+        self.send_command(Command::Go(address.into()))
+    }
+
+    /// Gets name of the underlying serial port, as known when the programmer
+    /// was created.
+    ///
+    /// Unlike [`SerialPort::name()`], it's still available after the port is
+    /// disconnected.
+    pub fn port_name(&self) -> Option<&str> {
+        self.port_name.as_deref()
+    }
+
+    /// Gets the baudrate the underlying serial port actually runs at.
+    ///
+    /// Adapters may silently clamp the baudrate of the probe to one they
+    /// support, in which case a warning is logged, since the bootloader
+    /// detects the baudrate from the first byte and may not be reliable at
+    /// the resulting rate.
+    pub fn actual_baudrate(&self) -> Result<Baudrate> {
+        self.check_baudrate(self.probe.baudrate())
+    }
+
+    /// Gets the baudrate of the underlying serial port, warning if it differs
+    /// from `requested`.
+    fn check_baudrate(&self, requested: Baudrate) -> Result<Baudrate> {
+        let actual = self.port.baud_rate()?;
+        if actual != requested {
+            warn!(
+                "port runs at {} baud rather than {} baud requested",
+                actual, requested
+            );
+        }
+        Ok(actual)
+    }
+
+    /// Gets the probe of the programmer.
+    pub fn probe(&self) -> &Probe {
+        &self.probe
+    }
+
     /// Gets the underlying serial port.
-    pub fn inner(&self) -> &Box<dyn SerialPort> {
-        &self.port
+    pub fn inner(&self) -> &dyn SerialPort {
+        self.port.as_ref()
     }
 
     /// Gets the underlying serial port and drops the programmer.
@@ -408,3 +1795,817 @@ impl Programmer {
         self.port
     }
 }
+
+/// Configuration state of a [`Programmer`]
+///
+/// Unlike [`Programmer`], the configuration doesn't own the serial port, thus
+/// it can be cloned freely and applied to another port with
+/// [`Programmer::open_with()`]. Besides the [`Probe`], it carries device
+/// information cached by the programmer so it's not read again.
+#[derive(Default, Debug, Clone)]
+pub struct ProgrammerConfig {
+    probe: Arc<Probe>,
+    bootloader: Option<Bootloader>,
+    device: Option<device::DeviceInfo>,
+}
+
+impl ProgrammerConfig {
+    /// Creates a [`ProgrammerConfig`] without any cached device information.
+    pub fn new(probe: Probe) -> Self {
+        Self::shared(Arc::new(probe))
+    }
+
+    /// Creates a [`ProgrammerConfig`] sharing a probe, without any cached
+    /// device information.
+    pub fn shared(probe: Arc<Probe>) -> Self {
+        Self {
+            probe,
+            bootloader: None,
+            device: None,
+        }
+    }
+
+    /// Gets probe of the configuration.
+    pub fn probe(&self) -> &Probe {
+        &self.probe
+    }
+
+    /// Gets cached bootloader information.
+    pub fn bootloader(&self) -> Option<&Bootloader> {
+        self.bootloader.as_ref()
+    }
+
+    /// Gets cached device, as identified by [`Programmer::identify_chip()`].
+    pub fn device(&self) -> Option<device::DeviceInfo> {
+        self.device
+    }
+}
+
+impl From<Probe> for ProgrammerConfig {
+    fn from(value: Probe) -> Self {
+        Self::new(value)
+    }
+}
+
+#[test]
+fn capturing_config() -> Result<()> {
+    let port = mock::MockPort::new(&[0x79, 0x01, 0x04, 0x15, 0x79]);
+    let mut programmer = Programmer::attach(Box::new(port), &Probe::new());
+    assert!(programmer.config().device().is_none());
+    programmer.identify_chip()?;
+    let config = programmer.config();
+    assert_eq!(config.device(), device::lookup(0x415));
+    assert!(config.bootloader().is_none());
+    Ok(())
+}
+
+#[test]
+fn framing_write() -> Result<()> {
+    let tests: &[(u32, &[u8], &[u8])] = &[
+        // word-aligned
+        (
+            0x0800_0000,
+            &[0x01, 0x02, 0x03, 0x04],
+            &[
+                0x08, 0x00, 0x00, 0x00, 0x08, 0x03, 0x01, 0x02, 0x03, 0x04, 0x07,
+            ],
+        ),
+        // misaligned, framed as is by the command itself
+        (
+            0x0800_0003,
+            &[0xaa, 0xbb, 0xcc],
+            &[0x08, 0x00, 0x00, 0x03, 0x0b, 0x02, 0xaa, 0xbb, 0xcc, 0xdf],
+        ),
+    ];
+    for (address, data, frames) in tests.iter().copied() {
+        let port = mock::MockPort::new(&[0x79, 0x79, 0x79]);
+        let mut programmer = Programmer::attach(Box::new(port.clone()), &Probe::new());
+        programmer.send_command(Command::Write {
+            address: address.into(),
+            data: data.try_into()?,
+        })?;
+        assert_eq!(port.written(), [&[0x31, 0xce], frames].concat());
+    }
+    Ok(())
+}
+
+#[test]
+fn framing_write_nack() -> Result<()> {
+    // The data frame is never sent once the address is rejected.
+    let port = mock::MockPort::new(&[0x79, 0x1f]);
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &Probe::new());
+    let result = programmer.write_memory(0x0800_0000u32, [0u8; 4].as_slice().try_into()?);
+    assert!(result.is_err_and(|e| e.is_nack()));
+    assert_eq!(port.written(), [0x31, 0xce, 0x08, 0x00, 0x00, 0x00, 0x08]);
+    Ok(())
+}
+
+#[test]
+fn safe_mode() -> Result<()> {
+    let port = mock::MockPort::new(&[0x79, 0x79, 0x79]);
+    let mut probe = Probe::new();
+    probe.set_safe_mode(true);
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &probe);
+    let result = programmer.write_memory(0x0800_0000u32, [0u8; 4].as_slice().try_into()?);
+    assert!(result.is_err_and(|e| e.is_safe_mode()));
+    assert!(port.written().is_empty());
+    Ok(())
+}
+
+#[test]
+fn framing_go() -> Result<()> {
+    // A third reply is left unread, since GO is acknowledged twice.
+    let port = mock::MockPort::new(&[0x79, 0x79, 0x79]);
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &Probe::new());
+    programmer.go(0x0800_0000)?;
+    assert_eq!(port.written(), [0x21, 0xde, 0x08, 0x00, 0x00, 0x00, 0x08]);
+    assert_eq!(port.bytes_to_read()?, 1);
+    Ok(())
+}
+
+#[test]
+fn loading_into_ram() -> Result<()> {
+    // F1 medium-density, with 20 KiB of RAM
+    let port = mock::MockPort::new(&[0x79, 0x01, 0x04, 0x10, 0x79]);
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &Probe::new());
+    let result = programmer.load_and_run_ram(0x2000_4ff0, &[0xa5; 0x20]);
+    assert!(result.is_err_and(|e| e.is_out_of_range()));
+    assert_eq!(port.written(), [0x02, 0xfd]);
+
+    // Unknown device, within SRAM
+    let port = mock::MockPort::new(&[0x79, 0x01, 0x09, 0x99, 0x79, 0x79, 0x79, 0x79, 0x79, 0x79]);
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &Probe::new());
+    programmer.load_and_run_ram(0x2000_4ff0, &[0xa5; 0x20])?;
+    assert_eq!(
+        port.written()[port.written().len() - 7..],
+        [0x21, 0xde, 0x20, 0x00, 0x4f, 0xf0, 0x9f]
+    );
+    Ok(())
+}
+
+#[test]
+fn identifying() -> Result<()> {
+    let port = mock::MockPort::new(&[0x79]);
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &Probe::new());
+    programmer.identify()?;
+    assert_eq!(port.written(), [0x7f]);
+    // Stale bytes are discarded before and after synchronizing.
+    assert_eq!(port.clears(), [ClearBuffer::All; 2]);
+
+    let mut get = vec![0x79, 0x0b, 0x31];
+    get.extend([
+        0x00, 0x01, 0x02, 0x11, 0x21, 0x31, 0x43, 0x63, 0x73, 0x82, 0x92,
+    ]);
+    get.push(0x79);
+    let port = mock::MockPort::new(&get);
+    let mut probe = Probe::new();
+    probe.set_identify(Identify::Get);
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &probe);
+    programmer.identify()?;
+    assert_eq!(port.written(), [0x00, 0xff]);
+    assert!(programmer.bootloader().is_some());
+    Ok(())
+}
+
+#[test]
+fn reading_partially() -> Result<()> {
+    // The first READ succeeds, while the second one is refused.
+    let mut replies = vec![0x79, 0x79, 0x79];
+    replies.extend([0xaa; 256]);
+    replies.push(0x1f);
+    let port = mock::MockPort::new(&replies);
+    let mut programmer = Programmer::attach(Box::new(port), &Probe::new());
+    let result = programmer.read_memory(0x0800_0000, 300);
+    assert!(matches!(result, Err(Error::PartialRead { read: 256, .. })));
+    Ok(())
+}
+
+#[test]
+fn reading_until_nack() -> Result<()> {
+    let port = mock::MockPort::device(mock::Faults::default());
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &Probe::new());
+    let data: Vec<u8> = (0..0x400).map(|i| i as u8).collect();
+    programmer.write_memory_chunked(0x0800_fc00, &data)?;
+
+    // Up to the end of flash memory
+    assert_eq!(programmer.read_until_nack(0x0800_fc00, 256)?, data);
+    // Flash memory ends partway through the second chunk, which is dropped.
+    assert_eq!(
+        programmer.read_until_nack(0x0800_fe80, 256)?,
+        data[0x280..0x380]
+    );
+    // Nothing read at all
+    assert!(programmer.read_until_nack(0x0900_0000, 256)?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn writing_chunked() -> Result<()> {
+    let data = (0..600).map(|i| i as u8).collect::<Vec<_>>();
+    let port = mock::MockPort::new(&[0x79; 9]);
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &Probe::new());
+    programmer.write_memory_chunked(0x0800_0000, &data)?;
+    let mut frames = Vec::new();
+    for (address, chunk) in [0x0800_0000u32, 0x0800_0100, 0x0800_0200]
+        .into_iter()
+        .zip(data.chunks(256))
+    {
+        let address = address.to_be_bytes();
+        let size = (chunk.len() - 1) as u8;
+        frames.extend([0x31, 0xce]);
+        frames.extend(address);
+        frames.push(address.iter().fold(0, |a, b| a ^ b));
+        frames.push(size);
+        frames.extend(chunk);
+        frames.push(chunk.iter().fold(size, |a, b| a ^ b));
+    }
+    assert_eq!(port.written(), frames);
+
+    let result = programmer.write_memory_chunked(0x0800_0002, &data);
+    assert!(result.is_err_and(|e| e.is_alignment()));
+    Ok(())
+}
+
+#[test]
+fn writing_verified() -> Result<()> {
+    let data = [0x12, 0x34, 0x56, 0x78];
+    // WRITE is acknowledged thrice, then READ thrice before the data.
+    let replies = |read: &[u8]| [&[0x79; 6][..], read].concat();
+
+    let port = mock::MockPort::new(&replies(&data));
+    let mut programmer = Programmer::attach(Box::new(port), &Probe::new());
+    programmer.write_verify(0x0800_0000, &data)?;
+
+    let port = mock::MockPort::new(&replies(&[0x12, 0x34, 0xa9, 0x78]));
+    let mut programmer = Programmer::attach(Box::new(port), &Probe::new());
+    let result = programmer.write_verify(0x0800_0000, &data);
+    assert!(matches!(
+        result,
+        Err(Error::VerifyMismatch {
+            address: 0x0800_0002,
+            expected: 0x56,
+            found: 0xa9,
+        })
+    ));
+
+    // READ refused under readout protection
+    let port = mock::MockPort::new(&[0x79, 0x79, 0x79, 0x1f]);
+    let mut programmer = Programmer::attach(Box::new(port), &Probe::new());
+    let result = programmer.write_verify(0x0800_0000, &data);
+    assert!(result.is_err_and(|e| e.is_nack()));
+
+    // Verified by GET_CHECKSUM, without reading back
+    let crc = stm32_crc32(&[0x7856_3412]).to_be_bytes();
+    let mut replies = vec![0x79, 0x0c, 0x33, 0x00, 0x01, 0x02, 0x11, 0x21, 0x31, 0x44];
+    replies.extend([0x63, 0x73, 0x82, 0x92, 0xa1, 0x79]);
+    replies.extend([0x79; 3 + 6]);
+    replies.extend(crc);
+    replies.push(crc.iter().fold(0, |a, b| a ^ b));
+    let port = mock::MockPort::new(&replies);
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &Probe::new());
+    programmer.bootloader_or_read()?;
+    programmer.write_verify(0x0800_0000, &data)?;
+    assert!(!port.written().windows(2).any(|w| w == [0x11, 0xee]));
+    Ok(())
+}
+
+#[test]
+fn reporting_progress() -> Result<()> {
+    use std::collections::hash_map::DefaultHasher;
+
+    let data = [0xa5; 600];
+    let port = mock::MockPort::device(mock::Faults::default());
+    let mut programmer = Programmer::attach(Box::new(port), &Probe::new());
+    let mut reports = Vec::new();
+    programmer.write_memory_chunked_with_progress(
+        0x0800_0000,
+        &data,
+        Some(&mut |progress| reports.push(progress)),
+    )?;
+    assert_eq!(
+        reports,
+        [
+            Progress::new(256, 600),
+            Progress::new(512, 600),
+            Progress::new(600, 600)
+        ]
+    );
+
+    let mut count = 0;
+    let read = programmer.read_memory_with_progress(
+        0x0800_0000,
+        600,
+        Some(&mut |progress| {
+            count += 1;
+            assert_eq!(progress.total(), 600);
+        }),
+    )?;
+    assert_eq!(read, data);
+    assert_eq!(count, 3);
+
+    let mut reports = Vec::new();
+    let mut hasher = DefaultHasher::new();
+    let hash = programmer.read_hashed_with_progress(
+        0x0800_0000,
+        600,
+        &mut hasher,
+        Some(&mut |progress| reports.push(progress.done())),
+    )?;
+    let mut expected = DefaultHasher::new();
+    for chunk in data.chunks(256) {
+        expected.write(chunk);
+    }
+    assert_eq!(hash, expected.finish());
+    assert_eq!(reports, [256, 512, 600]);
+    Ok(())
+}
+
+#[test]
+fn receiving_reliably() -> Result<()> {
+    let port = mock::MockPort::new(&[0x79, 0x01, 0x04, 0x10, 0x79]);
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &Probe::new());
+    assert_eq!(programmer.read_id()?.as_u16(), 0x0410);
+    // The trailing ACK of the device is consumed, and nothing is sent back.
+    assert_eq!(port.written(), [0x02, 0xfd]);
+
+    let port = mock::MockPort::new(&[0x79, 0x01, 0x04, 0x10, 0x1f]);
+    let mut programmer = Programmer::attach(Box::new(port), &Probe::new());
+    assert!(programmer.read_id().is_err_and(|e| e.is_nack()));
+    Ok(())
+}
+
+#[test]
+fn detecting_baudrate() -> Result<()> {
+    let port = mock::MockPort::only_at(&[0x79], 57_600);
+    let programmer = Programmer::autobaud_attached(
+        Box::new(port.clone()),
+        &Probe::new(),
+        &[9_600, 57_600, 115_200],
+    )?;
+    assert_eq!(programmer.probe().baudrate(), 57_600);
+    assert_eq!(port.baud_rate()?, 57_600);
+
+    let port = mock::MockPort::only_at(&[0x79], 38_400);
+    let result = Programmer::autobaud_attached(Box::new(port), &Probe::new(), &[]);
+    assert!(result.is_err_and(|e| e.is_unidentified()));
+    Ok(())
+}
+
+#[test]
+fn querying_actual_baudrate() -> Result<()> {
+    let port = mock::MockPort::new(&[0x79]);
+    port.set_max_baud_rate(115_200);
+    let mut builder = Probe::builder();
+    builder.baudrate(921_600);
+    let probe = builder.build();
+    let mut inner: Box<dyn SerialPort> = Box::new(port.clone());
+    inner.set_baud_rate(probe.baudrate())?;
+    let programmer = Programmer::attach(inner, &probe);
+    assert_eq!(programmer.actual_baudrate()?, 115_200);
+
+    // A clamped candidate is identified at the rate actually set.
+    let programmer = Programmer::autobaud_attached(Box::new(port), &probe, &[230_400])?;
+    assert_eq!(programmer.probe().baudrate(), 115_200);
+    assert_eq!(programmer.actual_baudrate()?, 115_200);
+    Ok(())
+}
+
+#[test]
+fn configuring_port() {
+    let mut probe = Probe::new();
+    let expected = serialport::new("/dev/ttyUSB0", 115_200)
+        .data_bits(DataBits::Eight)
+        .parity(Parity::Even)
+        .stop_bits(StopBits::One)
+        .flow_control(FlowControl::None)
+        .timeout(probe.timeout());
+    assert_eq!(Programmer::port_builder("/dev/ttyUSB0", &probe), expected);
+
+    probe.set_parity(Parity::None);
+    probe.set_stop_bits(StopBits::Two);
+    let expected = expected.parity(Parity::None).stop_bits(StopBits::Two);
+    assert_eq!(Programmer::port_builder("/dev/ttyUSB0", &probe), expected);
+}
+
+#[test]
+fn reading_bootloader_and_id() -> Result<()> {
+    let mut replies = vec![0x79, 0x0b, 0x31];
+    replies.extend([
+        0x00, 0x01, 0x02, 0x11, 0x21, 0x31, 0x44, 0x63, 0x73, 0x82, 0x92,
+    ]);
+    replies.extend([0x79, 0x79, 0x01, 0x04, 0x13, 0x79]);
+    let port = mock::MockPort::new(&replies);
+    // Replies arrive byte by byte, which reads must put together.
+    port.set_read_limit(1);
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &Probe::new());
+    let bootloader = programmer.read_bootloader()?;
+    assert!(bootloader.supports(Opcode::EXTENDED_ERASE));
+    assert!(!bootloader.supports(Opcode::ERASE));
+    assert_eq!(programmer.read_id()?.as_u16(), 0x0413);
+    assert_eq!(port.written(), [0x00, 0xff, 0x02, 0xfd]);
+
+    // The device goes silent in the middle of the ID.
+    let port = mock::MockPort::new(&[0x79, 0x01, 0x04, 0x13, 0x79]);
+    port.stall_at(2);
+    let mut programmer = Programmer::attach(Box::new(port), &Probe::new());
+    let result = programmer.read_id();
+    assert!(result.is_err_and(|e| e.is_frame_error() || e.is_io_error()));
+    Ok(())
+}
+
+#[test]
+fn retrying_commands() -> Result<()> {
+    let mut builder = Probe::builder();
+    builder.command_retries(2);
+    let probe = builder.build();
+
+    // GET_ID is NAcked twice, then succeeds.
+    let port = mock::MockPort::new(&[0x1f, 0x1f, 0x79, 0x01, 0x04, 0x13, 0x79]);
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &probe);
+    assert_eq!(programmer.read_id()?.as_u16(), 0x0413);
+    assert_eq!(port.written(), [0x02, 0xfd].repeat(3));
+
+    // Commands modifying the device are only sent once by default.
+    let port = mock::MockPort::new(&[0x1f, 0x79]);
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &probe);
+    let result = programmer.send_command(Command::Go(Address::from(0x0800_0000)));
+    assert!(result.is_err_and(|e| e.is_nack()));
+    assert_eq!(port.written(), [0x21, 0xde]);
+
+    let mut builder = ProbeBuilder::from(probe);
+    builder.retry_modifying(true);
+    let port = mock::MockPort::new(&[0x1f, 0x79, 0x79]);
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &builder.build());
+    programmer.send_command(Command::Go(Address::from(0x0800_0000)))?;
+    assert_eq!(port.written()[..4], [0x21, 0xde, 0x21, 0xde]);
+    Ok(())
+}
+
+#[test]
+fn writing_partially() -> Result<()> {
+    let data = [0xa5; 1024];
+    // Two chunks are written, then WRITE is refused.
+    let port = mock::MockPort::new(&[&[0x79; 6][..], &[0x1f]].concat());
+    let mut programmer = Programmer::attach(Box::new(port), &Probe::new());
+    let result = programmer.write_memory_chunked(0x0800_0000, &data);
+    match result {
+        Err(Error::PartialWrite { written, source }) => {
+            assert_eq!(written, 512);
+            assert!(source.is_nack());
+        }
+        result => panic!("unexpected {:?}", result),
+    }
+
+    // Nothing is written at all.
+    let port = mock::MockPort::new(&[0x1f]);
+    let mut programmer = Programmer::attach(Box::new(port), &Probe::new());
+    let result = programmer.write_memory_chunked(0x0800_0000, &data);
+    assert!(result.is_err_and(|e| e.is_nack()));
+    Ok(())
+}
+
+#[test]
+fn refusing_out_of_range() -> Result<()> {
+    use std::collections::hash_map::DefaultHasher;
+
+    let port = mock::MockPort::device(mock::Faults::default());
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &Probe::new());
+    let result = programmer.write_memory_chunked(0xffff_ff00, &[0xa5; 0x200]);
+    assert!(result.is_err_and(|e| e.is_out_of_range()));
+    let result = programmer.read_memory_hashed(0xffff_ff00, 0x200, &mut DefaultHasher::new());
+    assert!(result.is_err_and(|e| e.is_out_of_range()));
+    let result = programmer.write_verify(0xffff_ff00, &[0xa5; 0x200]);
+    assert!(result.is_err_and(|e| e.is_out_of_range()));
+    assert!(port.written().is_empty());
+
+    // The last byte of address space is still in range.
+    let result = programmer.read_memory(0xffff_ffff, 1);
+    assert!(result.is_err_and(|e| e.is_nack()));
+    Ok(())
+}
+
+#[test]
+fn aligning_writes() -> Result<()> {
+    let data = Data::try_from(&[0xa5; 8][..])?;
+    let port = mock::MockPort::new(&[0x79; 3]);
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &Probe::new());
+    assert_eq!(programmer.write_alignment(), 4);
+    let result = programmer.write_memory(0x0800_0002, data.clone());
+    assert!(matches!(
+        result,
+        Err(Error::Alignment {
+            address: 0x0800_0002
+        })
+    ));
+    assert!(port.written().is_empty());
+    programmer.write_memory(0x0800_0004, data.clone())?;
+
+    // L4 is written by double words.
+    let port = mock::MockPort::new(&[0x79, 0x01, 0x04, 0x15, 0x79]);
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &Probe::new());
+    programmer.identify_chip()?;
+    assert_eq!(programmer.write_alignment(), 8);
+    let result = programmer.write_memory(0x0800_0004, data);
+    assert!(result.is_err_and(|e| e.is_alignment()));
+    assert_eq!(port.written(), [0x02, 0xfd]);
+    Ok(())
+}
+
+#[test]
+fn reconnecting_after_reset() -> Result<()> {
+    let port = mock::MockPort::device(mock::Faults::default());
+    let mut builder = Probe::builder();
+    builder
+        .reset_for(Duration::ZERO)
+        .reconnect_delay(Duration::ZERO);
+    let probe = builder.build();
+    let mut programmer = Programmer::attach(Box::new(port), &probe);
+    programmer.write_memory(0x0800_0000u32, Data::try_from(&[0xa5; 4][..])?)?;
+
+    // The application started by GO doesn't reply anymore.
+    programmer.go(0x0800_0000)?;
+    assert!(programmer.read_id().is_err());
+
+    programmer.reconnect()?;
+    assert_eq!(programmer.read_id()?.as_u16(), 0x0410);
+    assert_eq!(programmer.read_memory(0x0800_0000, 4)?, [0xa5; 4]);
+    Ok(())
+}
+
+#[test]
+fn reconnecting_without_reset() -> Result<()> {
+    let mut scheme = SignalScheme::new();
+    scheme.set_reset(None);
+    scheme.set_boot(None);
+    let mut builder = Probe::builder();
+    builder
+        .signal_scheme(scheme)
+        .reconnect_delay(Duration::ZERO);
+    let mut probe = builder.build();
+    probe.set_max_attempts(2);
+
+    // A device reset by GO is only sent the synchronization byte.
+    let port = mock::MockPort::new(&[0x79]);
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &probe);
+    programmer.reconnect()?;
+    assert_eq!(port.written(), [0x7f]);
+
+    // A device still running the bootloader NAcks it, so the pending frame is
+    // aborted by padding, and GET checks that commands are aligned again.
+    let mut replies = vec![0x1f, 0x1f, 0x1f];
+    replies.extend([0x79, 0x02, 0x22, 0x00, 0x02, 0x79]);
+    replies.extend([0x79, 0x01, 0x04, 0x10, 0x79]);
+    let port = mock::MockPort::new(&replies);
+    port.stall_at(3);
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &probe);
+    programmer.reconnect()?;
+    assert_eq!(port.written(), [0x7f, 0x7f, 0xff, 0x00, 0xff]);
+    assert_eq!(programmer.read_id()?.as_u16(), 0x0410);
+    Ok(())
+}
+
+#[test]
+fn capturing_identify_failure() -> Result<()> {
+    let port = mock::MockPort::new(&[]);
+    let mut builder = Probe::builder();
+    builder.reset_for(Duration::ZERO);
+    let mut probe = builder.build();
+    probe.set_max_attempts(3);
+    let mut programmer = Programmer::attach(Box::new(port), &probe);
+    match programmer.handshake() {
+        Err(Error::Unidentified {
+            attempts: 3,
+            last: Some(last),
+            timed_out: false,
+        }) => assert!(last.is_io_error() || last.is_frame_error()),
+        result => panic!("unexpected result: {:?}", result.map(|_| ())),
+    }
+    Ok(())
+}
+
+#[test]
+fn limiting_identify_time() -> Result<()> {
+    let port = mock::MockPort::new(&[]);
+    let mut builder = Probe::builder();
+    builder
+        .reset_for(Duration::ZERO)
+        .max_identify_time(Duration::ZERO);
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &builder.build());
+    let result = programmer.handshake();
+    assert!(matches!(
+        result,
+        Err(Error::Unidentified {
+            attempts: 1,
+            timed_out: true,
+            ..
+        })
+    ));
+    // No attempt with the boot signal inverted once time is up
+    assert_eq!(port.written(), [0x7f]);
+    Ok(())
+}
+
+#[test]
+fn settling_after_reset() -> Result<()> {
+    use mock::Event;
+
+    let port = mock::MockPort::new(&[0x79]);
+    let mut builder = Probe::builder();
+    builder
+        .reset_for(Duration::from_millis(20))
+        .boot_settle(Duration::from_millis(30));
+    let probe = builder.build();
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &probe);
+    programmer.handshake()?;
+
+    let events = port.events();
+    let kinds: Vec<_> = events.iter().map(|(event, _)| *event).collect();
+    // Boot is held low, reset pulsed on RTS, then synchronized.
+    assert_eq!(
+        kinds,
+        [
+            Event::Dtr(false),
+            Event::Rts(false),
+            Event::Rts(true),
+            Event::Rts(false),
+            Event::Write,
+            Event::Dtr(true),
+        ]
+    );
+    let at = |i: usize| events[i].1;
+    assert!(at(3) - at(2) >= Duration::from_millis(20));
+    assert!(at(4) - at(3) >= Duration::from_millis(30));
+    Ok(())
+}
+
+#[test]
+fn diffing_regions() -> Result<()> {
+    let port = mock::MockPort::device(mock::Faults::default());
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &Probe::new());
+    programmer.write_memory_chunked(0x0800_0000, &[0xa5; 300])?;
+    programmer.write_memory_chunked(0x0800_0400, &[0xa5; 300])?;
+    programmer.write_memory_chunked(0x0800_0400 + 296, &[0x5a; 4])?;
+    assert_eq!(
+        programmer.diff_regions(0x0800_0000, 0x0800_0400, 300)?,
+        [296, 297, 298, 299]
+    );
+
+    // Refused before anything is sent
+    let written = port.written().len();
+    let result = programmer.diff_regions(0xffff_ff00, 0x0800_0000, 0x200);
+    assert!(result.is_err_and(|e| e.is_out_of_range()));
+    let result = programmer.diff_regions(0x0800_0000, 0xffff_ff00, 0x200);
+    assert!(result.is_err_and(|e| e.is_out_of_range()));
+    assert_eq!(port.written().len(), written);
+    Ok(())
+}
+
+#[test]
+fn overriding_timeouts() -> Result<()> {
+    let port = mock::MockPort::new(&[0x79, 0x01, 0x04, 0x10, 0x79].repeat(2));
+    let mut builder = Probe::builder();
+    builder
+        .ack_timeout(Duration::from_millis(100))
+        .data_timeout(Duration::from_millis(200));
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &builder.build());
+    let before = port.timeout();
+    let timeout = Duration::from_secs(7);
+    programmer.with_timeout(timeout, |p| p.read_id())?;
+    // Each reply is awaited with the override, then the timeout is restored.
+    assert_eq!(
+        port.timeouts(),
+        [
+            timeout, timeout, timeout, timeout, timeout, timeout, timeout, before
+        ]
+    );
+
+    let set = port.timeouts().len();
+    programmer.read_id()?;
+    assert_eq!(
+        port.timeouts()[set..],
+        [
+            Duration::from_millis(100),
+            before,
+            Duration::from_millis(200),
+            before,
+            Duration::from_millis(100),
+            before
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn reading_into_buffer() -> Result<()> {
+    let data: Vec<u8> = (0..1024).map(|i| i as u8).collect();
+    let port = mock::MockPort::device(mock::Faults::default());
+    let mut programmer = Programmer::attach(Box::new(port), &Probe::new());
+    programmer.write_memory_chunked(0x0800_0000, &data)?;
+
+    let mut buf = [0u8; 1024];
+    programmer.read_memory_into(0x0800_0000, &mut buf)?;
+    assert_eq!(buf[..], data[..]);
+
+    // Reading past the end of flash memory keeps what was read.
+    let mut buf = vec![0u8; 512];
+    let result = programmer.read_memory_into(0x0800_ff00, &mut buf);
+    assert!(matches!(result, Err(Error::PartialRead { read: 256, .. })));
+    Ok(())
+}
+
+#[test]
+fn synchronizing() -> Result<()> {
+    use serialport::SerialPort;
+
+    let port = mock::MockPort::new(&[0x79, 0x79]);
+    Programmer::attach_synchronized(Box::new(port.clone()), &Probe::new())?;
+    // Only the ACK of the synchronization byte is read.
+    assert_eq!(port.written(), [0x7f]);
+    assert_eq!(port.bytes_to_read()?, 1);
+
+    let port = mock::MockPort::new(&[0x1f]);
+    let mut programmer = Programmer::attach(Box::new(port), &Probe::new());
+    let result = programmer.synchronize();
+    assert!(matches!(
+        result,
+        Err(Error::Unidentified { attempts: 1, last: Some(ref last), .. }) if last.is_nack()
+    ));
+    Ok(())
+}
+
+#[test]
+fn tolerating_extra_ack() -> Result<()> {
+    let get = [0x79, 0x02, 0x31, 0x00, 0x02, 0x79];
+    let version = [0x79, 0x31, 0x00, 0x00, 0x79];
+    let id = [0x79, 0x01, 0x04, 0x13, 0x79];
+    let mut builder = Probe::builder();
+    builder.tolerate_extra_ack(true);
+    let probe = builder.build();
+
+    for extra in [&[0x79][..], &[]] {
+        let replies = [&get[..], extra, &version, extra, &id].concat();
+        let port = mock::MockPort::new(&replies);
+        if extra.is_empty() {
+            // Nothing arrives until the next command is sent.
+            port.stall_at(get.len());
+            port.stall_at(get.len() + version.len());
+        }
+        let mut programmer = Programmer::attach(Box::new(port), &probe);
+        assert_eq!(
+            programmer.read_bootloader()?.opcodes(),
+            [Opcode::GET, Opcode::GET_ID]
+        );
+        assert_eq!(programmer.read_version()?.version(), 0x31);
+        assert_eq!(programmer.read_id()?.as_u16(), 0x0413);
+    }
+    Ok(())
+}
+
+#[test]
+fn pipelining_writes() -> Result<()> {
+    use mock::Event;
+
+    let data: Vec<u8> = (0..600).map(|i| i as u8).collect();
+    let writes = |port: &mock::MockPort| {
+        port.events()
+            .iter()
+            .filter(|(event, _)| *event == Event::Write)
+            .count()
+    };
+
+    let port = mock::MockPort::device(mock::Faults::default());
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &Probe::new());
+    programmer.write_memory_chunked(0x0800_0000, &data)?;
+    assert_eq!(writes(&port), 3 * 3);
+
+    let port = mock::MockPort::device(mock::Faults::default());
+    let mut builder = Probe::builder();
+    builder.pipeline_writes(true);
+    let probe = builder.build();
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &probe);
+    programmer.write_memory_chunked(0x0800_0000, &data)?;
+    assert_eq!(writes(&port), 3 * 2);
+    assert_eq!(programmer.read_memory(0x0800_0000, data.len())?, data);
+
+    // The data is never sent once the address is refused.
+    let port = mock::MockPort::new(&[0x79, 0x1f]);
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &probe);
+    let result = programmer.write_memory(0x0800_0000u32, Data::try_from(&[0xa5; 4][..])?);
+    assert!(result.is_err_and(|e| e.is_nack()));
+    assert_eq!(port.written(), [0x31, 0xce, 0x08, 0x00, 0x00, 0x00, 0x08]);
+
+    // Once the opcode is refused, the address taken as commands is NAcked,
+    // and replies are drained until GET succeeds again.
+    let mut replies = vec![0x1f, 0x1f, 0x1f];
+    replies.extend([0x79, 0x02, 0x22, 0x00, 0x02, 0x79]);
+    replies.extend([0x79, 0x01, 0x04, 0x10, 0x79]);
+    let port = mock::MockPort::new(&replies);
+    port.stall_at(3);
+    port.stall_at(3);
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &probe);
+    let result = programmer.write_memory(0x0800_0000u32, Data::try_from(&[0xa5; 4][..])?);
+    assert!(result.is_err_and(|e| e.is_nack()));
+    assert!(!port.written().contains(&0xa5));
+    assert_eq!(programmer.read_id()?.as_u16(), 0x0410);
+    Ok(())
+}
@@ -0,0 +1,544 @@
+use serialport::{ClearBuffer, DataBits, FlowControl, Parity, SerialPort, StopBits};
+use std::collections::VecDeque;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::RecordingHandle;
+use crate::crc::stm32_crc32_bytes;
+
+/// In-memory serial port replaying scripted replies and recording bytes sent
+///
+/// Created by [`MockPort::device()`], the port replies as an emulated
+/// bootloader instead, with flash memory backing READ and WRITE.
+///
+/// Clones share the same state, so a test keeps one to inspect the port after
+/// handing another to a programmer.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct MockPort {
+    state: Arc<Mutex<State>>,
+    written: RecordingHandle,
+}
+
+#[derive(Debug)]
+struct State {
+    replies: VecDeque<u8>,
+    timeout: Duration,
+    timeouts: Vec<Duration>,
+    device: Option<Device>,
+    baud_rate: u32,
+    // Replies only arrive at this baudrate, if any.
+    only_at: Option<u32>,
+    // Baudrates set above this are clamped to it, if any.
+    max_baud_rate: Option<u32>,
+    // Reply bytes read so far, and where reads time out once.
+    consumed: usize,
+    stalls: VecDeque<usize>,
+    read_limit: Option<usize>,
+    events: Vec<(Event, Instant)>,
+    clears: Vec<ClearBuffer>,
+}
+
+/// Change of a MODEM control line, or bytes written, with the time it happens
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Event {
+    Rts(bool),
+    Dtr(bool),
+    Write,
+}
+
+/// Bootloader emulated by [`MockPort::device()`], which supports GET, GET_ID,
+/// READ, WRITE, GO and ERASE like F1 parts, and GET_CHECKSUM once enabled by
+/// [`MockPort::support_checksum()`].
+///
+/// GO leaves the bootloader, so the device ignores everything until `RTS` is
+/// raised, which resets it back into the bootloader, as wired by
+/// [`crate::SignalScheme::rts_dtr_auto()`].
+#[derive(Debug)]
+struct Device {
+    flash: Vec<u8>,
+    // Bytes received but not handled yet, since a frame is incomplete.
+    input: Vec<u8>,
+    expect: Expect,
+    faults: Faults,
+    checksum: bool,
+}
+
+/// Faults injected into a [`Device`]
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct Faults {
+    /// WRITE at this address is refused with NACK.
+    pub(crate) refuse_write_at: Option<u32>,
+    /// Byte written at this address is stored inverted.
+    pub(crate) corrupt_at: Option<u32>,
+    /// ERASE of this page is refused with NACK, as if write-protected.
+    pub(crate) protected_page: Option<u8>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Expect {
+    Command,
+    Address(u8),
+    Size(u32),
+    Data(u32),
+    Erase,
+    // Parameters of GET_CHECKSUM: address, size, polynomial and initial value,
+    // each one framed like an address.
+    Checksum(u8, u32, u32),
+    // Reset, waiting for the synchronization byte.
+    Sync,
+    // Running the application after GO.
+    Running,
+}
+
+impl MockPort {
+    /// Creates a port replying with `replies` in order, then timing out.
+    pub(crate) fn new(replies: &[u8]) -> Self {
+        let port = Self::default();
+        port.state.lock().unwrap().replies.extend(replies);
+        port
+    }
+
+    /// Creates a port like [`Self::new()`], which only replies while set to
+    /// `baud_rate`, and times out otherwise.
+    pub(crate) fn only_at(replies: &[u8], baud_rate: u32) -> Self {
+        let port = Self::new(replies);
+        port.state.lock().unwrap().only_at = Some(baud_rate);
+        port
+    }
+
+    /// Makes a read time out once it reaches `offset` of the replies, as if
+    /// the device paused, while later reads carry on. Stalls are made in
+    /// ascending order.
+    pub(crate) fn stall_at(&self, offset: usize) {
+        self.state.lock().unwrap().stalls.push_back(offset);
+    }
+
+    /// Clamps baudrates set above `limit` to it, like adapters which only
+    /// support a few standard rates.
+    pub(crate) fn set_max_baud_rate(&self, limit: u32) {
+        self.state.lock().unwrap().max_baud_rate = Some(limit);
+    }
+
+    /// Limits bytes returned by each read, as if replies arrived piecemeal.
+    pub(crate) fn set_read_limit(&self, limit: usize) {
+        self.state.lock().unwrap().read_limit = Some(limit);
+    }
+
+    /// Flash memory of [`MockPort::device()`], with 1 KiB pages.
+    pub(crate) const FLASH: std::ops::Range<u32> = 0x0800_0000..0x0801_0000;
+
+    /// Flash size register of F1 parts, telling the size of [`Self::FLASH`].
+    pub(crate) const FLASH_SIZE: u32 = 0x1fff_f7e0;
+
+    /// Creates a port replying as a bootloader of an F1 part, with erased
+    /// flash memory at [`Self::FLASH`].
+    pub(crate) fn device(faults: Faults) -> Self {
+        let port = Self::default();
+        port.state.lock().unwrap().device = Some(Device {
+            flash: vec![0xff; Self::FLASH.len()],
+            input: Vec::new(),
+            expect: Expect::Command,
+            faults,
+            checksum: false,
+        });
+        port
+    }
+
+    /// Makes [`MockPort::device()`] support GET_CHECKSUM, computing the CRC
+    /// like the CRC unit of STM32 regardless of parameters.
+    pub(crate) fn support_checksum(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.device.as_mut().unwrap().checksum = true;
+    }
+
+    /// Gets flash memory of [`MockPort::device()`].
+    pub(crate) fn flash(&self) -> Vec<u8> {
+        let state = self.state.lock().unwrap();
+        state.device.as_ref().unwrap().flash.clone()
+    }
+
+    /// Gets bytes sent to the port so far.
+    pub(crate) fn written(&self) -> Vec<u8> {
+        self.written.bytes()
+    }
+
+    /// Gets line changes and writes so far, in order.
+    pub(crate) fn events(&self) -> Vec<(Event, Instant)> {
+        self.state.lock().unwrap().events.clone()
+    }
+
+    /// Gets buffers cleared on the port so far, in order.
+    pub(crate) fn clears(&self) -> Vec<ClearBuffer> {
+        self.state.lock().unwrap().clears.clone()
+    }
+
+    /// Gets timeouts set on the port so far, in order.
+    pub(crate) fn timeouts(&self) -> Vec<Duration> {
+        self.state.lock().unwrap().timeouts.clone()
+    }
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            replies: VecDeque::new(),
+            timeout: Duration::ZERO,
+            timeouts: Vec::new(),
+            device: None,
+            baud_rate: 115_200,
+            only_at: None,
+            max_baud_rate: None,
+            consumed: 0,
+            stalls: VecDeque::new(),
+            read_limit: None,
+            events: Vec::new(),
+            clears: Vec::new(),
+        }
+    }
+}
+
+const ACK: u8 = 0x79;
+const NACK: u8 = 0x1f;
+
+impl Device {
+    /// Handles the next frame received, returning the reply, or [`None`] if
+    /// the frame is incomplete.
+    fn step(&mut self) -> Option<Vec<u8>> {
+        let (len, reply) = match self.expect {
+            Expect::Command => match self.input.as_slice() {
+                [0x7f, ..] => (1, vec![ACK]),
+                [opcode, complement, ..] if opcode ^ complement == 0xff => {
+                    (2, self.command(*opcode))
+                }
+                [_, _, ..] => (2, vec![NACK]),
+                _ => return None,
+            },
+            Expect::Address(opcode) => {
+                let frame = self.input.get(..5)?;
+                self.expect = Expect::Command;
+                if frame.iter().fold(0, |a, b| a ^ b) != 0 {
+                    (5, vec![NACK])
+                } else {
+                    let address = u32::from_be_bytes(frame[..4].try_into().unwrap());
+                    self.expect = match opcode {
+                        0x11 => Expect::Size(address),
+                        0x21 => Expect::Running,
+                        0x31 => Expect::Data(address),
+                        _ => Expect::Command,
+                    };
+                    (5, vec![ACK])
+                }
+            }
+            Expect::Size(address) => {
+                let frame = self.input.get(..2)?;
+                self.expect = Expect::Command;
+                let size = frame[0] as usize + 1;
+                let flash_size = ((MockPort::FLASH.len() / 1024) as u16).to_le_bytes();
+                match self.locate(address, size) {
+                    _ if address == MockPort::FLASH_SIZE
+                        && size <= 2
+                        && frame[0] ^ frame[1] == 0xff =>
+                    {
+                        let mut reply = vec![ACK];
+                        reply.extend(&flash_size[..size]);
+                        (2, reply)
+                    }
+                    Some(range) if frame[0] ^ frame[1] == 0xff => {
+                        let mut reply = vec![ACK];
+                        reply.extend(&self.flash[range]);
+                        (2, reply)
+                    }
+                    _ => (2, vec![NACK]),
+                }
+            }
+            Expect::Data(address) => {
+                let size = *self.input.first()? as usize + 1;
+                let frame = self.input.get(..size + 2)?;
+                self.expect = Expect::Command;
+                let (data, checksum) = frame[1..].split_at(size);
+                let valid = data.iter().fold(frame[0], |a, b| a ^ b) == checksum[0];
+                match self.locate(address, size) {
+                    Some(_) if self.faults.refuse_write_at == Some(address) => {
+                        (size + 2, vec![NACK])
+                    }
+                    Some(range) if valid => {
+                        let start = range.start;
+                        self.flash[range].copy_from_slice(data);
+                        if let Some(corrupt) = self.faults.corrupt_at
+                            && let Some(i) = self.locate(corrupt, 1)
+                            && (start..start + size).contains(&i.start)
+                        {
+                            self.flash[i.start] ^= 0xff;
+                        }
+                        (size + 2, vec![ACK])
+                    }
+                    _ => (size + 2, vec![NACK]),
+                }
+            }
+            Expect::Erase => {
+                let len = match self.input.as_slice() {
+                    [0xff, 0x00, ..] => 2,
+                    [n, ..] => *n as usize + 3,
+                    [] => return None,
+                };
+                let frame = self.input.get(..len)?;
+                self.expect = Expect::Command;
+                let pages = &frame[1..len - 1];
+                if len > 2
+                    && let Some(page) = self.faults.protected_page
+                    && pages.contains(&page)
+                {
+                    self.input.drain(..len);
+                    return Some(vec![NACK]);
+                }
+                if len == 2 {
+                    self.flash.fill(0xff);
+                } else {
+                    for &page in pages {
+                        let start = (page as usize * 1024).min(self.flash.len());
+                        let end = (start + 1024).min(self.flash.len());
+                        self.flash[start..end].fill(0xff);
+                    }
+                }
+                (len, vec![ACK])
+            }
+            Expect::Checksum(parameter, address, size) => {
+                let frame = self.input.get(..5)?;
+                self.expect = Expect::Command;
+                let value = u32::from_be_bytes(frame[..4].try_into().unwrap());
+                let reply = if frame.iter().fold(0, |a, b| a ^ b) != 0 {
+                    vec![NACK]
+                } else if parameter < 3 {
+                    self.expect = match parameter {
+                        0 => Expect::Checksum(1, value, 0),
+                        1 => Expect::Checksum(2, address, value),
+                        _ => Expect::Checksum(3, address, size),
+                    };
+                    vec![ACK]
+                } else {
+                    match self.locate(address, size as usize) {
+                        Some(range) => {
+                            let crc = stm32_crc32_bytes(&self.flash[range]).to_be_bytes();
+                            let mut reply = vec![ACK, ACK];
+                            reply.extend(crc);
+                            reply.push(crc.iter().fold(0, |a, b| a ^ b));
+                            reply
+                        }
+                        None => vec![ACK, NACK],
+                    }
+                };
+                (5, reply)
+            }
+            Expect::Sync => {
+                let skipped = self.input.iter().take_while(|&&b| b != 0x7f).count();
+                self.input.drain(..skipped);
+                self.input.first()?;
+                self.expect = Expect::Command;
+                (1, vec![ACK])
+            }
+            Expect::Running => {
+                self.input.clear();
+                return None;
+            }
+        };
+        self.input.drain(..len);
+        Some(reply)
+    }
+
+    fn command(&mut self, opcode: u8) -> Vec<u8> {
+        match opcode {
+            0x00 if self.checksum => vec![
+                ACK, 0x08, 0x22, 0x00, 0x01, 0x02, 0x11, 0x21, 0x31, 0x43, 0xa1, ACK,
+            ],
+            0x00 => vec![
+                ACK, 0x07, 0x22, 0x00, 0x01, 0x02, 0x11, 0x21, 0x31, 0x43, ACK,
+            ],
+            0x02 => vec![ACK, 0x01, 0x04, 0x10, ACK],
+            0x11 | 0x21 | 0x31 => {
+                self.expect = Expect::Address(opcode);
+                vec![ACK]
+            }
+            0x43 => {
+                self.expect = Expect::Erase;
+                vec![ACK]
+            }
+            0xa1 if self.checksum => {
+                self.expect = Expect::Checksum(0, 0, 0);
+                vec![ACK]
+            }
+            _ => vec![NACK],
+        }
+    }
+
+    /// Locates a region within flash memory.
+    fn locate(&self, address: u32, size: usize) -> Option<std::ops::Range<usize>> {
+        let start = address.checked_sub(MockPort::FLASH.start)? as usize;
+        (start + size <= self.flash.len()).then_some(start..start + size)
+    }
+}
+
+impl io::Read for MockPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut state = self.state.lock().unwrap();
+        if state.replies.is_empty() || state.only_at.is_some_and(|b| b != state.baud_rate) {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        if state.stalls.front() == Some(&state.consumed) {
+            state.stalls.pop_front();
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        let mut n = buf.len().min(state.replies.len());
+        if let Some(limit) = state.read_limit {
+            n = n.min(limit);
+        }
+        if let Some(&stall) = state.stalls.front() {
+            n = n.min(stall - state.consumed);
+        }
+        state.consumed += n;
+        for (b, r) in buf.iter_mut().zip(state.replies.drain(..n)) {
+            *b = r;
+        }
+        Ok(n)
+    }
+}
+
+impl io::Write for MockPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+        self.written.record(buf);
+        state.events.push((Event::Write, Instant::now()));
+        if let Some(device) = state.device.as_mut() {
+            device.input.extend_from_slice(buf);
+            while let Some(reply) = device.step() {
+                state.replies.extend(reply);
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SerialPort for MockPort {
+    fn name(&self) -> Option<String> {
+        Some("mock".into())
+    }
+
+    fn baud_rate(&self) -> serialport::Result<u32> {
+        Ok(self.state.lock().unwrap().baud_rate)
+    }
+
+    fn data_bits(&self) -> serialport::Result<DataBits> {
+        Ok(DataBits::Eight)
+    }
+
+    fn flow_control(&self) -> serialport::Result<FlowControl> {
+        Ok(FlowControl::None)
+    }
+
+    fn parity(&self) -> serialport::Result<Parity> {
+        Ok(Parity::Even)
+    }
+
+    fn stop_bits(&self) -> serialport::Result<StopBits> {
+        Ok(StopBits::One)
+    }
+
+    fn timeout(&self) -> Duration {
+        self.state.lock().unwrap().timeout
+    }
+
+    fn set_baud_rate(&mut self, baud_rate: u32) -> serialport::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.baud_rate = state
+            .max_baud_rate
+            .map_or(baud_rate, |max| baud_rate.min(max));
+        Ok(())
+    }
+
+    fn set_data_bits(&mut self, _: DataBits) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn set_flow_control(&mut self, _: FlowControl) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn set_parity(&mut self, _: Parity) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn set_stop_bits(&mut self, _: StopBits) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> serialport::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.timeout = timeout;
+        state.timeouts.push(timeout);
+        Ok(())
+    }
+
+    fn write_request_to_send(&mut self, level: bool) -> serialport::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.events.push((Event::Rts(level), Instant::now()));
+        if level && let Some(device) = state.device.as_mut() {
+            device.input.clear();
+            device.expect = Expect::Sync;
+        }
+        Ok(())
+    }
+
+    fn write_data_terminal_ready(&mut self, level: bool) -> serialport::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.events.push((Event::Dtr(level), Instant::now()));
+        Ok(())
+    }
+
+    fn read_clear_to_send(&mut self) -> serialport::Result<bool> {
+        Ok(false)
+    }
+
+    fn read_data_set_ready(&mut self) -> serialport::Result<bool> {
+        Ok(false)
+    }
+
+    fn read_ring_indicator(&mut self) -> serialport::Result<bool> {
+        Ok(false)
+    }
+
+    fn read_carrier_detect(&mut self) -> serialport::Result<bool> {
+        Ok(false)
+    }
+
+    fn bytes_to_read(&self) -> serialport::Result<u32> {
+        Ok(self.state.lock().unwrap().replies.len() as u32)
+    }
+
+    fn bytes_to_write(&self) -> serialport::Result<u32> {
+        Ok(0)
+    }
+
+    fn clear(&self, buffer: ClearBuffer) -> serialport::Result<()> {
+        // Scripted replies are yet to arrive, so there's nothing to discard,
+        // but tests may check what is cleared.
+        self.state.lock().unwrap().clears.push(buffer);
+        Ok(())
+    }
+
+    fn try_clone(&self) -> serialport::Result<Box<dyn SerialPort>> {
+        Ok(Box::new(self.clone()))
+    }
+
+    fn set_break(&self) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn clear_break(&self) -> serialport::Result<()> {
+        Ok(())
+    }
+}
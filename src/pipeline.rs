@@ -0,0 +1,208 @@
+use crate::{Error, Programmer, Result};
+use log::debug;
+
+/// Operation queued in a [`Pipeline`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PipelineStep {
+    /// Erases the whole flash memory, see [`Programmer::erase_all()`].
+    EraseAll,
+    /// Erases specific pages, see [`Programmer::erase_pages()`].
+    ErasePages(Vec<u16>),
+    /// Writes data at an address, see [`Programmer::write_memory_chunked()`].
+    Write { address: u32, data: Vec<u8> },
+    /// Reads memory back and compares it to data, failing with
+    /// [`Error::VerifyMismatch`] at the first difference.
+    Verify { address: u32, data: Vec<u8> },
+    /// Jumps to an address, see [`Programmer::go()`].
+    Go(u32),
+}
+
+/// Sequence of operations built by [`Programmer::pipeline()`], executed in
+/// order by [`Pipeline::run()`]
+#[derive(Debug)]
+pub struct Pipeline<'a> {
+    programmer: &'a mut Programmer,
+    steps: Vec<PipelineStep>,
+}
+
+impl<'a> Pipeline<'a> {
+    /// Queues erasing the whole flash memory.
+    pub fn erase_all(&mut self) -> &mut Self {
+        self.step(PipelineStep::EraseAll)
+    }
+
+    /// Queues erasing specific pages.
+    pub fn erase_pages(&mut self, pages: impl Into<Vec<u16>>) -> &mut Self {
+        self.step(PipelineStep::ErasePages(pages.into()))
+    }
+
+    /// Queues writing data at an address.
+    pub fn write(&mut self, address: u32, data: impl Into<Vec<u8>>) -> &mut Self {
+        self.step(PipelineStep::Write {
+            address,
+            data: data.into(),
+        })
+    }
+
+    /// Queues verifying memory at an address against data.
+    pub fn verify(&mut self, address: u32, data: impl Into<Vec<u8>>) -> &mut Self {
+        self.step(PipelineStep::Verify {
+            address,
+            data: data.into(),
+        })
+    }
+
+    /// Queues jumping to an address, which leaves the bootloader, so it's
+    /// usually the last step.
+    pub fn go(&mut self, address: u32) -> &mut Self {
+        self.step(PipelineStep::Go(address))
+    }
+
+    /// Queues any step.
+    pub fn step(&mut self, step: PipelineStep) -> &mut Self {
+        self.steps.push(step);
+        self
+    }
+
+    /// Gets steps queued so far.
+    pub fn steps(&self) -> &[PipelineStep] {
+        &self.steps
+    }
+
+    /// Runs steps in order, stopping at the first one failing.
+    ///
+    /// Steps after a failed one are not run, and are missing from the
+    /// report.
+    pub fn run(&mut self) -> PipelineReport {
+        let mut results = Vec::with_capacity(self.steps.len());
+        for (i, step) in self.steps.iter().enumerate() {
+            debug!("running step {}: {}", i, step);
+            let result = match step {
+                PipelineStep::EraseAll => self.programmer.erase_all(),
+                PipelineStep::ErasePages(pages) => self.programmer.erase_pages(pages),
+                PipelineStep::Write { address, data } => {
+                    self.programmer.write_memory_chunked(*address, data)
+                }
+                PipelineStep::Verify { address, data } => {
+                    self.programmer.compare_memory(*address, data)
+                }
+                PipelineStep::Go(address) => self.programmer.go(*address),
+            };
+            let failed = result.is_err();
+            results.push((step.clone(), result));
+            if failed {
+                break;
+            }
+        }
+        PipelineReport { results }
+    }
+}
+
+/// Describes a step without its data, which may be large.
+impl std::fmt::Display for PipelineStep {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::EraseAll => write!(f, "erase all"),
+            Self::ErasePages(pages) => write!(f, "erase {} pages", pages.len()),
+            Self::Write { address, data } => {
+                write!(f, "write {} bytes at {:#010x}", data.len(), address)
+            }
+            Self::Verify { address, data } => {
+                write!(f, "verify {} bytes at {:#010x}", data.len(), address)
+            }
+            Self::Go(address) => write!(f, "go to {:#010x}", address),
+        }
+    }
+}
+
+/// Results of [`Pipeline::run()`], one for each step run
+#[derive(Debug)]
+pub struct PipelineReport {
+    results: Vec<(PipelineStep, Result<()>)>,
+}
+
+impl PipelineReport {
+    /// Gets steps run along with their results, in order.
+    pub fn results(&self) -> &[(PipelineStep, Result<()>)] {
+        &self.results
+    }
+
+    /// Whether every step run succeeded.
+    ///
+    /// The last step run is the only one that may fail, since the pipeline
+    /// stops there.
+    pub fn is_success(&self) -> bool {
+        self.failed().is_none()
+    }
+
+    /// Gets the step failing along with its error, if any.
+    pub fn failed(&self) -> Option<(&PipelineStep, &Error)> {
+        self.results
+            .last()
+            .and_then(|(step, result)| result.as_ref().err().map(|e| (step, e)))
+    }
+
+    /// Converts the report into the error of the step failing, if any.
+    pub fn into_result(self) -> Result<()> {
+        match self.results.into_iter().last() {
+            Some((_, Err(e))) => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Programmer {
+    /// Creates a pipeline to queue operations, e.g. erasing, writing and
+    /// verifying an image, and run them at once.
+    ///
+    /// Steps just call the methods of the programmer in order.
+    pub fn pipeline(&mut self) -> Pipeline<'_> {
+        Pipeline {
+            programmer: self,
+            steps: Vec::new(),
+        }
+    }
+}
+
+#[test]
+fn running_pipeline() -> Result<()> {
+    use crate::Probe;
+    use crate::mock::{Faults, MockPort};
+
+    let data: Vec<u8> = (0..300).map(|i| i as u8).collect();
+    let port = MockPort::device(Faults::default());
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &Probe::new());
+    let report = programmer
+        .pipeline()
+        .erase_pages([0])
+        .write(0x0800_0000, data.clone())
+        .verify(0x0800_0000, data.clone())
+        .run();
+    assert!(report.is_success());
+    assert_eq!(report.results().len(), 3);
+    assert_eq!(port.flash()[..300], data[..]);
+
+    // Stops at the write refused, without verifying.
+    let faults = Faults {
+        refuse_write_at: Some(0x0800_0100),
+        ..Default::default()
+    };
+    let port = MockPort::device(faults);
+    let mut programmer = Programmer::attach(Box::new(port), &Probe::new());
+    let report = programmer
+        .pipeline()
+        .erase_all()
+        .write(0x0800_0000, data.clone())
+        .verify(0x0800_0000, data)
+        .run();
+    assert_eq!(report.results().len(), 2);
+    assert!(matches!(
+        report.failed(),
+        Some((
+            PipelineStep::Write { .. },
+            Error::PartialWrite { written: 256, .. }
+        ))
+    ));
+    assert!(report.into_result().is_err_and(|e| e.is_partial_write()));
+    Ok(())
+}
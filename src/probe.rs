@@ -1,6 +1,11 @@
-use std::time::Duration;
 #[allow(unused_imports)]
 use crate::Command;
+use crate::protocol::AddressWidth;
+use serialport::{Parity, StopBits};
+use std::time::Duration;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 pub type Baudrate = u32;
 
@@ -11,7 +16,13 @@ pub type Baudrate = u32;
 /// These signals are not particularly common nowadays, and their usage has
 /// changed compared to before. They're generally treated as GPIOs that can be
 /// controlled by the DTE (Data Terminal Equipment).
+///
+/// A signal is written as its name, prefixed by `!` if active low, e.g.
+/// `"rts"` or `"!dtr"`, which is also how it's serialized with the `serde`
+/// feature.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(into = "String", try_from = "String"))]
 pub enum Signal {
     /// Request To Send
     Rts { active_when: bool },
@@ -50,10 +61,98 @@ impl Signal {
     /// Converts a bool value to the raw one based on [`Self::active_when()`].
     pub fn raw_level(&self, active: bool) -> bool {
         // if the signal is "active high", then just pass through the value
-        if self.active_when() == true {
-            active
-        } else {
-            !active
+        if self.active_when() { active } else { !active }
+    }
+}
+
+impl std::fmt::Display for Signal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if !self.active_when() {
+            write!(f, "!")?;
+        }
+        match self {
+            Self::Rts { .. } => write!(f, "rts"),
+            Self::Dtr { .. } => write!(f, "dtr"),
+        }
+    }
+}
+
+/// Error parsing a [`Signal`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseSignalError(String);
+
+impl std::fmt::Display for ParseSignalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "incorrect signal format: {}", self.0)
+    }
+}
+
+impl std::error::Error for ParseSignalError {}
+
+impl std::str::FromStr for Signal {
+    type Err = ParseSignalError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // The prefix "!" indicates "active low".
+        let (name, active_when) = match s.strip_prefix('!') {
+            Some(name) => (name, false),
+            None => (s, true),
+        };
+        match name {
+            "rts" => Ok(Self::rts(active_when)),
+            "dtr" => Ok(Self::dtr(active_when)),
+            _ => Err(ParseSignalError(s.to_owned())),
+        }
+    }
+}
+
+impl From<Signal> for String {
+    fn from(value: Signal) -> Self {
+        value.to_string()
+    }
+}
+
+impl TryFrom<String> for Signal {
+    type Error = ParseSignalError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+/// Serializes durations as milliseconds, which is what configurations use.
+#[cfg(feature = "serde")]
+mod millis {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(duration.as_millis() as u64)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_millis)
+    }
+
+    /// Serializes optional durations as milliseconds, or nothing.
+    pub mod option {
+        use serde::{Deserialize, Deserializer, Serializer};
+        use std::time::Duration;
+
+        pub fn serialize<S: Serializer>(
+            duration: &Option<Duration>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            match duration {
+                Some(duration) => serializer.serialize_some(&(duration.as_millis() as u64)),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Option<Duration>, D::Error> {
+            Option::<u64>::deserialize(deserializer).map(|ms| ms.map(Duration::from_millis))
         }
     }
 }
@@ -67,6 +166,7 @@ impl Signal {
 /// The signal scheme varies; there might be vendor-specific standards on it,
 /// but it tends to be more board-specific.
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct SignalScheme {
     reset: Option<Signal>,
     boot: Option<Signal>,
@@ -74,19 +174,54 @@ pub struct SignalScheme {
 
 impl Default for SignalScheme {
     fn default() -> Self {
-        Self {
-            reset: Some(Signal::Rts { active_when: true }),
-            boot: Some(Signal::Dtr { active_when: false }),
-        }
+        Self::rts_dtr_auto()
     }
 }
 
 impl SignalScheme {
-    /// Creates a default [`SignalScheme`].
+    /// Creates a default [`SignalScheme`], which is [`Self::rts_dtr_auto()`].
     pub fn new() -> Self {
         Default::default()
     }
 
+    /// Creates a [`SignalScheme`] for the common auto-download wiring, where
+    /// `RTS` drives `NRST` and `DTR` drives `BOOT0` through inverters.
+    ///
+    /// Reset is active when `RTS` is high, and boot is active when `DTR` is
+    /// low.
+    pub fn rts_dtr_auto() -> Self {
+        Self {
+            reset: Some(Signal::rts(true)),
+            boot: Some(Signal::dtr(false)),
+        }
+    }
+
+    /// Creates a [`SignalScheme`] for the swapped auto-download wiring, where
+    /// `DTR` drives `NRST` and `RTS` drives `BOOT0`, as in the auto-reset
+    /// circuit of ESP-style boards adapted for STM32.
+    ///
+    /// Reset is active when `DTR` is high, and boot is active when `RTS` is
+    /// low.
+    pub fn dtr_rts_auto() -> Self {
+        Self {
+            reset: Some(Signal::dtr(true)),
+            boot: Some(Signal::rts(false)),
+        }
+    }
+
+    /// Creates a [`SignalScheme`] for STM32 Nucleo boards connected through
+    /// the virtual COM port of the on-board ST-LINK.
+    ///
+    /// The ST-LINK doesn't wire any MODEM control signal to the MCU, so
+    /// neither signal is driven: `BOOT0` has to be set by hand, e.g. by a
+    /// jumper, before the board is reset.
+    pub fn stm32_nucleo() -> Self {
+        Self {
+            reset: None,
+            boot: None,
+        }
+    }
+
     /// Creates a default [`SignalScheme`] builder.
     pub fn builder() -> SignalSchemeBuilder {
         Default::default()
@@ -163,6 +298,7 @@ impl From<SignalScheme> for SignalSchemeBuilder {
 /// ports, probably by using some command: at the moment, only [`Command::Get`]
 /// is supported.
 #[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Identify {
     /// Identify a device by sending [`Command::Synchronize`].
     #[default]
@@ -173,14 +309,47 @@ pub enum Identify {
 }
 
 /// Probe contains necessary parameters for probing an AN3155-compliant device.
+///
+/// With the `serde` feature, missing fields are filled with defaults when
+/// deserializing, so a configuration only needs to list what it changes.
+/// Durations are in milliseconds, and signals are like `"!rts"`, see
+/// [`Signal`].
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct Probe {
     baudrate: Baudrate,
     signal_scheme: SignalScheme,
+    #[cfg_attr(feature = "serde", serde(with = "millis"))]
     reset_for: Duration,
+    #[cfg_attr(feature = "serde", serde(with = "millis"))]
+    boot_settle: Duration,
     max_attempts: usize,
+    #[cfg_attr(feature = "serde", serde(with = "millis::option"))]
+    max_identify_time: Option<Duration>,
+    #[cfg_attr(feature = "serde", serde(with = "millis::option"))]
+    quiet_for: Option<Duration>,
+    #[cfg_attr(feature = "serde", serde(with = "millis"))]
     timeout: Duration,
+    #[cfg_attr(feature = "serde", serde(with = "millis::option"))]
+    ack_timeout: Option<Duration>,
+    #[cfg_attr(feature = "serde", serde(with = "millis::option"))]
+    data_timeout: Option<Duration>,
+    #[cfg_attr(feature = "serde", serde(with = "millis"))]
+    erase_ack_timeout: Duration,
+    #[cfg_attr(feature = "serde", serde(with = "millis"))]
+    reconnect_delay: Duration,
+    #[cfg_attr(feature = "serde", serde(with = "millis::option"))]
+    keepalive_interval: Option<Duration>,
     identify: Identify,
+    safe_mode: bool,
+    address_width: AddressWidth,
+    parity: Parity,
+    stop_bits: StopBits,
+    command_retries: usize,
+    retry_modifying: bool,
+    tolerate_extra_ack: bool,
+    pipeline_writes: bool,
 }
 
 impl Default for Probe {
@@ -189,9 +358,25 @@ impl Default for Probe {
             baudrate: 115_200u32,
             signal_scheme: SignalScheme::default(),
             reset_for: Duration::from_millis(10),
+            boot_settle: Duration::ZERO,
             max_attempts: 8,
+            max_identify_time: None,
+            quiet_for: None,
             timeout: Duration::from_millis(100),
+            ack_timeout: None,
+            data_timeout: None,
+            erase_ack_timeout: Duration::from_secs(30),
+            reconnect_delay: Duration::from_millis(500),
+            keepalive_interval: None,
             identify: Identify::default(),
+            safe_mode: false,
+            address_width: AddressWidth::default(),
+            parity: Parity::Even,
+            stop_bits: StopBits::One,
+            command_retries: 0,
+            retry_modifying: false,
+            tolerate_extra_ack: false,
+            pipeline_writes: false,
         }
     }
 }
@@ -207,6 +392,18 @@ impl Probe {
         Default::default()
     }
 
+    /// Parses a [`Probe`] from YAML, with defaults for missing fields.
+    #[cfg(feature = "yaml")]
+    pub fn from_yaml(s: &str) -> crate::Result<Self> {
+        serde_yaml::from_str(s).map_err(|e| crate::Error::Config(e.into()))
+    }
+
+    /// Serializes the probe to YAML.
+    #[cfg(feature = "yaml")]
+    pub fn to_yaml(&self) -> crate::Result<String> {
+        serde_yaml::to_string(self).map_err(|e| crate::Error::Config(e.into()))
+    }
+
     /// Gets baudrate of the probe.
     pub fn baudrate(&self) -> Baudrate {
         self.baudrate
@@ -257,6 +454,18 @@ impl Probe {
         self.reset_for = duration;
     }
 
+    /// Gets delay after releasing reset, for the bootloader to start before
+    /// it's synchronized with. There is no delay by default.
+    pub fn boot_settle(&self) -> Duration {
+        self.boot_settle
+    }
+
+    /// Sets delay after releasing reset, for the bootloader to start before
+    /// it's synchronized with.
+    pub fn set_boot_settle(&mut self, delay: Duration) {
+        self.boot_settle = delay;
+    }
+
     /// Gets maximum retries for probing a device.
     pub fn max_attempts(&self) -> usize {
         self.max_attempts
@@ -267,6 +476,37 @@ impl Probe {
         self.max_attempts = max;
     }
 
+    /// Gets maximum total time for identifying a device.
+    ///
+    /// Identification stops at whichever comes first of this limit and
+    /// [`Self::max_attempts()`], while an attempt in progress is never cut
+    /// short. There is no time limit by default.
+    pub fn max_identify_time(&self) -> Option<Duration> {
+        self.max_identify_time
+    }
+
+    /// Sets maximum total time for identifying a device, or removes the limit
+    /// with [`None`].
+    pub fn set_max_identify_time(&mut self, max: Option<Duration>) {
+        self.max_identify_time = max;
+    }
+
+    /// Gets duration of silence awaited on the port after resetting the
+    /// device and before handshaking.
+    ///
+    /// It helps with applications writing to the UART until they're reset
+    /// into the bootloader, see [`crate::Programmer::wait_for_quiet()`]. No
+    /// silence is awaited by default.
+    pub fn quiet_for(&self) -> Option<Duration> {
+        self.quiet_for
+    }
+
+    /// Sets duration of silence awaited before handshaking, or disables
+    /// waiting with [`None`].
+    pub fn set_quiet_for(&mut self, quiet_for: Option<Duration>) {
+        self.quiet_for = quiet_for;
+    }
+
     /// Gets timeout for reading from the underlying port.
     pub fn timeout(&self) -> Duration {
         self.timeout
@@ -277,6 +517,69 @@ impl Probe {
         self.timeout = timeout;
     }
 
+    /// Gets timeout for waiting for a reply (ACK / NACK).
+    ///
+    /// Falls back to [`Self::timeout()`] unless set explicitly.
+    pub fn ack_timeout(&self) -> Duration {
+        self.ack_timeout.unwrap_or(self.timeout)
+    }
+
+    /// Sets timeout for waiting for a reply (ACK / NACK).
+    pub fn set_ack_timeout(&mut self, timeout: Duration) {
+        self.ack_timeout = Some(timeout);
+    }
+
+    /// Gets timeout for reading payloads, e.g. memory read by READ, which
+    /// may take much longer than a reply at low baudrates.
+    ///
+    /// Falls back to [`Self::timeout()`] unless set explicitly.
+    pub fn data_timeout(&self) -> Duration {
+        self.data_timeout.unwrap_or(self.timeout)
+    }
+
+    /// Sets timeout for reading payloads.
+    pub fn set_data_timeout(&mut self, timeout: Duration) {
+        self.data_timeout = Some(timeout);
+    }
+
+    /// Gets timeout for waiting for the reply to an erase command.
+    ///
+    /// Erasing a large flash area can take several seconds, which is far longer
+    /// than any other command.
+    pub fn erase_ack_timeout(&self) -> Duration {
+        self.erase_ack_timeout
+    }
+
+    /// Sets timeout for waiting for the reply to an erase command.
+    pub fn set_erase_ack_timeout(&mut self, timeout: Duration) {
+        self.erase_ack_timeout = timeout;
+    }
+
+    /// Gets delay before re-synchronizing with a device that has been reset.
+    pub fn reconnect_delay(&self) -> Duration {
+        self.reconnect_delay
+    }
+
+    /// Sets delay before re-synchronizing with a device that has been reset.
+    pub fn set_reconnect_delay(&mut self, delay: Duration) {
+        self.reconnect_delay = delay;
+    }
+
+    /// Gets idle interval after which the device is pinged to keep the
+    /// bootloader from timing out.
+    ///
+    /// See [`crate::Programmer::keep_alive()`]. Keep-alive is disabled by
+    /// default, since it adds traffic to the bus.
+    pub fn keepalive_interval(&self) -> Option<Duration> {
+        self.keepalive_interval
+    }
+
+    /// Sets idle interval after which the device is pinged, or disables
+    /// keep-alive with [`None`].
+    pub fn set_keepalive_interval(&mut self, interval: Option<Duration>) {
+        self.keepalive_interval = interval;
+    }
+
     /// Gets identification scheme.
     pub fn identify(&self) -> Identify {
         self.identify
@@ -286,6 +589,122 @@ impl Probe {
     pub fn set_identify(&mut self, scheme: Identify) {
         self.identify = scheme;
     }
+
+    /// Gets whether commands that modify the device are refused.
+    ///
+    /// In safe mode, only GET, GET_VERSION, GET_ID and READ are sent, while
+    /// others fail with [`crate::Error::SafeMode`]. It's meant for inventory
+    /// of boards that mustn't be touched.
+    pub fn safe_mode(&self) -> bool {
+        self.safe_mode
+    }
+
+    /// Sets whether commands that modify the device are refused.
+    pub fn set_safe_mode(&mut self, safe_mode: bool) {
+        self.safe_mode = safe_mode;
+    }
+
+    /// Gets width of addresses sent to the device.
+    ///
+    /// It's 32-bit as required by AN3155, unless the bootloader is known to
+    /// use narrower addresses.
+    pub fn address_width(&self) -> AddressWidth {
+        self.address_width
+    }
+
+    /// Sets width of addresses sent to the device.
+    pub fn set_address_width(&mut self, width: AddressWidth) {
+        self.address_width = width;
+    }
+
+    /// Gets parity of the port.
+    ///
+    /// It's even as required by AN3155, unless the device or the adapter in
+    /// between is known to differ.
+    pub fn parity(&self) -> Parity {
+        self.parity
+    }
+
+    /// Sets parity of the port.
+    pub fn set_parity(&mut self, parity: Parity) {
+        self.parity = parity;
+    }
+
+    /// Gets stop bits of the port, which is one by default.
+    pub fn stop_bits(&self) -> StopBits {
+        self.stop_bits
+    }
+
+    /// Sets stop bits of the port.
+    pub fn set_stop_bits(&mut self, stop_bits: StopBits) {
+        self.stop_bits = stop_bits;
+    }
+
+    /// Gets how many times a command NAcked by the device is resent, which
+    /// is none by default.
+    ///
+    /// Only commands leaving the device as is are resent, unless
+    /// [`Self::retry_modifying()`] is set.
+    pub fn command_retries(&self) -> usize {
+        self.command_retries
+    }
+
+    /// Sets how many times a command NAcked by the device is resent.
+    pub fn set_command_retries(&mut self, retries: usize) {
+        self.command_retries = retries;
+    }
+
+    /// Gets whether commands modifying the device, e.g. WRITE and ERASE, are
+    /// resent as well.
+    ///
+    /// It's off by default, since a command may be NAcked after being partly
+    /// applied, which mustn't be repeated blindly.
+    pub fn retry_modifying(&self) -> bool {
+        self.retry_modifying
+    }
+
+    /// Sets whether commands modifying the device are resent as well.
+    pub fn set_retry_modifying(&mut self, retry: bool) {
+        self.retry_modifying = retry;
+    }
+
+    /// Gets whether a spurious ACK following replies to GET and GET_VERSION is
+    /// discarded, which some bootloader revisions and clone chips send.
+    ///
+    /// It's off by default, since waiting for it delays each of those
+    /// commands by a couple of bytes on the line.
+    pub fn tolerate_extra_ack(&self) -> bool {
+        self.tolerate_extra_ack
+    }
+
+    /// Sets whether a spurious ACK following replies to GET and GET_VERSION
+    /// is discarded.
+    pub fn set_tolerate_extra_ack(&mut self, tolerate: bool) {
+        self.tolerate_extra_ack = tolerate;
+    }
+
+    /// Gets whether the opcode and address frames of WRITE are sent at once,
+    /// before either ACK is received.
+    ///
+    /// Each WRITE of up to 256 bytes then waits for two round trips instead
+    /// of three, which dominates on adapters with high latency, e.g. FTDI
+    /// ones with their default latency timer of 16 ms: 64 KiB take 256
+    /// WRITEs, so about 8 s of waiting instead of 12 s. Those figures are
+    /// estimated from the latency, not measured. The data frame is only sent
+    /// once the address is acknowledged, since firmware bytes taken as
+    /// commands after a refused frame may form valid ones, e.g. a mass erase.
+    ///
+    /// It's off by default, since a refused opcode leaves the address to be
+    /// taken as commands, which is recovered from heuristically, see
+    /// [`crate::Programmer::abort_pending_frame()`].
+    pub fn pipeline_writes(&self) -> bool {
+        self.pipeline_writes
+    }
+
+    /// Sets whether the opcode and address frames of WRITE are sent at once.
+    pub fn set_pipeline_writes(&mut self, pipeline: bool) {
+        self.pipeline_writes = pipeline;
+    }
 }
 
 /// [`Probe`] builder
@@ -353,11 +772,111 @@ impl ProbeBuilder {
         self
     }
 
+    /// Sets delay after releasing reset, for the bootloader to start before
+    /// it's synchronized with.
+    pub fn boot_settle(&mut self, delay: Duration) -> &mut Self {
+        self.inner.boot_settle = delay;
+        self
+    }
+
+    /// Sets timeout for waiting for a reply (ACK / NACK).
+    pub fn ack_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.inner.ack_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets timeout for reading payloads.
+    pub fn data_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.inner.data_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets timeout for waiting for the reply to an erase command, or to
+    /// lifting readout protection, which mass-erases as well.
+    pub fn erase_ack_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.inner.erase_ack_timeout = timeout;
+        self
+    }
+
+    /// Sets delay before re-synchronizing with a device that has been reset.
+    pub fn reconnect_delay(&mut self, delay: Duration) -> &mut Self {
+        self.inner.reconnect_delay = delay;
+        self
+    }
+
+    /// Sets maximum total time for identifying a device.
+    pub fn max_identify_time(&mut self, max: Duration) -> &mut Self {
+        self.inner.max_identify_time = Some(max);
+        self
+    }
+
+    /// Sets duration of silence awaited before handshaking.
+    pub fn quiet_for(&mut self, quiet_for: Duration) -> &mut Self {
+        self.inner.quiet_for = Some(quiet_for);
+        self
+    }
+
+    /// Sets idle interval after which the device is pinged to keep the
+    /// bootloader from timing out.
+    pub fn keepalive_interval(&mut self, interval: Duration) -> &mut Self {
+        self.inner.keepalive_interval = Some(interval);
+        self
+    }
+
     /// Sets identification scheme.
     pub fn identify(&mut self, identify: Identify) -> &mut Self {
         self.inner.identify = identify;
         self
     }
+
+    /// Sets whether commands that modify the device are refused.
+    pub fn safe_mode(&mut self, safe_mode: bool) -> &mut Self {
+        self.inner.safe_mode = safe_mode;
+        self
+    }
+
+    /// Sets width of addresses sent to the device.
+    pub fn address_width(&mut self, width: AddressWidth) -> &mut Self {
+        self.inner.address_width = width;
+        self
+    }
+
+    /// Sets parity of the port.
+    pub fn parity(&mut self, parity: Parity) -> &mut Self {
+        self.inner.parity = parity;
+        self
+    }
+
+    /// Sets stop bits of the port.
+    pub fn stop_bits(&mut self, stop_bits: StopBits) -> &mut Self {
+        self.inner.stop_bits = stop_bits;
+        self
+    }
+
+    /// Sets how many times a command NAcked by the device is resent.
+    pub fn command_retries(&mut self, retries: usize) -> &mut Self {
+        self.inner.command_retries = retries;
+        self
+    }
+
+    /// Sets whether commands modifying the device are resent as well.
+    pub fn retry_modifying(&mut self, retry: bool) -> &mut Self {
+        self.inner.retry_modifying = retry;
+        self
+    }
+
+    /// Sets whether a spurious ACK following replies to GET and GET_VERSION
+    /// is discarded.
+    pub fn tolerate_extra_ack(&mut self, tolerate: bool) -> &mut Self {
+        self.inner.tolerate_extra_ack = tolerate;
+        self
+    }
+
+    /// Sets whether the opcode and address frames of WRITE are sent at once.
+    pub fn pipeline_writes(&mut self, pipeline: bool) -> &mut Self {
+        self.inner.pipeline_writes = pipeline;
+        self
+    }
 }
 
 impl From<Probe> for ProbeBuilder {
@@ -366,3 +885,39 @@ impl From<Probe> for ProbeBuilder {
         Self { inner: value }
     }
 }
+
+#[cfg(feature = "yaml")]
+#[test]
+fn serializing_probes() -> crate::Result<()> {
+    let mut builder = Probe::builder();
+    builder
+        .baudrate(57_600)
+        .signal_reset(Signal::dtr(true))
+        .signal_boot(Signal::rts(false))
+        .reset_for(Duration::from_millis(20))
+        .ack_timeout(Duration::from_millis(250))
+        .identify(Identify::Get);
+    let probe = builder.build();
+    let yaml = probe.to_yaml()?;
+    assert!(yaml.contains("reset: dtr\n"));
+    assert!(yaml.contains("boot: '!rts'\n"));
+    assert!(yaml.contains("reset_for: 20\n"));
+    assert!(yaml.contains("ack_timeout: 250\n"));
+    assert!(yaml.contains("data_timeout: null\n"));
+
+    let parsed = Probe::from_yaml(&yaml)?;
+    assert_eq!(parsed.baudrate(), 57_600);
+    assert_eq!(parsed.signal_reset(), Some(Signal::dtr(true)));
+    assert_eq!(parsed.signal_boot(), Some(Signal::rts(false)));
+    assert_eq!(parsed.reset_for(), Duration::from_millis(20));
+    assert_eq!(parsed.ack_timeout(), Duration::from_millis(250));
+    assert_eq!(parsed.identify(), Identify::Get);
+    assert_eq!(parsed.to_yaml()?, yaml);
+
+    let parsed = Probe::from_yaml("signal_scheme:\n  reset: '!dtr'\n  boot: null\ntimeout: 50\n")?;
+    assert_eq!(parsed.signal_reset(), Some(Signal::dtr(false)));
+    assert_eq!(parsed.signal_boot(), None);
+    assert_eq!(parsed.timeout(), Duration::from_millis(50));
+    assert!(Probe::from_yaml("signal_scheme:\n  reset: cts\n").is_err());
+    Ok(())
+}
@@ -0,0 +1,671 @@
+use crate::crc::{STM32_CRC32_INIT, stm32_crc32_bytes, stm32_crc32_update};
+use crate::image::Image;
+use crate::{Error, Opcode, Probe, Programmer, Result, VerifyMode, VerifyReport};
+use log::{info, warn};
+use serialport::{Parity, StopBits};
+use std::cell::Cell;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// Options for [`Programmer::program()`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProgramOptions {
+    address: u32,
+    erase: bool,
+    verify: VerifyMode,
+    append_trailer: Option<Trailer>,
+}
+
+impl Default for ProgramOptions {
+    fn default() -> Self {
+        Self {
+            address: 0x0800_0000,
+            erase: true,
+            verify: VerifyMode::default(),
+            append_trailer: None,
+        }
+    }
+}
+
+impl ProgramOptions {
+    /// Creates default [`ProgramOptions`], which erase the whole flash memory,
+    /// write to the start of STM32 flash (`0x08000000`) and verify.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Gets address to write data to.
+    pub fn address(&self) -> u32 {
+        self.address
+    }
+
+    /// Sets address to write data to.
+    pub fn set_address(&mut self, address: u32) {
+        self.address = address;
+    }
+
+    /// Gets whether the whole flash memory is erased before writing.
+    pub fn erase(&self) -> bool {
+        self.erase
+    }
+
+    /// Sets whether the whole flash memory is erased before writing.
+    pub fn set_erase(&mut self, erase: bool) {
+        self.erase = erase;
+    }
+
+    /// Gets whether written data is verified.
+    pub fn verify(&self) -> bool {
+        self.verify != VerifyMode::None
+    }
+
+    /// Sets whether written data is verified, by [`VerifyMode::Checksum`] if
+    /// so.
+    pub fn set_verify(&mut self, verify: bool) {
+        self.verify = if verify {
+            VerifyMode::Checksum
+        } else {
+            VerifyMode::None
+        };
+    }
+
+    /// Gets how written data is verified.
+    pub fn verify_mode(&self) -> VerifyMode {
+        self.verify
+    }
+
+    /// Sets how written data is verified.
+    pub fn set_verify_mode(&mut self, mode: VerifyMode) {
+        self.verify = mode;
+    }
+
+    /// Gets trailer written after the image, if any.
+    pub fn append_trailer(&self) -> Option<&Trailer> {
+        self.append_trailer.as_ref()
+    }
+
+    /// Sets trailer written after the image, e.g. a CRC for the application
+    /// to check itself against.
+    ///
+    /// The trailer is erased, written and verified along with the image, and
+    /// padded with `0xff` to [`Programmer::write_alignment()`]. Its address
+    /// must be aligned too.
+    pub fn set_append_trailer(&mut self, trailer: Option<Trailer>) {
+        self.append_trailer = trailer;
+    }
+
+    /// Lays out data at the address, followed by the trailer if any, e.g. to
+    /// plan the erase with [`Programmer::plan_erase()`].
+    ///
+    /// Fails with [`Error::OutOfRange`] if the trailer lies beyond 32-bit
+    /// address space.
+    pub fn image(&self, data: &[u8]) -> Result<Image> {
+        let mut image = Image::new();
+        image.push(self.address, data);
+        if let Some((address, trailer)) = self.trailer(data)? {
+            image.push(address, trailer);
+        }
+        Ok(image)
+    }
+
+    /// Gets address and bytes of the trailer for data, if any.
+    fn trailer(&self, data: &[u8]) -> Result<Option<(u32, Vec<u8>)>> {
+        let Some(trailer) = &self.append_trailer else {
+            return Ok(None);
+        };
+        let address = u32::try_from(data.len())
+            .ok()
+            .and_then(|len| self.address.checked_add(len))
+            .and_then(|end| end.checked_add(trailer.offset()))
+            .ok_or(Error::OutOfRange {
+                address: self.address,
+                size: data.len(),
+            })?;
+        let bytes = trailer.render(data);
+        Programmer::check_range(address, bytes.len())?;
+        Ok(Some((address, bytes)))
+    }
+}
+
+/// Content of a [`Trailer`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TrailerContent {
+    /// Fixed bytes, e.g. a version.
+    Bytes(Vec<u8>),
+
+    /// CRC-32 of the image as computed by the CRC unit of STM32, stored in
+    /// little endian.
+    ///
+    /// Images not ending on a word boundary are padded with `0xff`, the value
+    /// of erased flash.
+    Crc32,
+}
+
+/// Metadata written at an offset after the image, see
+/// [`ProgramOptions::set_append_trailer()`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trailer {
+    offset: u32,
+    content: TrailerContent,
+}
+
+impl Trailer {
+    /// Creates a trailer of fixed bytes, written `offset` bytes after the end
+    /// of the image.
+    pub fn bytes(offset: u32, bytes: impl Into<Vec<u8>>) -> Self {
+        Self {
+            offset,
+            content: TrailerContent::Bytes(bytes.into()),
+        }
+    }
+
+    /// Creates a trailer of the CRC-32 of the image, written `offset` bytes
+    /// after the end of the image.
+    pub fn crc32(offset: u32) -> Self {
+        Self {
+            offset,
+            content: TrailerContent::Crc32,
+        }
+    }
+
+    /// Gets offset from the end of the image.
+    pub fn offset(&self) -> u32 {
+        self.offset
+    }
+
+    /// Gets content of the trailer.
+    pub fn content(&self) -> &TrailerContent {
+        &self.content
+    }
+
+    /// Renders bytes of the trailer for an image.
+    pub fn render(&self, image: &[u8]) -> Vec<u8> {
+        match &self.content {
+            TrailerContent::Bytes(bytes) => bytes.clone(),
+            TrailerContent::Crc32 => {
+                let mut padded = image.to_vec();
+                padded.resize(image.len().next_multiple_of(4), 0xff);
+                stm32_crc32_bytes(&padded).to_le_bytes().to_vec()
+            }
+        }
+    }
+}
+
+/// Result of [`Programmer::program()`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProgramResult {
+    written: usize,
+    verified: Option<bool>,
+    report: Option<VerifyReport>,
+    elapsed: Duration,
+}
+
+impl ProgramResult {
+    /// Bytes written.
+    pub fn written(&self) -> usize {
+        self.written
+    }
+
+    /// Whether data read back matches written data, or [`None`] if not
+    /// verified.
+    pub fn verified(&self) -> Option<bool> {
+        self.verified
+    }
+
+    /// Gets report of verifying the image, including checksums compared if
+    /// any, or [`None`] if not verified.
+    pub fn verify_report(&self) -> Option<&VerifyReport> {
+        self.report.as_ref()
+    }
+
+    /// Time spent on programming.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+}
+
+/// Decides what happens to a batch once programming a device fails
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatchPolicy {
+    /// Program every device regardless of failures on others.
+    #[default]
+    Continue,
+
+    /// Abort programming on all devices as soon as one fails.
+    ///
+    /// Devices being programmed stop between chunks, thus they may be left
+    /// partially written.
+    FailFast,
+}
+
+/// Summary of programming a batch of devices
+#[derive(Debug)]
+pub struct BatchSummary {
+    results: Vec<(String, Result<ProgramResult>)>,
+}
+
+impl BatchSummary {
+    /// Gets results along with port names, in the order the ports were given.
+    ///
+    /// Devices aborted under [`BatchPolicy::FailFast`] fail with
+    /// [`Error::Aborted`].
+    pub fn results(&self) -> &[(String, Result<ProgramResult>)] {
+        &self.results
+    }
+
+    /// Consumes the summary and returns results along with port names.
+    pub fn into_results(self) -> Vec<(String, Result<ProgramResult>)> {
+        self.results
+    }
+
+    /// Gets names of ports programmed and verified (if requested) successfully.
+    pub fn succeeded(&self) -> impl Iterator<Item = &str> {
+        self.results
+            .iter()
+            .filter(|(_, result)| !is_failure(result))
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// Gets names of ports failed, including those failing verification.
+    pub fn failed(&self) -> impl Iterator<Item = &str> {
+        self.results
+            .iter()
+            .filter(|(_, result)| is_failure(result))
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// Checks whether all devices are programmed successfully.
+    pub fn is_success(&self) -> bool {
+        self.failed().next().is_none()
+    }
+}
+
+/// A device fails either on error or on mismatched data read back.
+fn is_failure(result: &Result<ProgramResult>) -> bool {
+    !matches!(
+        result,
+        Ok(ProgramResult {
+            verified: None | Some(true),
+            ..
+        })
+    )
+}
+
+impl Programmer {
+    /// Bytes on the line for each chunk besides its data: opcode, address and
+    /// size or length frames with their checksums and ACKs.
+    const CHUNK_OVERHEAD: u32 = 3 + 6 + 3;
+
+    /// Time for the device to handle each chunk, e.g. programming flash.
+    const CHUNK_LATENCY: Duration = Duration::from_millis(5);
+
+    /// Estimates above this are warned about before programming.
+    const SLOW_PROGRAM: Duration = Duration::from_secs(60);
+
+    /// Estimates time to program `size` bytes according to options, from
+    /// bytes on the line at the probe baudrate and the latency of each chunk.
+    ///
+    /// Verification is counted as reading back, unless verified by checksum
+    /// and cached bootloader information shows GET_CHECKSUM support. Erasing
+    /// is not counted, since it varies too much between devices.
+    pub fn estimate_program_time(&self, size: usize, options: &ProgramOptions) -> Duration {
+        let chunks = size.div_ceil(256) as u32;
+        let transfer = |bytes: u32| {
+            Duration::from_secs(1) * bytes * self.bits_per_byte() / self.probe.baudrate()
+                + Self::CHUNK_LATENCY * chunks
+        };
+        let bytes = size as u32 + chunks * Self::CHUNK_OVERHEAD;
+        let mut estimate = transfer(bytes);
+        let checksum = self
+            .bootloader()
+            .is_some_and(|b| b.supports(Opcode::GET_CHECKSUM));
+        let read_back = match options.verify_mode() {
+            VerifyMode::None => false,
+            VerifyMode::Checksum => !checksum,
+            VerifyMode::ReadBack => true,
+        };
+        if read_back {
+            estimate += transfer(bytes);
+        }
+        estimate
+    }
+
+    /// Gets bits on the line for each byte: start, 8 data bits, parity if any
+    /// and stop bits, as set on the probe.
+    fn bits_per_byte(&self) -> u32 {
+        let parity = match self.probe.parity() {
+            Parity::None => 0,
+            Parity::Odd | Parity::Even => 1,
+        };
+        let stop = match self.probe.stop_bits() {
+            StopBits::One => 1,
+            StopBits::Two => 2,
+        };
+        1 + 8 + parity + stop
+    }
+
+    /// Logs an estimate of the time to program `size` bytes, as a warning if
+    /// it's longer than a minute.
+    pub(crate) fn log_estimate(&self, size: usize, options: &ProgramOptions) {
+        let estimate = self.estimate_program_time(size, options);
+        let message = format!(
+            "programming {} bytes at {} baud takes about {}s",
+            size,
+            self.probe.baudrate(),
+            estimate.as_secs(),
+        );
+        if estimate > Self::SLOW_PROGRAM {
+            warn!("{}", message);
+        } else {
+            info!("{}", message);
+        }
+    }
+
+    /// Programs data into the device according to options.
+    ///
+    /// An estimate of the time it takes is logged beforehand, as a warning if
+    /// it's longer than a minute.
+    pub fn program(&mut self, data: &[u8], options: &ProgramOptions) -> Result<ProgramResult> {
+        self.program_unless(data, options, &AtomicBool::new(false))
+    }
+
+    /// Programs data like [`Self::program()`], but fails with
+    /// [`Error::Aborted`] at the next step or chunk once `abort` is set,
+    /// including chunks read back to verify.
+    fn program_unless(
+        &mut self,
+        data: &[u8],
+        options: &ProgramOptions,
+        abort: &AtomicBool,
+    ) -> Result<ProgramResult> {
+        let check = || {
+            if abort.load(Ordering::Relaxed) {
+                Err(Error::Aborted)
+            } else {
+                Ok(())
+            }
+        };
+        // Everything is checked up front, so nothing fails halfway once the
+        // flash memory is erased.
+        Self::check_range(options.address(), data.len())?;
+        let alignment = self.write_alignment();
+        let trailer = options.trailer(data)?.map(|(address, mut trailer)| {
+            trailer.resize(trailer.len().next_multiple_of(alignment as usize), 0xff);
+            (address, trailer)
+        });
+        let addresses = std::iter::once(options.address()).chain(trailer.as_ref().map(|t| t.0));
+        for address in addresses {
+            if !address.is_multiple_of(alignment) {
+                return Err(Error::Alignment { address });
+            }
+        }
+        let size = data.len() + trailer.as_ref().map_or(0, |(_, t)| t.len());
+        self.log_estimate(size, options);
+        let start = Instant::now();
+        if options.erase() {
+            check()?;
+            self.erase_all()?;
+        }
+        // CRC of whole words is computed as chunks are written, so verifying
+        // by checksum takes no extra pass over the data.
+        let words = data.len() & !3;
+        let crc = Cell::new(STM32_CRC32_INIT);
+        self.write_memory_chunked_with_transform(options.address(), data, |address, chunk| {
+            check()?;
+            let offset = (address - options.address()) as usize;
+            let len = words.saturating_sub(offset).min(chunk.len());
+            crc.set(stm32_crc32_update(crc.get(), &chunk[..len]));
+            Ok(())
+        })?;
+        if let Some((address, trailer)) = &trailer {
+            self.write_memory_chunked_with_transform(*address, trailer, |_, _| check())?;
+        }
+        check()?;
+        let report = match options.verify_mode() {
+            VerifyMode::None => None,
+            VerifyMode::Checksum => Some(self.verify_image_with_crc(
+                options.address(),
+                data,
+                Some(crc.get()),
+                &check,
+            )?),
+            VerifyMode::ReadBack => Some(self.verify_read_back(options.address(), data, &check)?),
+        };
+        let mut verified = report.as_ref().map(VerifyReport::matched);
+        if verified == Some(true)
+            && let Some((address, trailer)) = &trailer
+        {
+            let report = match options.verify_mode() {
+                VerifyMode::ReadBack => self.verify_read_back(*address, trailer, &check)?,
+                _ => self.verify_image_with_crc(*address, trailer, None, &check)?,
+            };
+            verified = Some(report.matched());
+        }
+        Ok(ProgramResult {
+            written: size,
+            verified,
+            report,
+            elapsed: start.elapsed(),
+        })
+    }
+
+    /// Opens devices on several ports and programs the same data into them in
+    /// parallel, one thread per port.
+    ///
+    /// Whether a failure on a port affects others is decided by `policy`.
+    pub fn program_all_parallel(
+        probes: &[(String, Arc<Probe>)],
+        data: &[u8],
+        options: ProgramOptions,
+        policy: BatchPolicy,
+    ) -> BatchSummary {
+        let names = probes.iter().map(|(path, _)| path.clone()).collect();
+        let open = |i: usize| {
+            let (path, probe) = &probes[i];
+            Self::open_shared(path, Arc::clone(probe))
+        };
+        Self::program_batch(names, open, data, &options, policy, &AtomicBool::new(false))
+    }
+
+    /// Programs devices opened by `open` with their index, one thread each,
+    /// setting `abort` once one fails under [`BatchPolicy::FailFast`].
+    fn program_batch(
+        names: Vec<String>,
+        open: impl Fn(usize) -> Result<Programmer> + Sync,
+        data: &[u8],
+        options: &ProgramOptions,
+        policy: BatchPolicy,
+        abort: &AtomicBool,
+    ) -> BatchSummary {
+        let open = &open;
+        let results = std::thread::scope(|scope| {
+            let handles = (0..names.len())
+                .map(|i| {
+                    scope.spawn(move || {
+                        let result =
+                            open(i).and_then(|mut p| p.program_unless(data, options, abort));
+                        if policy == BatchPolicy::FailFast && is_failure(&result) {
+                            abort.store(true, Ordering::Relaxed);
+                        }
+                        result
+                    })
+                })
+                .collect::<Vec<_>>();
+            names
+                .into_iter()
+                .zip(handles)
+                .map(|(name, handle)| {
+                    let result = handle.join().unwrap_or_else(|_| {
+                        Err(std::io::Error::other("programming panicked").into())
+                    });
+                    (name, result)
+                })
+                .collect()
+        });
+        BatchSummary { results }
+    }
+}
+
+#[test]
+fn estimating_program_time() {
+    use crate::mock::MockPort;
+
+    let mut options = ProgramOptions::new();
+    options.set_verify_mode(VerifyMode::None);
+    // 10 chunks of 256 bytes, each with 12 bytes of overhead and 5ms latency
+    let programmer = Programmer::attach(Box::new(MockPort::new(&[])), &Probe::new());
+    let transfer = Duration::from_secs(1) * 2680 * 11 / 115_200 + Duration::from_millis(50);
+    assert_eq!(programmer.estimate_program_time(2560, &options), transfer);
+
+    // Read back without GET_CHECKSUM known to be supported
+    options.set_verify_mode(VerifyMode::Checksum);
+    assert_eq!(
+        programmer.estimate_program_time(2560, &options),
+        transfer * 2
+    );
+
+    // 8N1 and 8N2
+    options.set_verify_mode(VerifyMode::None);
+    let mut builder = Probe::builder();
+    builder.parity(Parity::None);
+    let programmer = Programmer::attach(Box::new(MockPort::new(&[])), &builder.clone().build());
+    let transfer = Duration::from_secs(1) * 2680 * 10 / 115_200 + Duration::from_millis(50);
+    assert_eq!(programmer.estimate_program_time(2560, &options), transfer);
+    builder.stop_bits(StopBits::Two);
+    let programmer = Programmer::attach(Box::new(MockPort::new(&[])), &builder.build());
+    let transfer = Duration::from_secs(1) * 2680 * 11 / 115_200 + Duration::from_millis(50);
+    assert_eq!(programmer.estimate_program_time(2560, &options), transfer);
+}
+
+#[test]
+fn verifying_by_crc_while_writing() -> Result<()> {
+    use crate::VerifyMethod;
+    use crate::mock::{Faults, MockPort};
+
+    let data: Vec<u8> = (0..300).map(|i| i as u8).collect();
+    let reads = |port: &MockPort| port.written().windows(2).any(|w| w == [0x11, 0xee]);
+    let port = MockPort::device(Faults::default());
+    port.support_checksum();
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &Probe::new());
+    let result = programmer.program(&data, &ProgramOptions::new())?;
+    assert_eq!(result.verified(), Some(true));
+    assert!(!reads(&port));
+
+    // One byte corrupted
+    let faults = Faults {
+        corrupt_at: Some(0x0800_0100),
+        ..Default::default()
+    };
+    let port = MockPort::device(faults);
+    port.support_checksum();
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &Probe::new());
+    let result = programmer.program(&data, &ProgramOptions::new())?;
+    assert_eq!(result.verified(), Some(false));
+    let report = result.verify_report().unwrap();
+    assert_eq!(report.method(), VerifyMethod::Checksum);
+    let (expected, actual) = report.checksums().unwrap();
+    assert_eq!(expected, stm32_crc32_bytes(&data));
+    assert_ne!(expected, actual);
+    assert!(!reads(&port));
+    Ok(())
+}
+
+#[test]
+fn programming_trailers() -> Result<()> {
+    use crate::mock::{Faults, MockPort};
+
+    let data: Vec<u8> = (0..300).map(|i| i as u8).collect();
+    let mut options = ProgramOptions::new();
+    options.set_verify_mode(VerifyMode::ReadBack);
+
+    // Padded to a whole word
+    options.set_append_trailer(Some(Trailer::bytes(4, [0x01, 0x02, 0x03])));
+    let port = MockPort::device(Faults::default());
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &Probe::new());
+    let result = programmer.program(&data, &options)?;
+    assert_eq!(result.written(), 304);
+    assert_eq!(result.verified(), Some(true));
+    assert_eq!(port.flash()[..300], data);
+    assert_eq!(port.flash()[0x130..0x134], [0x01, 0x02, 0x03, 0xff]);
+
+    options.set_append_trailer(Some(Trailer::crc32(0)));
+    let image = options.image(&data)?;
+    assert_eq!(image.segments()[1].address(), 0x0800_012c);
+    let crc = stm32_crc32_bytes(&data).to_le_bytes();
+    let port = MockPort::device(Faults::default());
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &Probe::new());
+    assert_eq!(programmer.program(&data, &options)?.verified(), Some(true));
+    assert_eq!(port.flash()[0x12c..0x130], crc);
+
+    // Corrupted trailer
+    let faults = Faults {
+        corrupt_at: Some(0x0800_012e),
+        ..Default::default()
+    };
+    let port = MockPort::device(faults);
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &Probe::new());
+    assert_eq!(programmer.program(&data, &options)?.verified(), Some(false));
+    Ok(())
+}
+
+#[test]
+fn refusing_trailers_before_erasing() -> Result<()> {
+    use crate::mock::{Faults, MockPort};
+
+    let port = MockPort::device(Faults::default());
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &Probe::new());
+    let mut options = ProgramOptions::new();
+    options.set_append_trailer(Some(Trailer::bytes(2, [0x01; 4])));
+    let result = programmer.program(&[0xa5; 8], &options);
+    assert!(matches!(
+        result,
+        Err(Error::Alignment {
+            address: 0x0800_000a
+        })
+    ));
+
+    options.set_address(0xffff_ff00);
+    options.set_append_trailer(Some(Trailer::crc32(0)));
+    let result = programmer.program(&[0xa5; 0x100], &options);
+    assert!(result.is_err_and(|e| e.is_out_of_range()));
+    assert!(options.image(&[0xa5; 0x100]).is_err());
+    assert!(port.written().is_empty());
+    Ok(())
+}
+
+#[test]
+fn failing_batches() {
+    use crate::mock::{Faults, MockPort};
+
+    let data = [0xa5; 0x400];
+    let batch = |policy| {
+        let abort = AtomicBool::new(false);
+        let port = MockPort::device(Faults::default());
+        let open = |i| match i {
+            0 => Err(Error::NAck),
+            _ => {
+                // The second device only starts once the first failed.
+                while policy == BatchPolicy::FailFast && !abort.load(Ordering::Relaxed) {
+                    std::thread::yield_now();
+                }
+                Ok(Programmer::attach(Box::new(port.clone()), &Probe::new()))
+            }
+        };
+        let names = vec!["first".to_owned(), "second".to_owned()];
+        let options = ProgramOptions::new();
+        let summary = Programmer::program_batch(names, open, &data, &options, policy, &abort);
+        (summary, port)
+    };
+
+    let (summary, port) = batch(BatchPolicy::Continue);
+    assert!(summary.failed().eq(["first"]));
+    assert!(summary.succeeded().eq(["second"]));
+    assert_eq!(port.flash()[..0x400], data);
+
+    let (summary, port) = batch(BatchPolicy::FailFast);
+    assert!(summary.succeeded().next().is_none());
+    let second = &summary.results()[1].1;
+    assert!(second.as_ref().is_err_and(|e| e.is_aborted()));
+    assert!(port.written().is_empty());
+}
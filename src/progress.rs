@@ -0,0 +1,23 @@
+/// Progress of a chunked transfer, reported after each chunk, e.g. by
+/// [`crate::Programmer::read_memory_with_progress()`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    done: usize,
+    total: usize,
+}
+
+impl Progress {
+    pub(crate) fn new(done: usize, total: usize) -> Self {
+        Self { done, total }
+    }
+
+    /// Gets bytes transferred so far.
+    pub fn done(&self) -> usize {
+        self.done
+    }
+
+    /// Gets bytes to transfer in total.
+    pub fn total(&self) -> usize {
+        self.total
+    }
+}
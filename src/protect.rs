@@ -0,0 +1,159 @@
+#[cfg(doc)]
+use crate::Probe;
+use crate::{Command, Error, Programmer, Result, SectorNos};
+
+impl Programmer {
+    /// Enables write protection of flash sectors, by WRITE_PROTECT.
+    ///
+    /// The device resets itself once the option bytes are changed, so the
+    /// caller must [`Self::reconnect()`] before sending anything else.
+    pub fn write_protect(&mut self, sectors: &[u8]) -> Result<()> {
+        let sectors = SectorNos::try_from(sectors)?;
+        self.send_command(Command::WriteProtect(sectors))
+    }
+
+    /// Disables write protection of all flash sectors, by WRITE_UNPROTECT.
+    ///
+    /// The device resets itself once the option bytes are changed, so the
+    /// caller must [`Self::reconnect()`] before sending anything else.
+    pub fn write_unprotect(&mut self) -> Result<()> {
+        self.send_command(Command::WriteUnprotect())
+    }
+
+    /// Enables readout protection of flash memory, by READ_PROTECT.
+    ///
+    /// **Warning:** memory can no longer be read through the bootloader, and
+    /// lifting the protection mass-erases the flash memory. On some parts,
+    /// higher protection levels set by other means are irreversible.
+    ///
+    /// The device resets itself once the option bytes are changed, so the
+    /// caller must [`Self::reconnect()`] before sending anything else.
+    pub fn read_protect(&mut self) -> Result<()> {
+        self.send_command(Command::ReadProtect())
+    }
+
+    /// Disables readout protection of flash memory, by READ_UNPROTECT.
+    ///
+    /// The flash memory is mass-erased and the device resets itself, so it's
+    /// identified again after [`Probe::reset_for()`]. Fails with
+    /// [`Error::Unidentified`] if the device never comes back.
+    pub fn read_unprotect(&mut self) -> Result<()> {
+        self.send_command(Command::ReadUnprotect())?;
+        std::thread::sleep(self.probe.reset_for());
+        self.handshake()
+    }
+
+    /// Reads `len` option bytes at `address`, where each byte is followed by
+    /// its complement, as on F0, F1 and F3.
+    ///
+    /// Only the bytes themselves are returned. Fails with
+    /// [`Error::OptionBytesComplement`] if a byte is not followed by its
+    /// complement, e.g. because the option bytes are corrupted.
+    pub fn read_option_bytes(&mut self, address: u32, len: usize) -> Result<Vec<u8>> {
+        let raw = self.read_memory(address, len * 2)?;
+        raw.chunks(2)
+            .enumerate()
+            .map(|(i, pair)| match pair {
+                &[byte, complement] if byte == !complement => Ok(byte),
+                _ => Err(Error::OptionBytesComplement {
+                    address: address + (i * 2) as u32,
+                }),
+            })
+            .collect()
+    }
+
+    /// Writes option bytes at `address`, following each byte by its
+    /// complement as the device requires.
+    ///
+    /// **Warning:** the device resets itself once the option bytes are
+    /// written, so the caller must [`Self::reconnect()`] before sending
+    /// anything else. Option bytes also control readout and write protection,
+    /// so writing wrong values may lock the device.
+    pub fn write_option_bytes(&mut self, address: u32, bytes: &[u8]) -> Result<()> {
+        let raw = bytes.iter().flat_map(|&b| [b, !b]).collect::<Vec<_>>();
+        self.write_memory_chunked(address, &raw)
+    }
+}
+
+#[test]
+fn framing_write_protect() -> Result<()> {
+    use crate::Probe;
+    use crate::mock::MockPort;
+
+    let port = MockPort::new(&[0x79; 2]);
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &Probe::new());
+    programmer.write_protect(&[0, 1, 5])?;
+    assert_eq!(port.written(), [0x63, 0x9c, 0x02, 0x00, 0x01, 0x05, 0x06]);
+
+    // Refused before anything is sent
+    assert!(
+        programmer
+            .write_protect(&[])
+            .is_err_and(|e| e.is_protocol_conversion())
+    );
+
+    let port = MockPort::new(&[0x79; 2]);
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &Probe::new());
+    programmer.write_unprotect()?;
+    assert_eq!(port.written(), [0x73, 0x8c]);
+
+    // The second ACK is missing once the option bytes cannot be changed.
+    let port = MockPort::new(&[0x79, 0x1f]);
+    let mut programmer = Programmer::attach(Box::new(port), &Probe::new());
+    assert!(programmer.write_unprotect().is_err_and(|e| e.is_nack()));
+    Ok(())
+}
+
+#[test]
+fn framing_read_protect() -> Result<()> {
+    use crate::Probe;
+    use crate::mock::MockPort;
+
+    let port = MockPort::new(&[0x79; 2]);
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &Probe::new());
+    programmer.read_protect()?;
+    assert_eq!(port.written(), [0x82, 0x7d]);
+
+    // The device is identified again once it has been reset.
+    let port = MockPort::new(&[0x79; 3]);
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &Probe::new());
+    programmer.read_unprotect()?;
+    assert_eq!(port.written(), [0x92, 0x6d, 0x7f]);
+
+    let port = MockPort::new(&[0x79; 2]);
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &Probe::new());
+    let result = programmer.read_unprotect();
+    assert!(result.is_err_and(|e| e.is_unidentified()));
+    assert_eq!(port.written()[..3], [0x92, 0x6d, 0x7f]);
+    Ok(())
+}
+
+#[test]
+fn pairing_option_bytes() -> Result<()> {
+    use crate::Probe;
+    use crate::mock::MockPort;
+
+    let port = MockPort::new(&[0x79; 3]);
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &Probe::new());
+    programmer.write_option_bytes(0x1fff_f800, &[0xa5, 0x00])?;
+    assert_eq!(
+        port.written(),
+        [
+            0x31, 0xce, 0x1f, 0xff, 0xf8, 0x00, 0x18, 0x03, 0xa5, 0x5a, 0x00, 0xff, 0x03
+        ]
+    );
+
+    let mut replies = vec![0x79, 0x79, 0x79, 0xa5, 0x5a, 0x00, 0xff];
+    replies.extend([0x79, 0x79, 0x79, 0xa5, 0x5a, 0x00, 0x00]);
+    let port = MockPort::new(&replies);
+    let mut programmer = Programmer::attach(Box::new(port), &Probe::new());
+    assert_eq!(programmer.read_option_bytes(0x1fff_f800, 2)?, [0xa5, 0x00]);
+    let result = programmer.read_option_bytes(0x1fff_f800, 2);
+    assert!(result.is_err_and(|e| matches!(
+        e,
+        Error::OptionBytesComplement {
+            address: 0x1fff_f802
+        }
+    )));
+    Ok(())
+}
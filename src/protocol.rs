@@ -1,3 +1,4 @@
+use crate::device::Family;
 #[allow(unused_imports)]
 use binrw::{BinRead, BinWrite, binread, binrw, binwrite};
 use std::borrow::Cow;
@@ -48,6 +49,10 @@ impl std::error::Error for Error {}
 pub struct Exceeded(usize, ExpectedRange);
 
 impl Exceeded {
+    pub(crate) fn new(unexpected: usize, expected: RangeInclusive<usize>) -> Self {
+        Self(unexpected, expected.into())
+    }
+
     pub fn unexpected(&self) -> usize {
         self.0
     }
@@ -97,16 +102,44 @@ impl de::Expected for ExpectedRange {
     }
 }
 
-mod checksum {
+/// XOR checksums framing commands and payloads, for custom commands sent by
+/// [`crate::Programmer::send()`]
+///
+/// ```
+/// use binrw::BinWrite;
+/// use std::io::{Cursor, Write};
+/// use yapu::Opcode;
+/// use yapu::checksum::{Buffer, checksum};
+///
+/// // A single byte, e.g. an opcode, is followed by its complement.
+/// let mut frame = Cursor::new(Vec::new());
+/// Opcode::WRITE.write(&mut frame)?;
+/// assert_eq!(frame.into_inner(), [0x31, checksum(&[0x31])]);
+///
+/// // Longer frames, e.g. an address, are followed by XOR of all bytes.
+/// let address = 0x0800_0000u32.to_be_bytes();
+/// let mut buffer = Buffer::new();
+/// buffer.write_all(&address)?;
+/// assert_eq!(buffer.state(), checksum(&address));
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub mod checksum {
+    /// Writer accumulating XOR of all bytes written, e.g. by serializing
+    /// a frame into it
+    ///
+    /// Unlike [`checksum()`], a single byte written is not complemented.
     #[derive(Default, Debug, Clone)]
     pub struct Buffer {
         state: u8,
     }
 
     impl Buffer {
+        /// Creates an empty [`Buffer`], whose checksum is 0.
         pub fn new() -> Self {
             Self::default()
         }
+
+        /// Gets XOR of all bytes written so far.
         pub fn state(&self) -> u8 {
             self.state
         }
@@ -114,7 +147,7 @@ mod checksum {
 
     impl std::io::Write for Buffer {
         fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-            self.state = self.state ^ iter(buf.iter().copied());
+            self.state ^= iter(buf.iter().copied());
             Ok(buf.len())
         }
         fn flush(&mut self) -> std::io::Result<()> {
@@ -122,6 +155,15 @@ mod checksum {
         }
     }
 
+    /// Computes checksum of a frame as AN3155 defines it: the complement of a
+    /// single byte, or XOR of all bytes otherwise.
+    pub fn checksum(bytes: &[u8]) -> u8 {
+        match bytes {
+            [byte] => single(*byte),
+            bytes => iter(bytes.iter().copied()),
+        }
+    }
+
     pub(super) fn single(data: u8) -> u8 {
         data ^ 0xff
     }
@@ -172,9 +214,33 @@ impl Opcode {
     pub const SPECIAL: Self = Self(0x50u8);
     pub const EXTENDED_SPECIAL: Self = Self(0x51u8);
 
+    /// All opcodes defined by AN3155.
+    pub const KNOWN: [Self; 15] = [
+        Self::GET,
+        Self::GET_VERSION,
+        Self::GET_ID,
+        Self::READ,
+        Self::GO,
+        Self::WRITE,
+        Self::ERASE,
+        Self::EXTENDED_ERASE,
+        Self::WRITE_PROTECT,
+        Self::WRITE_UNPROTECT,
+        Self::READ_PROTECT,
+        Self::READ_UNPROTECT,
+        Self::GET_CHECKSUM,
+        Self::SPECIAL,
+        Self::EXTENDED_SPECIAL,
+    ];
+
     pub fn as_u8(&self) -> u8 {
         self.0
     }
+
+    /// Whether the opcode is defined by AN3155.
+    pub fn is_known(&self) -> bool {
+        Self::KNOWN.contains(self)
+    }
 }
 
 impl std::fmt::Display for Opcode {
@@ -206,31 +272,100 @@ impl From<u8> for Opcode {
     }
 }
 
+/// Width of address frames
+///
+/// AN3155 always uses 32-bit addresses, while a few compatible bootloaders on
+/// tiny parts use narrower ones.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum AddressWidth {
+    Bits16,
+    Bits24,
+    #[default]
+    Bits32,
+}
+
+impl AddressWidth {
+    /// Number of bytes of an address, excluding its checksum.
+    pub fn bytes(self) -> usize {
+        match self {
+            Self::Bits16 => 2,
+            Self::Bits24 => 3,
+            Self::Bits32 => 4,
+        }
+    }
+
+    /// Largest address of the width.
+    pub fn max(self) -> u32 {
+        u32::MAX >> (32 - 8 * self.bytes())
+    }
+}
+
 /// Address
-#[binwrite]
+///
+/// It's written in big endian with as many bytes as its [`AddressWidth`],
+/// followed by XOR checksum of these bytes. With the `serde` feature, it's
+/// serialized as a plain number.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-#[bw(big)]
-pub struct Address(
-    u32,
-    #[bw(calc = checksum::iter(self.0.to_ne_bytes().iter().copied()))] u8,
-);
+#[cfg_attr(feature = "serde", serde(from = "u32", into = "u32"))]
+pub struct Address {
+    value: u32,
+    width: AddressWidth,
+}
 
 impl Address {
     pub fn as_u32(&self) -> u32 {
-        self.0
+        self.value
+    }
+
+    /// Gets width of the address on the wire.
+    pub fn width(&self) -> AddressWidth {
+        self.width
+    }
+
+    /// Converts the address to another width, failing if it doesn't fit.
+    pub fn with_width(self, width: AddressWidth) -> Result<Self, Error> {
+        if self.value > width.max() {
+            return Err(Exceeded(self.value as usize, (0..=width.max() as usize).into()).into());
+        }
+        Ok(Self { width, ..self })
+    }
+}
+
+impl BinWrite for Address {
+    type Args<'a> = ();
+
+    fn write_options<W: std::io::Write + std::io::Seek>(
+        &self,
+        writer: &mut W,
+        _: binrw::Endian,
+        _: Self::Args<'_>,
+    ) -> binrw::BinResult<()> {
+        let bytes = self.value.to_be_bytes();
+        let bytes = &bytes[bytes.len() - self.width.bytes()..];
+        writer.write_all(bytes)?;
+        writer.write_all(&[checksum::iter(bytes.iter().copied())])?;
+        Ok(())
     }
 }
 
+impl binrw::meta::WriteEndian for Address {
+    const ENDIAN: binrw::meta::EndianKind = binrw::meta::EndianKind::Endian(binrw::Endian::Big);
+}
+
 impl From<u32> for Address {
     fn from(value: u32) -> Self {
-        Self(value)
+        Self {
+            value,
+            width: AddressWidth::default(),
+        }
     }
 }
 
-impl Into<u32> for Address {
-    fn into(self) -> u32 {
-        self.0
+impl From<Address> for u32 {
+    fn from(value: Address) -> Self {
+        value.value
     }
 }
 
@@ -252,7 +387,8 @@ pub type ExtendedPageNo = u16;
 pub type SectorNo = u8;
 
 define_slice_item! { pub Byte(u8), as_u8, u8, 1..=256 }
-define_slice_item! { pub Page(PageNo), as_u8, u8, 1..=256 }
+// 256 pages would be framed as 0xff, which stands for a global erase.
+define_slice_item! { pub Page(PageNo), as_u8, u8, 1..=255 }
 define_slice_item! { pub ExtendedPage(ExtendedPageNo), as_u16, u16, 1..=0xff00 }
 define_slice_item! { pub Sector(SectorNo), as_u8, u8, 1..=256 }
 
@@ -261,9 +397,14 @@ define_slice_item! { pub Sector(SectorNo), as_u8, u8, 1..=256 }
 #[bw(big)]
 pub struct Size(u8, #[bw(calc = checksum::single(self.0))] u8);
 
-impl Into<usize> for Size {
-    fn into(self) -> usize {
-        self.0 as usize + <Byte as SliceItem>::SIZE_RANGE.start()
+impl Size {
+    /// Valid range of sizes of a single transfer.
+    pub const RANGE: RangeInclusive<usize> = <Byte as SliceItem>::SIZE_RANGE;
+}
+
+impl From<Size> for usize {
+    fn from(value: Size) -> Self {
+        value.0 as usize + <Byte as SliceItem>::SIZE_RANGE.start()
     }
 }
 
@@ -273,7 +414,8 @@ impl TryFrom<usize> for Size {
     fn try_from(value: usize) -> Result<Self, Self::Error> {
         let range = <Byte as SliceItem>::SIZE_RANGE;
         if range.contains(&value) {
-            Ok(Self(value as u8))
+            // the size is shifted on the wire, i.e. `N - 1` stands for `N`
+            Ok(Self((value - range.start()) as u8))
         } else {
             Err(Exceeded(value, range.into()).into())
         }
@@ -287,9 +429,8 @@ impl<'de> Deserialize<'de> for Size {
         D: Deserializer<'de>,
     {
         let value = usize::deserialize(deserializer)?;
-        let converted = Self::try_from(value).map_err(|e| {
-            let exceeded = e.exceeded().unwrap();
-            exceeded.to_serde::<D>()
+        let converted = Self::try_from(value).map_err(|e| match e {
+            Error::Exceeded(exceeded) => exceeded.to_serde::<D>(),
         })?;
         Ok(converted)
     }
@@ -301,7 +442,7 @@ impl serde::Serialize for Size {
     where
         S: Serializer,
     {
-        serializer.serialize_u64(self.0 as u64)
+        serializer.serialize_u64(usize::from(*self) as u64)
     }
 }
 
@@ -346,9 +487,9 @@ impl<'a, T: SliceItem> Slice<'a, T> {
     }
 }
 
-impl<'a, T: SliceItem> Into<Cow<'a, [T::Repr]>> for Slice<'a, T> {
-    fn into(self) -> Cow<'a, [T::Repr]> {
-        self.inner
+impl<'a, T: SliceItem> From<Slice<'a, T>> for Cow<'a, [T::Repr]> {
+    fn from(value: Slice<'a, T>) -> Self {
+        value.inner
     }
 }
 
@@ -392,6 +533,31 @@ impl<'a, T: SliceItem> TryFrom<&'a [T::Repr]> for Slice<'a, T> {
     }
 }
 
+impl<'a, T: SliceItem, const N: usize> TryFrom<[T::Repr; N]> for Slice<'a, T> {
+    type Error = Error;
+
+    fn try_from(value: [T::Repr; N]) -> Result<Self, Self::Error> {
+        Self::try_from(value.to_vec())
+    }
+}
+
+impl<'a, 'b, T: SliceItem> IntoIterator for &'b Slice<'a, T> {
+    type Item = &'b T::Repr;
+    type IntoIter = std::slice::Iter<'b, T::Repr>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.inner.iter()
+    }
+}
+
+impl<'a> Slice<'a, Byte> {
+    /// Creates [`Data`] from borrowed or owned bytes, which must be within
+    /// [`Size::RANGE`].
+    pub fn from_bytes(bytes: impl Into<Cow<'a, [u8]>>) -> Result<Self, Error> {
+        Self::try_from(bytes.into())
+    }
+}
+
 impl<'a, T: SliceItem + BinWrite<Args<'a> = ()>> BinWrite for Slice<'a, T>
 where
     [T::Repr]: BinWrite<Args<'a> = ()>,
@@ -408,16 +574,33 @@ where
     ) -> binrw::BinResult<()> {
         use binrw::io::NoSeek;
 
+        // the inner `Cow` is reachable through `DerefMut`, so the length may
+        // have left the valid range since the slice was constructed
+        let len = self.inner.len();
+        let exceeded = |writer: &mut W| -> binrw::BinResult<binrw::Error> {
+            Ok(binrw::Error::Custom {
+                pos: writer.stream_position()?,
+                err: Box::new(Error::from(Exceeded(len, T::SIZE_RANGE.into()))),
+            })
+        };
+        if !T::SIZE_RANGE.contains(&len) {
+            return Err(exceeded(writer)?);
+        }
+
         // write shifted size
         let lowerbound = *<T as SliceItem>::SIZE_RANGE.start();
-        let size = <T as SliceItem>::Size::try_from(self.inner.len() - lowerbound).unwrap();
+        let size = match <T as SliceItem>::Size::try_from(len - lowerbound) {
+            Ok(size) => size,
+            Err(_) => return Err(exceeded(writer)?),
+        };
         size.write_options(writer, endian, args)?;
 
         // write data
         self.inner.write_options(writer, endian, args)?;
 
-        // write checksum
+        // write checksum, which covers the size as well
         let mut buffer = checksum::Buffer::new();
+        size.write_options(&mut NoSeek::new(&mut buffer), endian, args)?;
         self.inner
             .write_options(&mut NoSeek::new(&mut buffer), endian, args)?;
         buffer.state().write_options(writer, endian, args)?;
@@ -462,8 +645,11 @@ pub enum Command<'a> {
         data: Data<'a>,
     },
     Erase(#[bw(calc = Opcode::ERASE)] Opcode, Erase<'a>),
-    ExtendedErase(#[bw(calc = Opcode::ERASE)] Opcode, ExtendedErase<'a>),
-    WriteProtect(#[bw(calc = Opcode::WRITE_PROTECT)] Opcode),
+    ExtendedErase(
+        #[bw(calc = Opcode::EXTENDED_ERASE)] Opcode,
+        ExtendedErase<'a>,
+    ),
+    WriteProtect(#[bw(calc = Opcode::WRITE_PROTECT)] Opcode, SectorNos<'a>),
     WriteUnprotect(#[bw(calc = Opcode::WRITE_UNPROTECT)] Opcode),
     ReadProtect(#[bw(calc = Opcode::READ_PROTECT)] Opcode),
     ReadUnprotect(#[bw(calc = Opcode::READ_UNPROTECT)] Opcode),
@@ -473,6 +659,37 @@ pub enum Command<'a> {
     Synchronize,
 }
 
+impl Command<'_> {
+    /// Gets opcode of the command, or [`None`] for synchronization.
+    pub fn opcode(&self) -> Option<Opcode> {
+        let opcode = match self {
+            Self::Get() => Opcode::GET,
+            Self::Version() => Opcode::GET_VERSION,
+            Self::Id() => Opcode::GET_ID,
+            Self::Read { .. } => Opcode::READ,
+            Self::Go(..) => Opcode::GO,
+            Self::Write { .. } => Opcode::WRITE,
+            Self::Erase(..) => Opcode::ERASE,
+            Self::ExtendedErase(..) => Opcode::EXTENDED_ERASE,
+            Self::WriteProtect(..) => Opcode::WRITE_PROTECT,
+            Self::WriteUnprotect() => Opcode::WRITE_UNPROTECT,
+            Self::ReadProtect() => Opcode::READ_PROTECT,
+            Self::ReadUnprotect() => Opcode::READ_UNPROTECT,
+            Self::Synchronize => return None,
+        };
+        Some(opcode)
+    }
+
+    /// Whether the command leaves the device as is, i.e. it's allowed in
+    /// [`crate::Probe::safe_mode()`].
+    pub fn is_harmless(&self) -> bool {
+        matches!(
+            self,
+            Self::Get() | Self::Version() | Self::Id() | Self::Read { .. } | Self::Synchronize
+        )
+    }
+}
+
 /// Command for [`Opcode::ERASE`].
 #[derive(BinWrite, Debug, Clone)]
 #[bw(big)]
@@ -578,6 +795,10 @@ pub struct Bootloader {
 }
 
 impl Bootloader {
+    pub(crate) fn new(version: u8, opcodes: Vec<Opcode>) -> Self {
+        Self { version, opcodes }
+    }
+
     /// Bootloader version in [`u8`].
     #[inline]
     pub fn version(&self) -> u8 {
@@ -614,9 +835,26 @@ impl Bootloader {
     }
 }
 
+impl std::fmt::Display for Bootloader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (", self.version_string())?;
+        for (i, opcode) in self.opcodes.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", opcode)?;
+        }
+        write!(f, ")")
+    }
+}
+
 /// Version
+///
+/// With the `serde` feature, it's serialized as major and minor versions
+/// along with option bytes.
 #[derive(BinRead, Debug, Clone)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(into = "VersionRepr", from = "VersionRepr"))]
 #[br(big)]
 pub struct Version {
     version: u8,
@@ -651,6 +889,44 @@ impl Version {
     pub fn options(&self) -> [u8; 2] {
         self.options
     }
+
+    /// Checks whether the protocol version is 3.x, which replaced ERASE with
+    /// EXTENDED_ERASE.
+    pub fn is_usart_v3(&self) -> bool {
+        self.major() == 3
+    }
+
+    /// Checks whether the protocol version is expected to support a command,
+    /// according to the history of AN3155:
+    ///
+    /// - 2.x: ERASE, along with the basic and protection commands
+    /// - 3.0: EXTENDED_ERASE instead of ERASE
+    /// - 3.1: SPECIAL and EXTENDED_SPECIAL
+    /// - 3.3: GET_CHECKSUM
+    ///
+    /// This is a hint to pick a code path without the full GET response. The
+    /// opcodes advertised by GET, see [`Bootloader::supports()`], are
+    /// authoritative.
+    pub fn supports(&self, opcode: Opcode) -> bool {
+        let version = self.version;
+        match opcode {
+            Opcode::GET
+            | Opcode::GET_VERSION
+            | Opcode::GET_ID
+            | Opcode::READ
+            | Opcode::GO
+            | Opcode::WRITE
+            | Opcode::WRITE_PROTECT
+            | Opcode::WRITE_UNPROTECT
+            | Opcode::READ_PROTECT
+            | Opcode::READ_UNPROTECT => (0x20..0x40).contains(&version),
+            Opcode::ERASE => (0x20..0x30).contains(&version),
+            Opcode::EXTENDED_ERASE => (0x30..0x40).contains(&version),
+            Opcode::SPECIAL | Opcode::EXTENDED_SPECIAL => (0x31..0x40).contains(&version),
+            Opcode::GET_CHECKSUM => (0x33..0x40).contains(&version),
+            _ => false,
+        }
+    }
 }
 
 impl std::fmt::Display for Version {
@@ -659,10 +935,43 @@ impl std::fmt::Display for Version {
     }
 }
 
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+struct VersionRepr {
+    major: u8,
+    minor: u8,
+    options: [u8; 2],
+}
+
+#[cfg(feature = "serde")]
+impl From<Version> for VersionRepr {
+    fn from(value: Version) -> Self {
+        Self {
+            major: value.major(),
+            minor: value.minor(),
+            options: value.options,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<VersionRepr> for Version {
+    fn from(value: VersionRepr) -> Self {
+        Self {
+            version: value.major << 4 | value.minor & 0xf,
+            options: value.options,
+        }
+    }
+}
+
 /// Chip ID
+///
+/// With the `serde` feature, it's serialized as both a hex string like
+/// `"0x0410"` and raw bytes, and deserialized from the raw bytes.
 #[binread]
 #[derive(Debug, Clone)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(into = "IdRepr", from = "IdRepr"))]
 #[br(big)]
 pub struct Id {
     #[br(temp)]
@@ -691,9 +1000,13 @@ impl Id {
     }
 
     /// Converts chip ID to a fixed-size array.
+    ///
+    /// A shorter ID is right-aligned, i.e. zero-extended, and a longer one is
+    /// truncated to its least significant `N` bytes.
     pub fn as_array<const N: usize>(&self) -> [u8; N] {
         let mut buf: [u8; N] = [0u8; N];
-        buf[N - self.id.len()..].copy_from_slice(&self.id);
+        let len = self.id.len().min(N);
+        buf[N - len..].copy_from_slice(&self.id[self.id.len() - len..]);
         buf
     }
 
@@ -714,4 +1027,177 @@ impl Id {
     pub fn as_u64(&self) -> u64 {
         u64::from_be_bytes(self.as_array())
     }
+
+    /// Decodes the family of the device from its product ID.
+    ///
+    /// Returns [`None`] if the ID is not a known 16-bit product ID.
+    pub fn family(&self) -> Option<Family> {
+        let pid = <[u8; 2]>::try_from(self.id.as_slice()).ok()?;
+        Family::from_product_id(u16::from_be_bytes(pid))
+    }
+}
+
+/// Formats chip ID as a hex string of all its bytes, like `0x0410`.
+impl std::fmt::Display for Id {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "0x")?;
+        for b in &self.id {
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+struct IdRepr {
+    #[serde(default, skip_deserializing)]
+    id: String,
+    bytes: Vec<u8>,
+}
+
+#[cfg(feature = "serde")]
+impl From<Id> for IdRepr {
+    fn from(value: Id) -> Self {
+        Self {
+            id: value.to_string(),
+            bytes: value.id,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<IdRepr> for Id {
+    fn from(value: IdRepr) -> Self {
+        Self { id: value.bytes }
+    }
+}
+
+#[test]
+fn framing_address_widths() -> Result<(), Box<dyn std::error::Error>> {
+    use binrw::io::NoSeek;
+
+    let tests: &[(u32, AddressWidth, &[u8])] = &[
+        (0x3456, AddressWidth::Bits16, &[0x34, 0x56, 0x62]),
+        (0x12_3456, AddressWidth::Bits24, &[0x12, 0x34, 0x56, 0x70]),
+        (
+            0x0812_3456,
+            AddressWidth::Bits32,
+            &[0x08, 0x12, 0x34, 0x56, 0x78],
+        ),
+    ];
+    for (address, width, frame) in tests.iter().copied() {
+        let mut written = Vec::new();
+        Address::from(address)
+            .with_width(width)?
+            .write(&mut NoSeek::new(&mut written))?;
+        assert_eq!(written, frame);
+    }
+    assert!(
+        Address::from(0x1_0000)
+            .with_width(AddressWidth::Bits16)
+            .is_err()
+    );
+    Ok(())
+}
+
+#[test]
+fn framing_extended_erase() -> binrw::BinResult<()> {
+    use binrw::io::NoSeek;
+
+    let mut written = Vec::new();
+    Command::ExtendedErase(ExtendedErase::Global).write(&mut NoSeek::new(&mut written))?;
+    assert_eq!(written, [0x44, 0xbb, 0xff, 0xff, 0x00]);
+    Ok(())
+}
+
+#[test]
+fn converting_ids() {
+    let id = |bytes: &[u8]| Id { id: bytes.to_vec() };
+
+    let short = id(&[0x10]);
+    assert_eq!(short.as_u16(), 0x10);
+    assert_eq!(short.as_u32(), 0x10);
+    assert_eq!(short.as_u64(), 0x10);
+
+    let pid = id(&[0x04, 0x10]);
+    assert_eq!(pid.as_u16(), 0x0410);
+    assert_eq!(pid.as_u32(), 0x0410);
+    assert_eq!(pid.as_u64(), 0x0410);
+
+    let long = id(&[0x12, 0x34, 0x04, 0x10]);
+    assert_eq!(long.as_u16(), 0x0410);
+    assert_eq!(long.as_u32(), 0x1234_0410);
+    assert_eq!(long.as_u64(), 0x1234_0410);
+
+    assert_eq!(pid.to_string(), "0x0410");
+    assert_eq!(long.to_string(), "0x12340410");
+
+    let longer = id(&[0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef]);
+    assert_eq!(longer.as_u16(), 0xcdef);
+    assert_eq!(longer.as_u32(), 0x89ab_cdef);
+    assert_eq!(longer.as_u64(), 0x0123_4567_89ab_cdef);
+}
+
+#[test]
+fn matching_version_capabilities() {
+    let version = |version| Version {
+        version,
+        options: [0; 2],
+    };
+    let v22 = version(0x22);
+    assert!(!v22.is_usart_v3());
+    assert!(v22.supports(Opcode::READ) && v22.supports(Opcode::ERASE));
+    assert!(!v22.supports(Opcode::EXTENDED_ERASE) && !v22.supports(Opcode::GET_CHECKSUM));
+
+    let v31 = version(0x31);
+    assert!(v31.is_usart_v3());
+    assert!(v31.supports(Opcode::EXTENDED_ERASE) && v31.supports(Opcode::SPECIAL));
+    assert!(!v31.supports(Opcode::ERASE) && !v31.supports(Opcode::GET_CHECKSUM));
+
+    let v33 = version(0x33);
+    assert!(v33.supports(Opcode::GET_CHECKSUM));
+
+    // Unknown versions and opcodes are never assumed to be supported.
+    assert!(!version(0x10).supports(Opcode::GET));
+    assert!(!v33.supports(Opcode(0xff)));
+}
+
+#[test]
+fn writing_empty_data() -> Result<(), Box<dyn std::error::Error>> {
+    use binrw::io::NoSeek;
+
+    let mut data = Data::try_from(&[0xa5; 4][..])?;
+    data.to_mut().clear();
+
+    let mut written = Vec::new();
+    let result = data.write(&mut NoSeek::new(&mut written));
+    let Err(binrw::Error::Custom { err, .. }) = result else {
+        panic!("expected a custom frame error, got {result:?}");
+    };
+    let err = err.downcast::<Error>().expect("a protocol error");
+    assert_eq!(err.exceeded().map(Exceeded::unexpected), Some(0));
+    assert!(written.is_empty());
+    Ok(())
+}
+
+#[test]
+fn converting_data() -> Result<(), Error> {
+    let data = Data::try_from([0xa5u8])?;
+    assert_eq!(data.as_slice(), [0xa5]);
+
+    let data = Data::try_from([0x5au8; 256])?;
+    let mut len = 0;
+    for &b in &data {
+        assert_eq!(b, 0x5a);
+        len += 1;
+    }
+    assert_eq!(len, 256);
+    assert!(Data::try_from([0u8; 257]).is_err_and(|e| e.is_exceeded()));
+
+    let bytes = vec![1, 2, 3];
+    assert_eq!(Data::from_bytes(&bytes[..])?.as_slice(), [1, 2, 3]);
+    assert_eq!(Data::from_bytes(bytes)?.as_slice(), [1, 2, 3]);
+    assert!(Data::from_bytes(Vec::new()).is_err());
+    Ok(())
 }
@@ -0,0 +1,87 @@
+use crate::{Address, Error, Programmer};
+use std::io::{Read, Seek, SeekFrom};
+
+/// Seekable reader of device memory
+///
+/// The reader implements [`Read`] and [`Seek`] on top of
+/// [`Programmer::read_memory()`], so memory of the device can be fed to any
+/// parser of binary formats directly. Positions are relative to the base
+/// address the reader is created with.
+#[derive(Debug)]
+pub struct FlashReader<'a> {
+    programmer: &'a mut Programmer,
+    base: u32,
+    position: u64,
+}
+
+impl<'a> FlashReader<'a> {
+    /// Maximum bytes transferred by a single read.
+    pub const MAX_READ: usize = 256;
+
+    /// Creates a reader of device memory starting at `base`.
+    pub fn new(programmer: &'a mut Programmer, base: impl Into<Address>) -> Self {
+        Self {
+            programmer,
+            base: base.into().as_u32(),
+            position: 0,
+        }
+    }
+
+    /// Gets the base address of the reader.
+    pub fn base(&self) -> u32 {
+        self.base
+    }
+
+    /// Gets the current position relative to the base address.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Consumes the reader and returns the programmer.
+    pub fn into_inner(self) -> &'a mut Programmer {
+        self.programmer
+    }
+
+    /// Bytes left until the end of the 32-bit address space.
+    fn remaining(&self) -> u64 {
+        (u32::MAX as u64 + 1).saturating_sub(self.base as u64 + self.position)
+    }
+}
+
+impl Read for FlashReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = buf.len().min(Self::MAX_READ).min(self.remaining() as usize);
+        if len == 0 {
+            return Ok(0);
+        }
+        let address = self.base as u64 + self.position;
+        self.programmer
+            .read_chunk(address as u32, &mut buf[..len])
+            .map_err(|e| match e {
+                Error::Io(e) => e,
+                e => std::io::Error::other(e),
+            })?;
+        self.position += len as u64;
+        Ok(len)
+    }
+}
+
+impl Seek for FlashReader<'_> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+            SeekFrom::End(..) => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::Unsupported,
+                    "end of device memory is unknown",
+                ));
+            }
+        };
+        self.position = position.ok_or(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "invalid seek to a negative position",
+        ))?;
+        Ok(self.position)
+    }
+}
@@ -0,0 +1,224 @@
+use crate::{Bootloader, Opcode, Probe, Programmer};
+use serialport::{ClearBuffer, DataBits, FlowControl, Parity, SerialPort, StopBits};
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Bytes sent to a port, recorded for inspection
+///
+/// Clones share the same bytes, so a handle stays usable after the port is
+/// handed to a programmer. See [`Programmer::recording()`].
+#[derive(Debug, Clone, Default)]
+pub struct RecordingHandle {
+    bytes: Arc<Mutex<Vec<u8>>>,
+}
+
+impl RecordingHandle {
+    /// Gets bytes recorded so far.
+    pub fn bytes(&self) -> Vec<u8> {
+        self.lock().clone()
+    }
+
+    /// Gets bytes recorded so far and clears them.
+    pub fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.lock())
+    }
+
+    /// Checks whether nothing is recorded.
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    pub(crate) fn record(&self, bytes: &[u8]) {
+        self.lock().extend_from_slice(bytes);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<u8>> {
+        // Bytes recorded stay usable even if a writer panicked.
+        self.bytes.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+const ACK: u8 = 0x79;
+
+/// Port recording bytes sent without any hardware, and acknowledging each
+/// frame
+#[derive(Debug, Clone)]
+struct RecordingPort {
+    recorded: RecordingHandle,
+    baud_rate: u32,
+    timeout: Duration,
+    // An ACK is due, since bytes were sent after the last reply.
+    ack: bool,
+}
+
+impl Programmer {
+    /// Creates a programmer that records every byte it would send, without
+    /// touching any hardware, e.g. to check a sequence of commands against
+    /// expected AN3155 traffic.
+    ///
+    /// Each frame sent is acknowledged, as if by a bootloader of protocol 3.1
+    /// supporting every command but ERASE, while any data expected from the
+    /// device times out. So commands only awaiting ACK, e.g. WRITE,
+    /// EXTENDED_ERASE and GO, succeed, but READ and GET_ID fail.
+    pub fn recording(probe: &Probe) -> (Self, RecordingHandle) {
+        let recorded = RecordingHandle::default();
+        let port = RecordingPort {
+            recorded: recorded.clone(),
+            baud_rate: probe.baudrate(),
+            timeout: probe.timeout(),
+            ack: false,
+        };
+        let mut programmer = Self::attach(Box::new(port), probe);
+        let opcodes = Opcode::KNOWN.into_iter().filter(|&o| o != Opcode::ERASE);
+        programmer.bootloader = Some(Bootloader::new(0x31, opcodes.collect()));
+        (programmer, recorded)
+    }
+}
+
+impl io::Read for RecordingPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.ack || buf.is_empty() {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        self.ack = false;
+        buf[0] = ACK;
+        Ok(1)
+    }
+}
+
+impl io::Write for RecordingPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.recorded.record(buf);
+        self.ack |= !buf.is_empty();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SerialPort for RecordingPort {
+    fn name(&self) -> Option<String> {
+        Some("recording".into())
+    }
+
+    fn baud_rate(&self) -> serialport::Result<u32> {
+        Ok(self.baud_rate)
+    }
+
+    fn data_bits(&self) -> serialport::Result<DataBits> {
+        Ok(DataBits::Eight)
+    }
+
+    fn flow_control(&self) -> serialport::Result<FlowControl> {
+        Ok(FlowControl::None)
+    }
+
+    fn parity(&self) -> serialport::Result<Parity> {
+        Ok(Parity::Even)
+    }
+
+    fn stop_bits(&self) -> serialport::Result<StopBits> {
+        Ok(StopBits::One)
+    }
+
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    fn set_baud_rate(&mut self, baud_rate: u32) -> serialport::Result<()> {
+        self.baud_rate = baud_rate;
+        Ok(())
+    }
+
+    fn set_data_bits(&mut self, _: DataBits) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn set_flow_control(&mut self, _: FlowControl) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn set_parity(&mut self, _: Parity) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn set_stop_bits(&mut self, _: StopBits) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> serialport::Result<()> {
+        self.timeout = timeout;
+        Ok(())
+    }
+
+    fn write_request_to_send(&mut self, _: bool) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn write_data_terminal_ready(&mut self, _: bool) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn read_clear_to_send(&mut self) -> serialport::Result<bool> {
+        Ok(false)
+    }
+
+    fn read_data_set_ready(&mut self) -> serialport::Result<bool> {
+        Ok(false)
+    }
+
+    fn read_ring_indicator(&mut self) -> serialport::Result<bool> {
+        Ok(false)
+    }
+
+    fn read_carrier_detect(&mut self) -> serialport::Result<bool> {
+        Ok(false)
+    }
+
+    fn bytes_to_read(&self) -> serialport::Result<u32> {
+        Ok(self.ack as u32)
+    }
+
+    fn bytes_to_write(&self) -> serialport::Result<u32> {
+        Ok(0)
+    }
+
+    fn clear(&self, _: ClearBuffer) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn try_clone(&self) -> serialport::Result<Box<dyn SerialPort>> {
+        Ok(Box::new(self.clone()))
+    }
+
+    fn set_break(&self) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn clear_break(&self) -> serialport::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn recording_commands() -> crate::Result<()> {
+    let (mut programmer, recorded) = Programmer::recording(&Probe::new());
+    programmer.erase_pages(&[1])?;
+    assert_eq!(recorded.take(), [0x44, 0xbb, 0x00, 0x00, 0x00, 0x01, 0x01]);
+    programmer.write_memory_chunked(0x0800_0000, &[0x12, 0x34, 0x56, 0x78])?;
+    programmer.go(0x0800_0000)?;
+    assert_eq!(
+        recorded.bytes(),
+        [
+            0x31, 0xce, 0x08, 0x00, 0x00, 0x00, 0x08, 0x03, 0x12, 0x34, 0x56, 0x78, 0x0b, 0x21,
+            0xde, 0x08, 0x00, 0x00, 0x00, 0x08
+        ]
+    );
+
+    // Nothing comes from the device but ACKs.
+    assert!(programmer.read_id().is_err());
+    Ok(())
+}
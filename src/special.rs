@@ -0,0 +1,159 @@
+use crate::protocol::Exceeded;
+use crate::{Error, Opcode, Programmer, Result};
+
+impl Programmer {
+    /// Maximum bytes sent along with a SPECIAL command, or in the first
+    /// buffer of an EXTENDED_SPECIAL command.
+    pub const MAX_SPECIAL_TX: usize = 128;
+
+    /// Maximum bytes sent in the second buffer of an EXTENDED_SPECIAL command.
+    pub const MAX_EXTENDED_SPECIAL_TX: usize = 1024;
+
+    /// Sends a vendor-specific operation by SPECIAL, and returns the data the
+    /// device replies with.
+    ///
+    /// The operation is selected by `subcommand`, whose meaning depends on the
+    /// device, see AN2606. Up to [`Self::MAX_SPECIAL_TX`] bytes of `tx` are
+    /// sent along with it.
+    ///
+    /// Fails with [`Error::Unsupported`] if the bootloader doesn't advertise
+    /// SPECIAL, or with [`Error::SafeMode`] in safe mode, since the operation
+    /// may modify the device.
+    pub fn special(&mut self, subcommand: u16, tx: &[u8]) -> Result<Vec<u8>> {
+        Self::check_special_len(tx, Self::MAX_SPECIAL_TX)?;
+        self.check_special(Opcode::SPECIAL)?;
+        self.send_frame(Opcode::SPECIAL)?;
+        self.send_special_buffer(&subcommand.to_be_bytes())?;
+        self.send_special_data(tx)?;
+
+        let mut len = [0u8; 2];
+        self.recv_payload(&mut len)?;
+        let mut rx = vec![0u8; u16::from_be_bytes(len) as usize];
+        self.recv_payload(&mut rx)?;
+        self.recv_ack_within(self.probe.ack_timeout())?;
+        Ok(rx)
+    }
+
+    /// Sends a vendor-specific operation by EXTENDED_SPECIAL, which carries two
+    /// buffers and only replies with ACK.
+    ///
+    /// Up to [`Self::MAX_SPECIAL_TX`] bytes of `tx` and
+    /// [`Self::MAX_EXTENDED_SPECIAL_TX`] bytes of `tx2` are sent. Otherwise it
+    /// fails like [`Self::special()`].
+    pub fn extended_special(&mut self, subcommand: u16, tx: &[u8], tx2: &[u8]) -> Result<()> {
+        Self::check_special_len(tx, Self::MAX_SPECIAL_TX)?;
+        Self::check_special_len(tx2, Self::MAX_EXTENDED_SPECIAL_TX)?;
+        self.check_special(Opcode::EXTENDED_SPECIAL)?;
+        self.send_frame(Opcode::EXTENDED_SPECIAL)?;
+        self.send_special_buffer(&subcommand.to_be_bytes())?;
+        self.send_special_data(tx)?;
+        self.send_special_data(tx2)
+    }
+
+    /// Fails if a buffer is longer than `max` bytes.
+    fn check_special_len(tx: &[u8], max: usize) -> Result<()> {
+        if tx.len() > max {
+            let exceeded = Exceeded::new(tx.len(), 0..=max);
+            return Err(Error::Protocol(exceeded.into()));
+        }
+        Ok(())
+    }
+
+    /// Fails in safe mode, or if the bootloader doesn't advertise `opcode`.
+    fn check_special(&mut self, opcode: Opcode) -> Result<()> {
+        if self.probe.safe_mode() {
+            return Err(Error::SafeMode(opcode));
+        }
+        if !self.bootloader_or_read()?.supports(opcode) {
+            return Err(Error::Unsupported(opcode));
+        }
+        Ok(())
+    }
+
+    /// Sends a buffer preceded by its length, and awaits ACK.
+    fn send_special_data(&mut self, tx: &[u8]) -> Result<()> {
+        let mut buffer = (tx.len() as u16).to_be_bytes().to_vec();
+        buffer.extend_from_slice(tx);
+        self.send_special_buffer(&buffer)
+    }
+
+    /// Sends bytes followed by their XOR checksum, and awaits ACK.
+    fn send_special_buffer(&mut self, bytes: &[u8]) -> Result<()> {
+        let checksum = bytes.iter().fold(0, |a, b| a ^ b);
+        self.send_raw(bytes)?;
+        self.send_raw(&[checksum])?;
+        self.recv_ack_within(self.probe.ack_timeout())
+    }
+}
+
+#[test]
+fn framing_special() -> Result<()> {
+    use crate::Probe;
+    use crate::mock::MockPort;
+
+    let mut replies = vec![0x79, 0x0c, 0x31];
+    replies.extend([
+        0x00, 0x01, 0x02, 0x11, 0x21, 0x31, 0x44, 0x63, 0x73, 0x82, 0x92, 0x50,
+    ]);
+    replies.push(0x79);
+    replies.extend([0x79; 3]);
+    replies.extend([0x00, 0x02, 0xab, 0xcd, 0x79]);
+    // The sub-command is refused.
+    replies.extend([0x79, 0x1f]);
+    let port = MockPort::new(&replies);
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &Probe::new());
+    assert_eq!(programmer.special(0x0102, &[0x11, 0x22])?, [0xab, 0xcd]);
+    assert_eq!(
+        port.written()[2..],
+        [0x50, 0xaf, 0x01, 0x02, 0x03, 0x00, 0x02, 0x11, 0x22, 0x31]
+    );
+
+    // Refused before anything is sent
+    let tx = [0u8; 129];
+    let result = programmer.special(0x0102, &tx);
+    assert!(result.is_err_and(|e| e.is_protocol_conversion()));
+    let result = programmer.special(0x0102, &[]);
+    assert!(result.is_err_and(|e| e.is_nack()));
+    assert_eq!(port.written()[12..], [0x50, 0xaf, 0x01, 0x02, 0x03]);
+    Ok(())
+}
+
+#[test]
+fn framing_extended_special() -> Result<()> {
+    use crate::Probe;
+    use crate::mock::MockPort;
+
+    let mut replies = vec![0x79, 0x0d, 0x31];
+    replies.extend([
+        0x00, 0x01, 0x02, 0x11, 0x21, 0x31, 0x44, 0x63, 0x73, 0x82, 0x92, 0x50, 0x51,
+    ]);
+    replies.push(0x79);
+    replies.extend([0x79; 4]);
+    let port = MockPort::new(&replies);
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &Probe::new());
+    programmer.extended_special(0x0102, &[0x11], &[0x22, 0x33])?;
+    assert_eq!(
+        port.written()[2..],
+        [
+            0x51, 0xae, 0x01, 0x02, 0x03, 0x00, 0x01, 0x11, 0x10, 0x00, 0x02, 0x22, 0x33, 0x13
+        ]
+    );
+
+    // Refused before anything is sent
+    let tx2 = [0u8; 1025];
+    let result = programmer.extended_special(0x0102, &[], &tx2);
+    assert!(result.is_err_and(|e| e.is_protocol_conversion()));
+    assert_eq!(port.written().len(), 16);
+
+    // SPECIAL alone doesn't do.
+    let mut replies = vec![0x79, 0x0c, 0x31];
+    replies.extend([
+        0x00, 0x01, 0x02, 0x11, 0x21, 0x31, 0x44, 0x63, 0x73, 0x82, 0x92, 0x50,
+    ]);
+    replies.push(0x79);
+    let port = MockPort::new(&replies);
+    let mut programmer = Programmer::attach(Box::new(port), &Probe::new());
+    let result = programmer.extended_special(0x0102, &[], &[]);
+    assert!(result.is_err_and(|e| e.is_unsupported()));
+    Ok(())
+}
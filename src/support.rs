@@ -0,0 +1,70 @@
+use crate::{Opcode, Programmer, Result};
+use log::warn;
+use std::ops::RangeInclusive;
+
+/// How well a bootloader is supported by this build
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProtocolSupport {
+    /// The protocol version and all advertised commands are implemented.
+    Full,
+
+    /// The protocol version is implemented, but some advertised commands are
+    /// not, which are listed here. Other commands work as usual.
+    Partial(Vec<Opcode>),
+
+    /// The protocol version is not implemented, so commands may be misframed.
+    Unsupported,
+}
+
+impl ProtocolSupport {
+    pub fn is_full(&self) -> bool {
+        matches!(self, Self::Full)
+    }
+    pub fn is_partial(&self) -> bool {
+        matches!(self, Self::Partial(..))
+    }
+    pub fn is_unsupported(&self) -> bool {
+        matches!(self, Self::Unsupported)
+    }
+}
+
+impl Programmer {
+    /// Protocol versions implemented, as reported by GET.
+    ///
+    /// Minor versions of AN3155 only add commands without changing framing,
+    /// so every minor version of 2.x and 3.x is covered.
+    pub const PROTOCOL_VERSIONS: RangeInclusive<u8> = 0x20..=0x3f;
+
+    /// Checks whether the bootloader protocol is supported, using cached
+    /// bootloader information if any.
+    ///
+    /// A warning is logged unless the protocol is fully supported.
+    pub fn check_protocol_support(&mut self) -> Result<ProtocolSupport> {
+        let bootloader = self.bootloader_or_read()?;
+        let support = if !Self::PROTOCOL_VERSIONS.contains(&bootloader.version()) {
+            warn!(
+                "bootloader protocol {} is not supported",
+                bootloader.version_string(),
+            );
+            ProtocolSupport::Unsupported
+        } else {
+            let unknown = bootloader
+                .opcodes()
+                .iter()
+                .copied()
+                .filter(|opcode| !opcode.is_known())
+                .collect::<Vec<_>>();
+            if unknown.is_empty() {
+                ProtocolSupport::Full
+            } else {
+                warn!(
+                    "bootloader protocol {} is partially supported, unknown commands: {:02x?}",
+                    bootloader.version_string(),
+                    unknown.iter().map(Opcode::as_u8).collect::<Vec<_>>(),
+                );
+                ProtocolSupport::Partial(unknown)
+            }
+        };
+        Ok(support)
+    }
+}
@@ -0,0 +1,46 @@
+use super::{Programmer, Result};
+use log::warn;
+use std::ops::{Deref, DerefMut};
+use std::time::Duration;
+
+/// Guard that overrides the read timeout of a programmer's port, restoring
+/// the previous value once dropped.
+///
+/// The previous timeout is restored even when an operation returns early.
+pub(crate) struct TimeoutGuard<'a> {
+    programmer: &'a mut Programmer,
+    previous: Duration,
+}
+
+impl<'a> TimeoutGuard<'a> {
+    pub(crate) fn new(programmer: &'a mut Programmer, timeout: Duration) -> Result<Self> {
+        let previous = programmer.port.timeout();
+        programmer.port.set_timeout(timeout)?;
+        Ok(Self {
+            programmer,
+            previous,
+        })
+    }
+}
+
+impl Deref for TimeoutGuard<'_> {
+    type Target = Programmer;
+
+    fn deref(&self) -> &Self::Target {
+        self.programmer
+    }
+}
+
+impl DerefMut for TimeoutGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.programmer
+    }
+}
+
+impl Drop for TimeoutGuard<'_> {
+    fn drop(&mut self) {
+        if let Err(e) = self.programmer.port.set_timeout(self.previous) {
+            warn!("cannot restore port timeout: {}", e);
+        }
+    }
+}
@@ -0,0 +1,287 @@
+use crate::crc::{STM32_CRC32_INIT, STM32_CRC32_POLYNOMIAL, stm32_crc32_bytes};
+use crate::{Address, Error, Opcode, Programmer, Reply, Result};
+use log::debug;
+
+/// Method used to verify memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifyMethod {
+    /// CRC computed by the device with GET_CHECKSUM, compared to the CRC of
+    /// the image.
+    Checksum,
+
+    /// Memory read back chunk by chunk and compared to the image, used if
+    /// GET_CHECKSUM is unsupported or the region is not word-aligned.
+    ReadBack,
+}
+
+/// How programmed data is verified
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifyMode {
+    /// Data is not verified.
+    None,
+
+    /// CRC of the data is computed while it's being written, and compared to
+    /// the CRC computed by the device with GET_CHECKSUM once at the end, so
+    /// nothing is read back.
+    ///
+    /// Memory is read back instead if GET_CHECKSUM is unsupported.
+    #[default]
+    Checksum,
+
+    /// Memory is read back chunk by chunk and compared to the data.
+    ReadBack,
+}
+
+/// Report of [`Programmer::verify_image()`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyReport {
+    method: VerifyMethod,
+    matched: bool,
+    checksums: Option<(u32, u32)>,
+}
+
+impl VerifyReport {
+    /// Gets method used to verify memory.
+    pub fn method(&self) -> VerifyMethod {
+        self.method
+    }
+
+    /// Whether memory matches the image.
+    pub fn matched(&self) -> bool {
+        self.matched
+    }
+
+    /// Gets CRCs of the image and of device memory, in that order, if
+    /// verified by [`VerifyMethod::Checksum`].
+    ///
+    /// Only whole words are covered, while a trailing partial word is read
+    /// back.
+    pub fn checksums(&self) -> Option<(u32, u32)> {
+        self.checksums
+    }
+}
+
+impl Programmer {
+    /// Verifies that memory at `address` matches `data`.
+    ///
+    /// GET_CHECKSUM is preferred, so only a CRC goes over the wire. Bootloaders
+    /// without it, e.g. on older F1 parts, are verified by reading memory back
+    /// instead, which is slower. The method used is in the report.
+    ///
+    /// Each READ is retried like any command, see
+    /// [`Probe::command_retries()`](crate::Probe::command_retries).
+    pub fn verify_image(&mut self, address: u32, data: &[u8]) -> Result<VerifyReport> {
+        self.verify_image_with_crc(address, data, None, &|| Ok(()))
+    }
+
+    /// Verifies memory like [`Self::verify_image()`], given the CRC of the
+    /// whole words of `data` if already computed, e.g. while writing.
+    ///
+    /// `check` is called before the checksum and before each chunk read back,
+    /// and any error it returns, e.g. [`Error::Aborted`], stops verifying.
+    pub(crate) fn verify_image_with_crc(
+        &mut self,
+        address: u32,
+        data: &[u8],
+        crc: Option<u32>,
+        check: &dyn Fn() -> Result<()>,
+    ) -> Result<VerifyReport> {
+        Self::check_range(address, data.len())?;
+        let bootloader = self.bootloader_or_read()?;
+        if !bootloader.supports(Opcode::GET_CHECKSUM) {
+            debug!("GET_CHECKSUM unsupported, verifying by reading back");
+        } else if !address.is_multiple_of(4) {
+            debug!(
+                "{:#010x} not word-aligned, verifying by reading back",
+                address
+            );
+        } else {
+            // A trailing partial word is read back, since GET_CHECKSUM only
+            // works on whole words.
+            let (words, tail) = data.split_at(data.len() & !3);
+            let expected = crc.unwrap_or_else(|| stm32_crc32_bytes(words));
+            let actual = if words.is_empty() {
+                expected
+            } else {
+                check()?;
+                self.get_checksum(address, words.len() as u32)?
+            };
+            let matched = actual == expected
+                && self.read_back_matches(address + words.len() as u32, tail, check)?;
+            if !matched {
+                debug!(
+                    "checksum mismatch: {:#010x} expected, {:#010x} actual",
+                    expected, actual
+                );
+            }
+            return Ok(VerifyReport {
+                method: VerifyMethod::Checksum,
+                matched,
+                checksums: Some((expected, actual)),
+            });
+        }
+        self.verify_read_back(address, data, check)
+    }
+
+    /// Verifies memory by reading it back, regardless of GET_CHECKSUM, calling
+    /// `check` before each chunk like [`Self::verify_image_with_crc()`].
+    pub(crate) fn verify_read_back(
+        &mut self,
+        address: u32,
+        data: &[u8],
+        check: &dyn Fn() -> Result<()>,
+    ) -> Result<VerifyReport> {
+        Ok(VerifyReport {
+            method: VerifyMethod::ReadBack,
+            matched: self.read_back_matches(address, data, check)?,
+            checksums: None,
+        })
+    }
+
+    /// Reads memory back in chunks and compares it to `data`, stopping at the
+    /// first chunk that differs, or at the first error of `check`.
+    fn read_back_matches(
+        &mut self,
+        address: u32,
+        data: &[u8],
+        check: &dyn Fn() -> Result<()>,
+    ) -> Result<bool> {
+        let mut buf = [0u8; 256];
+        for (i, expected) in data.chunks(256).enumerate() {
+            check()?;
+            let chunk = &mut buf[..expected.len()];
+            self.read_chunk(address + (i * 256) as u32, chunk)?;
+            if chunk != expected {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Computes CRC-32 of a region on the device with GET_CHECKSUM, which is
+    /// far faster than reading the region back.
+    ///
+    /// The CRC is computed like the CRC unit of STM32 by default: 32-bit words
+    /// in little endian, polynomial `0x04c11db7`, initial value `0xffffffff`,
+    /// no reflection and no final XOR. Both `address` and `size` must be
+    /// multiples of 4, otherwise it fails with [`Error::Alignment`].
+    ///
+    /// Fails with [`Error::Unsupported`] if the bootloader lacks GET_CHECKSUM.
+    pub fn get_checksum(&mut self, address: u32, size: u32) -> Result<u32> {
+        if !address.is_multiple_of(4) {
+            return Err(Error::Alignment { address });
+        }
+        if !size.is_multiple_of(4) {
+            return Err(Error::Alignment {
+                address: address.wrapping_add(size),
+            });
+        }
+        if !self.bootloader_or_read()?.supports(Opcode::GET_CHECKSUM) {
+            return Err(Error::Unsupported(Opcode::GET_CHECKSUM));
+        }
+        self.send_frame(Opcode::GET_CHECKSUM)?;
+        self.send_frame(Address::from(address))?;
+        // The size and CRC parameters are framed like addresses: 4 bytes, then
+        // XOR checksum.
+        self.send_reliable(Address::from(size))?;
+        self.send_reliable(Address::from(STM32_CRC32_POLYNOMIAL))?;
+        self.send_reliable(Address::from(STM32_CRC32_INIT))?;
+        if let Reply::NAck = self.recv::<Reply>()? {
+            return Err(Error::NAck);
+        }
+        let mut reply = [0u8; 5];
+        self.recv_payload(&mut reply)?;
+        let (crc, checksum) = reply.split_at(4);
+        if crc.iter().fold(0, |a, b| a ^ b) != checksum[0] {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "checksum mismatch in GET_CHECKSUM reply",
+            )
+            .into());
+        }
+        Ok(u32::from_be_bytes(crc.try_into().unwrap()))
+    }
+}
+
+#[test]
+fn verifying_by_crc32() -> Result<()> {
+    use crate::Probe;
+    use crate::mock::{Faults, MockPort};
+
+    let data: Vec<u8> = (0..300).map(|i| i as u8).collect();
+    let faults = Faults {
+        corrupt_at: Some(0x0800_0400 + 299),
+        ..Default::default()
+    };
+    let port = MockPort::device(faults);
+    let mut programmer = Programmer::attach(Box::new(port), &Probe::new());
+    programmer.write_memory_chunked(0x0800_0000, &data)?;
+    programmer.write_memory_chunked(0x0800_0400, &data)?;
+    // GET_CHECKSUM is unsupported by F1, so memory is read back.
+    assert!(programmer.read_memory_crc32_verify(0x0800_0000, &data)?);
+    assert!(!programmer.read_memory_crc32_verify(0x0800_0400, &data)?);
+    Ok(())
+}
+
+#[test]
+fn aborting_read_back() -> Result<()> {
+    use crate::Probe;
+    use crate::mock::{Faults, MockPort};
+    use std::cell::Cell;
+
+    let data = [0xa5; 600];
+    let port = MockPort::device(Faults::default());
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &Probe::new());
+    programmer.write_memory_chunked(0x0800_0000, &data)?;
+    let written = port.written().len();
+
+    // Aborted once the first chunk is read back
+    let chunks = Cell::new(0);
+    let check = || {
+        chunks.set(chunks.get() + 1);
+        match chunks.get() {
+            1 => Ok(()),
+            _ => Err(Error::Aborted),
+        }
+    };
+    let result = programmer.verify_read_back(0x0800_0000, &data, &check);
+    assert!(result.is_err_and(|e| e.is_aborted()));
+    let reads = port.written()[written..]
+        .windows(2)
+        .filter(|w| *w == [0x11, 0xee])
+        .count();
+    assert_eq!(reads, 1);
+    Ok(())
+}
+
+#[test]
+fn framing_get_checksum() -> Result<()> {
+    use crate::Probe;
+    use crate::mock::MockPort;
+
+    let mut replies = vec![0x79, 0x0c, 0x33];
+    replies.extend([
+        0x00, 0x01, 0x02, 0x11, 0x21, 0x31, 0x44, 0x63, 0x73, 0x82, 0x92, 0xa1,
+    ]);
+    replies.push(0x79);
+    replies.extend([0x79; 6]);
+    replies.extend([0x12, 0x34, 0x56, 0x78, 0x08]);
+    let port = MockPort::new(&replies);
+    let mut programmer = Programmer::attach(Box::new(port.clone()), &Probe::new());
+    assert_eq!(programmer.get_checksum(0x0800_0000, 0x400)?, 0x1234_5678);
+    assert_eq!(
+        port.written()[2..],
+        [
+            0xa1, 0x5e, 0x08, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x04, 0x00, 0x04, 0x04, 0xc1,
+            0x1d, 0xb7, 0x6f, 0xff, 0xff, 0xff, 0xff, 0x00,
+        ]
+    );
+
+    // Refused before anything is sent
+    assert!(
+        programmer
+            .get_checksum(0x0800_0002, 4)
+            .is_err_and(|e| e.is_alignment())
+    );
+    Ok(())
+}
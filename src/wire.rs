@@ -0,0 +1,75 @@
+use log::{Level, log_enabled, trace};
+use std::io::Read;
+
+/// Log target of raw bytes sent and received, e.g. `TX: 7f` and `RX: 79`.
+///
+/// They're logged at trace level, so the target must be enabled explicitly,
+/// e.g. by `RUST_LOG=yapu::wire=trace` with `env_logger`.
+pub const WIRE_TARGET: &str = "yapu::wire";
+
+/// Checks whether raw bytes are logged, so that they're only collected then.
+pub(crate) fn enabled() -> bool {
+    log_enabled!(target: WIRE_TARGET, Level::Trace)
+}
+
+/// Logs bytes sent.
+pub(crate) fn tx(bytes: &[u8]) {
+    if !bytes.is_empty() {
+        trace!(target: WIRE_TARGET, "TX: {}", hex(bytes));
+    }
+}
+
+/// Logs bytes received.
+pub(crate) fn rx(bytes: &[u8]) {
+    if !bytes.is_empty() {
+        trace!(target: WIRE_TARGET, "RX: {}", hex(bytes));
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Reader collecting bytes read through it, to be logged at once.
+pub(crate) struct Tap<R> {
+    inner: R,
+    seen: Option<Vec<u8>>,
+}
+
+impl<R: Read> Tap<R> {
+    pub(crate) fn new(inner: R) -> Self {
+        Self {
+            inner,
+            seen: enabled().then(Vec::new),
+        }
+    }
+}
+
+impl<R> Drop for Tap<R> {
+    fn drop(&mut self) {
+        // Bytes are logged even if reading fails midway.
+        if let Some(seen) = &self.seen {
+            rx(seen);
+        }
+    }
+}
+
+impl<R: Read> Read for Tap<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if let Some(seen) = self.seen.as_mut() {
+            seen.extend_from_slice(&buf[..n]);
+        }
+        Ok(n)
+    }
+}
+
+#[test]
+fn formatting_hex() {
+    assert_eq!(hex(&[0x7f]), "7f");
+    assert_eq!(hex(&[0x00, 0xff, 0x79]), "00 ff 79");
+}